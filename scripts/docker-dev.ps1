@@ -62,8 +62,8 @@ function Start-Services {
     
     if ($LASTEXITCODE -eq 0) {
         Write-Host "✅ Services started successfully!" -ForegroundColor Green
-        Write-Host "🔗 API available at: http://localhost:3000" -ForegroundColor Cyan
-        Write-Host "📊 Health check: http://localhost:3000/health" -ForegroundColor Cyan
+        Write-Host "🔗 API available at: http://localhost:8080/graphql" -ForegroundColor Cyan
+        Write-Host "📊 Health check: http://localhost:8080/readyz" -ForegroundColor Cyan
         
         if ($Logs) {
             Show-Logs
@@ -107,7 +107,7 @@ function Check-Health {
     Write-Host "🏥 Checking service health..." -ForegroundColor Yellow
     
     $response = try {
-        Invoke-RestMethod -Uri "http://localhost:3000/health" -TimeoutSec 10
+        Invoke-RestMethod -Uri "http://localhost:8080/readyz" -TimeoutSec 10
     } catch {
         Write-Host "❌ Health check failed: $($_.Exception.Message)" -ForegroundColor Red
         return
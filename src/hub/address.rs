@@ -0,0 +1,166 @@
+use secp256k1::PublicKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Keccak256};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::hub::signature_keys::{decode_exact, strip_hex_prefix, SignatureError};
+
+/// A 20-byte Ethereum address.
+///
+/// Displayed with its EIP-55 checksum; `to_lower_hex` gives the lowercase form
+/// the node and `SignatureKeys::address_key` use. Two spellings of the same
+/// address always compare equal.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address([u8; 20]);
+
+impl Address {
+    pub fn from_bytes(bytes: [u8; 20]) -> Self {
+        Address(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    /// The last 20 bytes of the Keccak-256 of the uncompressed key.
+    pub fn from_public_key(public_key: &PublicKey) -> Self {
+        let hash = Keccak256::digest(&public_key.serialize_uncompressed()[1..]);
+        let mut bytes = [0u8; 20];
+        bytes.copy_from_slice(&hash[12..]);
+        Address(bytes)
+    }
+
+    /// Address of a hex public key, compressed (33 bytes) or uncompressed (65).
+    pub fn from_public_key_hex(public_key: &str) -> Result<Self, SignatureError> {
+        let bytes = hex::decode(strip_hex_prefix(public_key))
+            .map_err(|_| SignatureError::InvalidHex("Public key"))?;
+        let public_key =
+            PublicKey::from_slice(&bytes).map_err(|_| SignatureError::InvalidPublicKey)?;
+        Ok(Self::from_public_key(&public_key))
+    }
+
+    /// Parses either an address or a public key, which clients use
+    /// interchangeably to identify themselves.
+    pub fn from_address_or_public_key(text: &str) -> Result<Self, SignatureError> {
+        if strip_hex_prefix(text).len() == 40 {
+            text.parse()
+        } else {
+            Self::from_public_key_hex(text)
+        }
+    }
+
+    /// `0x` and 40 lowercase hex digits.
+    pub fn to_lower_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.0))
+    }
+
+    /// `0x` and 40 hex digits, letters uppercased where the Keccak-256 of the
+    /// lowercase digits has a nibble of 8 or more (EIP-55).
+    pub fn to_checksum(&self) -> String {
+        let lower = hex::encode(self.0);
+        let hash = Keccak256::digest(lower.as_bytes());
+        let digits: String = lower
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+                if nibble >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+        format!("0x{}", digits)
+    }
+}
+
+/// Accepts 40 hex digits with or without `0x`. All-lowercase and all-uppercase
+/// digits are taken as is; mixed case must be a valid EIP-55 checksum.
+impl FromStr for Address {
+    type Err = SignatureError;
+
+    fn from_str(text: &str) -> Result<Self, SignatureError> {
+        let address = Address(decode_exact("Address", text)?);
+        let digits = strip_hex_prefix(text);
+        let mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+            && digits.chars().any(|c| c.is_ascii_uppercase());
+        if mixed_case && address.to_checksum()[2..] != *digits {
+            return Err(SignatureError::InvalidChecksum(text.to_string()));
+        }
+        Ok(address)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksum())
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address({})", self.to_checksum())
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_checksum())
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hub::signature_keys::SignatureKeys;
+
+    #[test]
+    fn test_checksum_matches_eip55() {
+        for checksummed in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let address: Address = checksummed.parse().unwrap();
+            assert_eq!(address.to_string(), checksummed);
+            assert_eq!(address.to_lower_hex(), checksummed.to_ascii_lowercase());
+            assert_eq!(checksummed.to_ascii_lowercase().parse::<Address>().unwrap(), address);
+            assert_eq!(checksummed[2..].to_ascii_uppercase().parse::<Address>().unwrap(), address);
+        }
+        assert_eq!(
+            "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed".parse::<Address>(),
+            Err(SignatureError::InvalidChecksum(
+                "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string()
+            ))
+        );
+        assert!("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn test_public_keys_of_both_forms_give_the_same_address() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let public_key = PublicKey::from_slice(&hex::decode(&keys.public_key).unwrap()).unwrap();
+        let compressed = hex::encode(public_key.serialize());
+
+        let address = Address::from_public_key_hex(&keys.public_key).unwrap();
+        assert_eq!(address.to_lower_hex(), keys.address_key);
+        assert_eq!(Address::from_address_or_public_key(&compressed).unwrap(), address);
+        assert_eq!(
+            Address::from_address_or_public_key(&format!("0x{}", keys.public_key)).unwrap(),
+            address
+        );
+        assert_eq!(Address::from_address_or_public_key(&address.to_string()).unwrap(), address);
+        assert!(Address::from_address_or_public_key("04abcd").is_err());
+    }
+}
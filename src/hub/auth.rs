@@ -1,22 +1,34 @@
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::Sha256;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::hub::address::Address;
 use crate::hub::signature_keys::SignatureKeys;
+use crate::hub::typed_data::TypedData;
+
+/// How long, in seconds, a sign-in challenge can be answered.
+pub const SIGN_IN_CHALLENGE_TTL_SECS: u64 = 300;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    pub pk: String, // public key
+    // Tokens issued before the claim held an address may carry a public key
+    #[serde(deserialize_with = "address_or_public_key")]
+    pub pk: Address, // address of the authenticated user
     pub exp: usize, // expiration time
 }
 
+fn address_or_public_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+    Address::from_address_or_public_key(&String::deserialize(deserializer)?)
+        .map_err(serde::de::Error::custom)
+}
+
 pub fn generate_jwt_token(
-    public_key: &str,
+    address: &Address,
     expiration_hours: u64,
     jwt_secret: &str,
 ) -> Result<(String, usize), String> {
-    SignatureKeys::validate_public_key(public_key)?;
-
     let expiration = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -24,7 +36,7 @@ pub fn generate_jwt_token(
         + (expiration_hours * 3600) as usize;
 
     let claims = Claims {
-        pk: public_key.to_string(),
+        pk: *address,
         exp: expiration,
     };
 
@@ -37,3 +49,110 @@ pub fn generate_jwt_token(
     
     Ok((token, expiration))
 }
+
+/// A sign-in challenge for `address` to sign with `eth_signTypedData_v4`.
+/// Its nonce is a MAC of the address and the issue time under the JWT
+/// secret, so any instance can check it later without keeping state.
+pub fn sign_in_challenge(address: &Address, jwt_secret: &str) -> TypedData {
+    challenge_issued_at(address, now_secs(), jwt_secret)
+}
+
+fn challenge_issued_at(address: &Address, issued_at: u64, jwt_secret: &str) -> TypedData {
+    let nonce = hex::encode(challenge_mac(address, issued_at, jwt_secret).finalize().into_bytes());
+    TypedData::sign_in(&address.to_checksum(), &nonce, issued_at)
+}
+
+/// The address that signed a challenge from [`sign_in_challenge`], given the
+/// 65-byte hex signature a wallet returns. The typed data is rebuilt from the
+/// challenge's message, so a signature over another domain or statement is
+/// rejected. A signed challenge stays valid until it expires.
+pub fn verify_sign_in(
+    challenge: &TypedData,
+    signature: &str,
+    jwt_secret: &str,
+) -> Result<Address, String> {
+    let field = |name: &str| {
+        challenge
+            .message
+            .get(name)
+            .ok_or_else(|| format!("Sign-in challenge has no {} field", name))
+    };
+    let text = |name: &str| {
+        field(name)?
+            .as_str()
+            .ok_or_else(|| format!("Sign-in challenge {} must be a string", name))
+    };
+    let address: Address = text("address")?.parse()?;
+    let nonce = text("nonce")?;
+    let issued_at = field("issuedAt")?
+        .as_u64()
+        .ok_or_else(|| "Sign-in challenge issuedAt must be a number".to_string())?;
+
+    let nonce = hex::decode(nonce).map_err(|_| "Sign-in challenge nonce is not valid hex")?;
+    challenge_mac(&address, issued_at, jwt_secret)
+        .verify_slice(&nonce)
+        .map_err(|_| "Sign-in challenge was not issued by this hub".to_string())?;
+    // Instances may disagree on the time by a little, so allow either side
+    if now_secs().abs_diff(issued_at) > SIGN_IN_CHALLENGE_TTL_SECS {
+        return Err("Sign-in challenge has expired".to_string());
+    }
+
+    let typed_data = TypedData::sign_in(text("address")?, text("nonce")?, issued_at);
+    let (r, s, v) = SignatureKeys::split_signature(signature)?;
+    if !SignatureKeys::verify_typed_data(&address.to_lower_hex(), &typed_data, &r, &s, v)? {
+        return Err(format!("Sign-in challenge was not signed by {}", address));
+    }
+    Ok(address)
+}
+
+fn challenge_mac(address: &Address, issued_at: u64, jwt_secret: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(jwt_secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(b"sign-in:");
+    mac.update(address.as_bytes());
+    mac.update(&issued_at.to_be_bytes());
+    mac
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test secret";
+
+    fn sign(keys: &SignatureKeys, challenge: &TypedData) -> String {
+        let (r, s, v) = SignatureKeys::sign_typed_data(&keys.secret_key, challenge).unwrap();
+        format!("{}{}{:02x}", r, s, v)
+    }
+
+    #[test]
+    fn test_challenges_expire_and_are_bound_to_the_secret() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let address: Address = keys.address_key.parse().unwrap();
+
+        let challenge = sign_in_challenge(&address, SECRET);
+        assert_eq!(verify_sign_in(&challenge, &sign(&keys, &challenge), SECRET), Ok(address));
+        assert_eq!(
+            verify_sign_in(&challenge, &sign(&keys, &challenge), "another secret").unwrap_err(),
+            "Sign-in challenge was not issued by this hub"
+        );
+
+        for issued_at in [
+            now_secs() - SIGN_IN_CHALLENGE_TTL_SECS - 10,
+            now_secs() + SIGN_IN_CHALLENGE_TTL_SECS + 10,
+        ] {
+            let stale = challenge_issued_at(&address, issued_at, SECRET);
+            assert_eq!(
+                verify_sign_in(&stale, &sign(&keys, &stale), SECRET).unwrap_err(),
+                "Sign-in challenge has expired"
+            );
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use clap::{Args, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::Path;
+
+use crate::hub::address::Address;
+use crate::hub::auth::generate_jwt_token;
+use crate::hub::configuration::AppConfig;
+use crate::hub::hd_keys::{derive_keys, generate_mnemonic, DerivationPath, HARDENED};
+use crate::hub::keystore::{Kdf, Keystore};
+use crate::hub::redact::Secret;
+use crate::hub::secrets::{EnvSecretProvider, FileSecretProvider, SecretProvider};
+use crate::hub::signature_keys::{SignatureKeys, SignatureScheme};
+use crate::hub::typed_data::TypedData;
+
+// Offline tools for keys, signatures and tokens; none of them start the hub.
+// A doc comment here would replace the binary's own description in --help.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate a new secp256k1 key pair and its address
+    Keygen,
+    /// Derive the address of a secret or public key
+    Address(AddressArgs),
+    /// Sign a message, or an unsigned transaction from createUnsignedRideRequest
+    Sign(SignArgs),
+    /// Verify a message signature or a signed transaction
+    Verify(VerifyArgs),
+    /// Mint a JWT with the secret of the selected configuration
+    Token(TokenArgs),
+    /// Encrypt a new or existing secret key into a JSON keystore file
+    Keystore(KeystoreArgs),
+    /// Generate a BIP-39 mnemonic, or derive accounts from an existing one
+    Mnemonic(MnemonicArgs),
+}
+
+/// Variable read for the keystore password when --password-file is not given.
+pub const KEYSTORE_PASSWORD_ENV: &str = "CLUTCH_KEYSTORE_PASSWORD";
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct SecretKeyArgs {
+    /// Hex secret key (visible in shell history; prefer --secret-key-file)
+    #[arg(long)]
+    secret_key: Option<String>,
+    /// File holding the hex secret key
+    #[arg(long)]
+    secret_key_file: Option<String>,
+    /// Encrypted JSON keystore, e.g. from geth or a wallet export
+    #[arg(long)]
+    keystore: Option<String>,
+}
+
+impl SecretKeyArgs {
+    fn load(&self, password: &PasswordArgs) -> Result<SignatureKeys, String> {
+        if let Some(keys) = read_secret_key(&self.secret_key, &self.secret_key_file)? {
+            return Ok(keys);
+        }
+        match &self.keystore {
+            Some(path) => Keystore::read(Path::new(path))?.decrypt(password.load()?.expose()),
+            None => Err("A secret key is required".to_string()),
+        }
+    }
+}
+
+/// Reads a hex secret key given inline or in a file; `None` if neither is set.
+fn read_secret_key(
+    inline: &Option<String>,
+    file: &Option<String>,
+) -> Result<Option<SignatureKeys>, String> {
+    let secret_key = match (inline, file) {
+        (Some(secret_key), _) => Secret::new(secret_key.trim()),
+        (None, Some(path)) => FileSecretProvider.fetch(path)?,
+        (None, None) => return Ok(None),
+    };
+    SignatureKeys::from_secret_key(secret_key.expose()).map(Some).map_err(String::from)
+}
+
+#[derive(Args, Debug)]
+pub struct PasswordArgs {
+    /// File holding the keystore password [default: $CLUTCH_KEYSTORE_PASSWORD]
+    #[arg(long)]
+    password_file: Option<String>,
+}
+
+impl PasswordArgs {
+    fn load(&self) -> Result<Secret, String> {
+        match &self.password_file {
+            Some(path) => FileSecretProvider.fetch(path),
+            None => EnvSecretProvider
+                .fetch(KEYSTORE_PASSWORD_ENV)
+                .map_err(|e| format!("Keystore password: {}, or pass --password-file", e)),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct AddressArgs {
+    /// Hex secret key
+    #[arg(long)]
+    secret_key: Option<String>,
+    /// File holding the hex secret key
+    #[arg(long)]
+    secret_key_file: Option<String>,
+    /// Encrypted JSON keystore
+    #[arg(long)]
+    keystore: Option<String>,
+    /// Hex public key, compressed or uncompressed
+    #[arg(long)]
+    public_key: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SignArgs {
+    #[command(flatten)]
+    key: SecretKeyArgs,
+    #[command(flatten)]
+    password: PasswordArgs,
+    #[command(flatten)]
+    input: SignInput,
+    /// Sign --message with the EIP-191 prefix, like a wallet's personal_sign
+    #[arg(long, requires = "message")]
+    personal: bool,
+    /// Sign --transaction as EIP-712 typed data, like a browser wallet would
+    #[arg(long, requires = "transaction")]
+    eip712: bool,
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct SignInput {
+    /// Text to sign
+    #[arg(long)]
+    message: Option<String>,
+    /// Unsigned transaction JSON file, or - for stdin
+    #[arg(long)]
+    transaction: Option<String>,
+    /// EIP-712 typed data JSON file (eth_signTypedData_v4 format), or - for stdin
+    #[arg(long)]
+    typed_data: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Signed transaction JSON file, or - for stdin
+    #[arg(long, conflicts_with_all = ["message", "typed_data", "address", "signature", "r"])]
+    transaction: Option<String>,
+    /// Signed text
+    #[arg(long, required_unless_present_any = ["transaction", "typed_data"])]
+    message: Option<String>,
+    /// --message was signed with the EIP-191 prefix (personal_sign)
+    #[arg(long, requires = "message")]
+    personal: bool,
+    /// Signed EIP-712 typed data JSON file, or - for stdin
+    #[arg(long, conflicts_with = "message")]
+    typed_data: Option<String>,
+    /// Address expected to have signed
+    #[arg(long, required_unless_present = "transaction")]
+    address: Option<String>,
+    /// 65-byte hex signature as wallets return it, instead of --r, --s and --v
+    #[arg(long, conflicts_with = "r", required_unless_present_any = ["transaction", "r"])]
+    signature: Option<String>,
+    #[arg(long, requires_all = ["s", "v"])]
+    r: Option<String>,
+    #[arg(long)]
+    s: Option<String>,
+    #[arg(long)]
+    v: Option<i32>,
+}
+
+#[derive(Args, Debug)]
+pub struct TokenArgs {
+    /// Address or public key the token is issued to
+    #[arg(long)]
+    public_key: String,
+    /// Lifetime in hours instead of auth.jwt_expiration_hours
+    #[arg(long)]
+    hours: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct KeystoreArgs {
+    /// Keystore file to create; an existing file is never overwritten
+    #[arg(long)]
+    out: String,
+    /// Hex secret key to encrypt instead of generating a new one
+    #[arg(long, conflicts_with = "secret_key_file")]
+    secret_key: Option<String>,
+    /// File holding the hex secret key to encrypt
+    #[arg(long)]
+    secret_key_file: Option<String>,
+    /// Key derivation function
+    #[arg(long, value_enum, default_value_t = KdfChoice::Scrypt)]
+    kdf: KdfChoice,
+    #[command(flatten)]
+    password: PasswordArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct MnemonicArgs {
+    /// Number of words in a new mnemonic
+    #[arg(long, default_value_t = 12, conflicts_with = "mnemonic_file")]
+    words: usize,
+    /// File holding the mnemonic to derive accounts from
+    #[arg(long)]
+    mnemonic_file: Option<String>,
+    /// File holding the BIP-39 passphrase, if the mnemonic has one
+    #[arg(long)]
+    passphrase_file: Option<String>,
+    /// Number of accounts to derive along m/44'/60'/0'/0/<index>
+    #[arg(long, default_value_t = 1)]
+    count: u32,
+    /// Index of the first account
+    #[arg(long, default_value_t = 0)]
+    index: u32,
+    /// Derive the single key at this path instead, e.g. m/44'/60'/1'/0/0
+    #[arg(long, conflicts_with_all = ["count", "index"])]
+    path: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum KdfChoice {
+    /// scrypt with the parameters geth uses by default
+    Scrypt,
+    /// scrypt with geth's --lightkdf parameters
+    ScryptLight,
+    /// PBKDF2-HMAC-SHA256
+    Pbkdf2,
+}
+
+impl From<KdfChoice> for Kdf {
+    fn from(choice: KdfChoice) -> Self {
+        match choice {
+            KdfChoice::Scrypt => Kdf::SCRYPT,
+            KdfChoice::ScryptLight => Kdf::SCRYPT_LIGHT,
+            KdfChoice::Pbkdf2 => Kdf::PBKDF2,
+        }
+    }
+}
+
+/// Runs `command` with the configuration of `env` and returns what to print.
+pub fn run(command: Command, env: &str) -> Result<String, String> {
+    match command {
+        Command::Keygen => {
+            let keys = SignatureKeys::generate_new_keypair();
+            Ok(pretty(&json!({
+                "secret_key": keys.secret_key,
+                "public_key": keys.public_key,
+                "address": keys.address_key,
+            })))
+        }
+        Command::Address(args) => {
+            if let Some(public_key) = &args.public_key {
+                return SignatureKeys::address_from_public_key(public_key).map_err(String::from);
+            }
+            if let Some(path) = &args.keystore {
+                // The address is stored in the clear, so no password is needed
+                let keystore = Keystore::read(Path::new(path))?;
+                return keystore
+                    .address
+                    .map(|address| format!("0x{}", address.trim_start_matches("0x")))
+                    .ok_or_else(|| format!("{} does not record its address", path));
+            }
+            read_secret_key(&args.secret_key, &args.secret_key_file)?
+                .map(|keys| keys.address_key)
+                .ok_or_else(|| "A secret key is required".to_string())
+        }
+        Command::Sign(args) => {
+            let keys = args.key.load(&args.password)?;
+            let (r, s, v) = if let Some(message) = &args.input.message {
+                if args.personal {
+                    SignatureKeys::sign_personal_message(&keys.secret_key, message.as_bytes())?
+                } else {
+                    SignatureKeys::sign(&keys.secret_key, message.as_bytes())?
+                }
+            } else if let Some(path) = &args.input.typed_data {
+                SignatureKeys::sign_typed_data(&keys.secret_key, &read_typed_data(path)?)?
+            } else {
+                let unsigned = read_json(args.input.transaction.as_deref().unwrap_or("-"))?;
+                let scheme = if args.eip712 {
+                    SignatureScheme::Eip712
+                } else {
+                    SignatureScheme::Keccak
+                };
+                let signed = SignatureKeys::sign_transaction(&keys.secret_key, &unsigned, scheme)?;
+                return Ok(pretty(&json!({
+                    "raw_transaction": SignatureKeys::encode_raw_transaction(&signed),
+                    "signed_transaction": signed,
+                })));
+            };
+            Ok(pretty(&json!({
+                "address": keys.address_key,
+                "r": r,
+                "s": s,
+                "v": v,
+                "signature": format!("0x{}{}{:02x}", r, s, v),
+            })))
+        }
+        Command::Verify(args) => {
+            let valid = match &args.transaction {
+                Some(path) => SignatureKeys::verify_transaction(&read_json(path)?)?,
+                None => {
+                    let address = args.address.unwrap_or_default();
+                    let (r, s, v) = match &args.signature {
+                        Some(signature) => SignatureKeys::split_signature(signature)?,
+                        None => (
+                            args.r.unwrap_or_default(),
+                            args.s.unwrap_or_default(),
+                            args.v.unwrap_or_default(),
+                        ),
+                    };
+                    let message = args.message.unwrap_or_default();
+                    if let Some(path) = &args.typed_data {
+                        let typed_data = read_typed_data(path)?;
+                        SignatureKeys::verify_typed_data(&address, &typed_data, &r, &s, v)?
+                    } else if args.personal {
+                        SignatureKeys::verify_personal_message(
+                            &address,
+                            message.as_bytes(),
+                            &r,
+                            &s,
+                            v,
+                        )?
+                    } else {
+                        let address = address.to_ascii_lowercase();
+                        SignatureKeys::verify(&address, message.as_bytes(), &r, &s, v)?
+                    }
+                }
+            };
+            if valid {
+                Ok("Signature is valid".to_string())
+            } else {
+                Err("Signature is not valid".to_string())
+            }
+        }
+        Command::Token(args) => {
+            let config = AppConfig::load_configuration(env).map_err(|e| e.to_string())?;
+            if config.auth.jwt_secret.is_empty() {
+                return Err(format!("auth.jwt_secret is not set for env {:?}", env));
+            }
+            let hours = args.hours.unwrap_or(config.auth.jwt_expiration_hours);
+            let address = Address::from_address_or_public_key(&args.public_key)?;
+            let (token, expires_at) =
+                generate_jwt_token(&address, hours, config.auth.jwt_secret.expose())?;
+            Ok(pretty(&json!({
+                "token": token,
+                "expires_at": expires_at,
+                "address": address,
+            })))
+        }
+        Command::Keystore(args) => {
+            let keys = read_secret_key(&args.secret_key, &args.secret_key_file)?
+                .unwrap_or_else(SignatureKeys::generate_new_keypair);
+            let password = args.password.load()?;
+            let keystore = Keystore::encrypt(&keys, password.expose(), args.kdf.into())?;
+            keystore.write(Path::new(&args.out))?;
+            Ok(pretty(&json!({
+                "address": keys.address_key,
+                "public_key": keys.public_key,
+                "keystore": args.out,
+            })))
+        }
+        Command::Mnemonic(args) => {
+            let (phrase, generated) = match &args.mnemonic_file {
+                Some(path) => (FileSecretProvider.fetch(path)?, false),
+                None => (Secret::new(generate_mnemonic(args.words)?), true),
+            };
+            let passphrase = match &args.passphrase_file {
+                Some(path) => FileSecretProvider.fetch(path)?,
+                None => Secret::default(),
+            };
+            let paths = match &args.path {
+                Some(path) => vec![path.parse::<DerivationPath>()?],
+                None => (0..args.count)
+                    .map(|offset| {
+                        args.index
+                            .checked_add(offset)
+                            .filter(|index| *index < HARDENED)
+                            .map(DerivationPath::ethereum)
+                            .ok_or_else(|| "Account index is out of range".to_string())
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            let accounts = paths
+                .iter()
+                .map(|path| {
+                    let keys = derive_keys(phrase.expose(), passphrase.expose(), path)?;
+                    Ok(json!({
+                        "path": path.to_string(),
+                        "address": keys.address_key,
+                        "public_key": keys.public_key,
+                        "secret_key": keys.secret_key,
+                    }))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let mut output = json!({ "accounts": accounts });
+            if generated {
+                output["mnemonic"] = Value::from(phrase.expose());
+            }
+            Ok(pretty(&output))
+        }
+    }
+}
+
+fn read_json(path: &str) -> Result<Value, String> {
+    let mut contents = String::new();
+    if path == "-" {
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    } else {
+        contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    }
+    serde_json::from_str(&contents).map_err(|e| format!("{} is not valid JSON: {}", path, e))
+}
+
+fn read_typed_data(path: &str) -> Result<TypedData, String> {
+    serde_json::from_value(read_json(path)?)
+        .map_err(|e| format!("{} is not EIP-712 typed data: {}", path, e))
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).expect("JSON values always serialize")
+}
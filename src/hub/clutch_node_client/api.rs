@@ -0,0 +1,76 @@
+use super::client::ClutchNodeClient;
+use super::types::{
+    Block, GetBalanceRequest, GetBalanceResponse, GetBlockByIndexRequest, GetNextNonceRequest,
+    GetNextNonceResponse, GetTransactionRequest, SendRawTransactionResponse, Transaction,
+};
+use async_trait::async_trait;
+use serde_json::json;
+use tracing::info;
+
+/// Typed view of the clutch-node JSON-RPC methods the hub relies on.
+///
+/// Resolvers depend on this trait rather than on `ClutchNodeClient` so they
+/// can be exercised against an in-memory implementation.
+#[async_trait]
+pub trait NodeApi: Send + Sync {
+    /// Gets the next nonce value for the given address.
+    async fn get_next_nonce(&self, address: &str) -> Result<u64, String>;
+
+    /// Submits a signed, hex-encoded transaction.
+    async fn send_raw_transaction(
+        &self,
+        raw_transaction: &str,
+    ) -> Result<SendRawTransactionResponse, String>;
+
+    async fn get_latest_block(&self) -> Result<Block, String>;
+
+    async fn get_block_by_index(&self, index: u64) -> Result<Block, String>;
+
+    async fn get_transaction(&self, hash: &str) -> Result<Transaction, String>;
+
+    async fn get_balance(&self, address: &str) -> Result<u64, String>;
+}
+
+#[async_trait]
+impl NodeApi for ClutchNodeClient {
+    async fn get_next_nonce(&self, address: &str) -> Result<u64, String> {
+        let request = GetNextNonceRequest {
+            address: address.to_string(),
+        };
+        let response: GetNextNonceResponse = self.call("get_next_nonce", &request).await?;
+        info!("Retrieved nonce {} for address {}", response.nonce, address);
+        Ok(response.nonce)
+    }
+
+    async fn send_raw_transaction(
+        &self,
+        raw_transaction: &str,
+    ) -> Result<SendRawTransactionResponse, String> {
+        // The node expects the raw transaction as a bare string, not an object
+        self.call("send_raw_transaction", &raw_transaction).await
+    }
+
+    async fn get_latest_block(&self) -> Result<Block, String> {
+        self.call("get_latest_block", &json!({})).await
+    }
+
+    async fn get_block_by_index(&self, index: u64) -> Result<Block, String> {
+        self.call("get_block_by_index", &GetBlockByIndexRequest { index })
+            .await
+    }
+
+    async fn get_transaction(&self, hash: &str) -> Result<Transaction, String> {
+        let request = GetTransactionRequest {
+            hash: hash.to_string(),
+        };
+        self.call("get_transaction", &request).await
+    }
+
+    async fn get_balance(&self, address: &str) -> Result<u64, String> {
+        let request = GetBalanceRequest {
+            address: address.to_string(),
+        };
+        let response: GetBalanceResponse = self.call("get_balance", &request).await?;
+        Ok(response.balance)
+    }
+}
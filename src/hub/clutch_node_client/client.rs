@@ -1,164 +1,414 @@
 use super::connection::start_connection_loop;
-use super::types::{JSONRPCRequest, JSONRPCResponse};
-use futures_util::stream::SplitSink;
+use super::tls::{build_connector, build_handshake_request, TlsOptions};
+use super::types::{JSONRPCRequest, JSONRPCResponse, PendingRequests, SharedSink};
+use crate::hub::metric::{record_node_rpc, NODE_PENDING_REQUESTS};
+use crate::hub::redact::Secret;
+use crate::hub::shutdown::Shutdown;
+use crate::hub::telemetry::{current_request_id, current_traceparent};
+use futures_util::future::join_all;
 use futures_util::SinkExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json;
-use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio::sync::{oneshot, Mutex};
-use tokio::time::{timeout, Duration};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::time::{timeout, Duration, Instant};
 use tokio_tungstenite::tungstenite::protocol::Message;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use tracing::{error, info};
+use tracing::{debug, info_span, Instrument};
 use uuid::Uuid;
 
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Most of the shutdown grace period kept back for the close handshake.
+const MAX_CLOSE_WAIT: Duration = Duration::from_secs(1);
+
+/// Tuning knobs for the node connection.
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    /// How long a single request waits for its response.
+    pub request_timeout: Duration,
+    /// Coalesce requests issued in the same scheduler tick into one batch frame.
+    pub auto_batch: bool,
+    /// Upper bound on the number of requests in an automatically built batch.
+    pub max_batch_size: usize,
+    /// How often to ping the node; `Duration::ZERO` disables keepalive.
+    pub ping_interval: Duration,
+    /// How long to wait for any frame after a ping before forcing a reconnect.
+    pub pong_timeout: Duration,
+    /// Pause between reconnection attempts.
+    pub reconnect_delay: Duration,
+    /// How long opening the WebSocket, TLS handshake included, may take.
+    pub connect_timeout: Duration,
+    /// TLS settings applied to `wss://` URLs.
+    pub tls: TlsOptions,
+    /// Extra headers sent with the WebSocket handshake, e.g. `Authorization`.
+    pub handshake_headers: Vec<(String, Secret)>,
+    /// Attach the caller's W3C `traceparent` and `X-Request-Id` to each
+    /// JSON-RPC request.
+    pub propagate_trace_context: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            request_timeout: Duration::from_secs(10),
+            auto_batch: false,
+            max_batch_size: 50,
+            ping_interval: Duration::from_secs(20),
+            pong_timeout: Duration::from_secs(10),
+            reconnect_delay: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(10),
+            tls: TlsOptions::default(),
+            handshake_headers: Vec::new(),
+            propagate_trace_context: false,
+        }
+    }
+}
+
 pub struct ClutchNodeClient {
-    ws_sink: Arc<Mutex<Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>>,
-    pending_requests: Arc<Mutex<HashMap<String, oneshot::Sender<String>>>>,
+    ws_sink: SharedSink,
+    pending_requests: PendingRequests,
+    batch_tx: Option<mpsc::UnboundedSender<JSONRPCRequest>>,
+    options: ClientOptions,
+    closing: Shutdown,
 }
 
 impl ClutchNodeClient {
     /// Creates a new WebSocketManager and starts the connection task.
-    pub fn new(url: String) -> Arc<Self> {
+    pub fn new(url: String) -> Result<Arc<Self>, String> {
+        Self::with_options(url, ClientOptions::default())
+    }
+
+    /// Creates a client with explicit options and starts the connection task.
+    /// Fails if the URL, handshake headers or TLS files are unusable, as no
+    /// number of reconnects would fix them.
+    pub fn with_options(url: String, options: ClientOptions) -> Result<Arc<Self>, String> {
+        build_handshake_request(&url, &options.handshake_headers)?;
+        let connector = build_connector(&url, &options.tls)?;
         let ws_sink = Arc::new(Mutex::new(None));
         let pending_requests = Arc::new(Mutex::new(HashMap::new()));
 
         // Start the background connection task
+        let closing = Shutdown::new();
         let ws_sink_clone = ws_sink.clone();
         let pending_requests_clone = pending_requests.clone();
+        let options_clone = options.clone();
+        let closing_clone = closing.clone();
         tokio::spawn(async move {
-            start_connection_loop(url, ws_sink_clone, pending_requests_clone).await;
+            start_connection_loop(
+                url,
+                options_clone,
+                connector,
+                ws_sink_clone,
+                pending_requests_clone,
+                closing_clone,
+            )
+            .await;
         });
 
-        Arc::new(ClutchNodeClient {
+        // Start the micro-batcher if requested; it stops once the client is dropped
+        let batch_tx = if options.auto_batch {
+            let (batch_tx, batch_rx) = mpsc::unbounded_channel();
+            tokio::spawn(run_batcher(
+                batch_rx,
+                ws_sink.clone(),
+                pending_requests.clone(),
+                options.max_batch_size.max(1),
+            ));
+            Some(batch_tx)
+        } else {
+            None
+        };
+
+        Ok(Arc::new(ClutchNodeClient {
             ws_sink,
             pending_requests,
-        })
+            batch_tx,
+            options,
+            closing,
+        }))
+    }
+
+    /// Returns true while a WebSocket connection to the node is open.
+    pub async fn is_connected(&self) -> bool {
+        self.ws_sink.lock().await.is_some()
+    }
+
+    /// Lets pending requests finish, then closes the connection for good.
+    /// Requests still pending at that point fail. Draining and the close
+    /// handshake together take at most `grace`; a quarter of it, up to
+    /// `MAX_CLOSE_WAIT`, is kept back for the handshake.
+    pub async fn shutdown(&self, grace: Duration) {
+        let deadline = Instant::now() + grace;
+        let drained_by = deadline - (grace / 4).min(MAX_CLOSE_WAIT);
+        while Instant::now() < drained_by && !self.pending_requests.lock().await.is_empty() {
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
+
+        self.closing.trigger();
+
+        // The connection task sends the close frame and releases the sink. A
+        // write stuck on an unresponsive node holds the sink, so the lock
+        // itself is waited for under the deadline too.
+        let closed = async {
+            while self.is_connected().await {
+                tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+            }
+        };
+        let _ = tokio::time::timeout_at(deadline, closed).await;
+    }
+
+    fn build_request(&self, method: &str, params: serde_json::Value) -> JSONRPCRequest {
+        let id = Uuid::new_v4().to_string();
+        // Both are non-standard JSON-RPC members, so only send them on request
+        let (traceparent, request_id) = if self.options.propagate_trace_context {
+            (current_traceparent(), current_request_id())
+        } else {
+            (None, None)
+        };
+
+        JSONRPCRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id,
+            traceparent,
+            request_id,
+        }
     }
 
     /// Sends a request and awaits the response.
+    ///
+    /// With `auto_batch` enabled the request is queued and may share a frame
+    /// with other requests issued in the same tick.
     pub async fn send_request(
         &self,
         method: &str,
         params: serde_json::Value,
     ) -> Result<serde_json::Value, String> {
-        let id = Uuid::new_v4().to_string();
-        
-        // Format the request based on the method type
-        // For send_raw_transaction, params should be a direct string not an object
-        let request = if method == "send_raw_transaction" {
-            // For send_raw_transaction, extract the string from the Value
-            let tx_string = match &params {
-                serde_json::Value::String(s) => s.clone(),
-                _ => params.as_str().unwrap_or_default().to_string(),
-            };
-            
-            JSONRPCRequest {
-                jsonrpc: "2.0".to_string(),
-                method: method.to_string(),
-                params: serde_json::Value::String(tx_string),
-                id: id.clone(),
+        let span = info_span!("node.rpc", otel.kind = "client", rpc.method = method);
+        self.send_request_in_span(method, params).instrument(span).await
+    }
+
+    async fn send_request_in_span(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let started = Instant::now();
+        let request = self.build_request(method, params);
+        let id = request.id.clone();
+        let resp_rx = self.register(&id).await;
+
+        let sent = match &self.batch_tx {
+            Some(batch_tx) => batch_tx
+                .send(request)
+                .map_err(|_| "Request batcher is not running".to_string()),
+            None => {
+                let request_json = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+
+                // Bodies may carry signed transactions and locations, so only log the envelope
+                debug!(rpc.id = %id, "Sending {} request to node", method);
+                send_frame(&self.ws_sink, request_json).await
             }
-        } else {
-            // For other methods, use the params as provided
-            JSONRPCRequest {
-                jsonrpc: "2.0".to_string(),
-                method: method.to_string(),
-                params,
-                id: id.clone(),
+        };
+
+        let result = match sent {
+            Ok(()) => self.await_response(&id, resp_rx).await,
+            Err(e) => {
+                if self.pending_requests.lock().await.remove(&id).is_some() {
+                    NODE_PENDING_REQUESTS.dec();
+                }
+                Err(e)
             }
         };
 
-        let request_json = serde_json::to_string(&request).map_err(|e| e.to_string())?;
-        
-        // Log the actual request being sent for debugging
-        info!("Sending request to node: {}", request_json);
+        record_node_rpc(method, result.is_ok(), started.elapsed());
+        result
+    }
+
+    /// Sends a request with typed params and deserializes its result.
+    pub async fn call<P, R>(&self, method: &str, params: &P) -> Result<R, String>
+    where
+        P: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let params = serde_json::to_value(params).map_err(|e| e.to_string())?;
+        let result = self.send_request(method, params).await?;
+        serde_json::from_value(result)
+            .map_err(|e| format!("Unexpected {} response from node: {}", method, e))
+    }
+
+    /// Sends several requests as one JSON-RPC batch frame and returns their
+    /// results in the same order as `calls`.
+    pub async fn send_batch(
+        &self,
+        calls: Vec<(&str, serde_json::Value)>,
+    ) -> Vec<Result<serde_json::Value, String>> {
+        if calls.is_empty() {
+            return Vec::new();
+        }
 
-        // Check if the connection is established
-        let mut ws_sink_lock = self.ws_sink.lock().await;
-        if let Some(ws_sink) = ws_sink_lock.as_mut() {
-            let (resp_tx, resp_rx) = oneshot::channel();
+        let span = info_span!("node.rpc_batch", otel.kind = "client", rpc.batch_size = calls.len());
+        self.send_batch_in_span(calls).instrument(span).await
+    }
 
-            {
-                let mut pending = self.pending_requests.lock().await;
-                pending.insert(id.clone(), resp_tx);
+    async fn send_batch_in_span(
+        &self,
+        calls: Vec<(&str, serde_json::Value)>,
+    ) -> Vec<Result<serde_json::Value, String>> {
+        let started = Instant::now();
+        let requests: Vec<JSONRPCRequest> = calls
+            .into_iter()
+            .map(|(method, params)| self.build_request(method, params))
+            .collect();
+
+        let mut receivers = Vec::with_capacity(requests.len());
+        for request in &requests {
+            receivers.push(self.register(&request.id).await);
+        }
+
+        let sent = match serde_json::to_string(&requests) {
+            Ok(batch_json) => {
+                debug!("Sending batch of {} requests to node", requests.len());
+                send_frame(&self.ws_sink, batch_json).await
             }
+            Err(e) => Err(e.to_string()),
+        };
 
-            // Send the request
-            if let Err(e) = ws_sink.send(Message::Text(request_json)).await {
-                // Sending failed, remove the pending request
+        let results: Vec<Result<serde_json::Value, String>> = match sent {
+            Ok(()) => {
+                join_all(
+                    requests
+                        .iter()
+                        .zip(receivers)
+                        .map(|(request, resp_rx)| self.await_response(&request.id, resp_rx)),
+                )
+                .await
+            }
+            Err(e) => {
                 let mut pending = self.pending_requests.lock().await;
-                pending.remove(&id);
-                return Err(format!("Failed to send request: {}", e));
+                for request in &requests {
+                    if pending.remove(&request.id).is_some() {
+                        NODE_PENDING_REQUESTS.dec();
+                    }
+                }
+                requests.iter().map(|_| Err(e.clone())).collect()
             }
+        };
 
-            // Wait for response with timeout
-            let response_result = timeout(Duration::from_secs(10), resp_rx).await;
+        let elapsed = started.elapsed();
+        for (request, result) in requests.iter().zip(&results) {
+            record_node_rpc(&request.method, result.is_ok(), elapsed);
+        }
+        results
+    }
 
-            match response_result {
-                Ok(Ok(response_json)) => {
-                    if response_json.is_empty() {
-                        // Connection lost, and no response received
-                        return Err("Connection lost before receiving response".to_string());
-                    }
+    async fn register(&self, id: &str) -> oneshot::Receiver<Result<String, String>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        // The gauge is shared by every client, so it is moved rather than set
+        let mut pending = self.pending_requests.lock().await;
+        if pending.insert(id.to_string(), resp_tx).is_none() {
+            NODE_PENDING_REQUESTS.inc();
+        }
+        resp_rx
+    }
 
-                    // Log the response for debugging
-                    info!("Received response: {}", response_json);
+    async fn await_response(
+        &self,
+        id: &str,
+        resp_rx: oneshot::Receiver<Result<String, String>>,
+    ) -> Result<serde_json::Value, String> {
+        // Wait for response with timeout
+        let response_result = timeout(self.options.request_timeout, resp_rx).await;
 
-                    // Parse the response
-                    let response: JSONRPCResponse =
-                        serde_json::from_str(&response_json).map_err(|e| e.to_string())?;
-                    if response.id != id {
-                        return Err("Mismatched response ID".to_string());
-                    }
-                    if let Some(error) = response.error {
-                        Err(error.message)
-                    } else if let Some(result) = response.result {
-                        Ok(result)
-                    } else {
-                        Err("No result or error in response".to_string())
-                    }
+        match response_result {
+            Ok(Ok(Ok(response_json))) => {
+                debug!(rpc.id = %id, "Received response from node");
+
+                // Parse the response
+                let response: JSONRPCResponse =
+                    serde_json::from_str(&response_json).map_err(|e| e.to_string())?;
+                if response.id != id {
+                    return Err("Mismatched response ID".to_string());
                 }
-                Ok(Err(_)) => {
-                    // Sender was dropped
-                    Err("Failed to receive response".to_string())
+                if let Some(error) = response.error {
+                    Err(error.message)
+                } else if let Some(result) = response.result {
+                    Ok(result)
+                } else {
+                    Err("No result or error in response".to_string())
                 }
-                Err(_) => {
-                    // Timeout occurred
-                    let mut pending = self.pending_requests.lock().await;
-                    pending.remove(&id);
-                    Err("Request timed out".to_string())
+            }
+            Ok(Ok(Err(e))) => Err(e),
+            Ok(Err(_)) => {
+                // Sender was dropped
+                Err("Failed to receive response".to_string())
+            }
+            Err(_) => {
+                // Timeout occurred
+                if self.pending_requests.lock().await.remove(id).is_some() {
+                    NODE_PENDING_REQUESTS.dec();
                 }
+                Err("Request timed out".to_string())
             }
-        } else {
-            Err("WebSocket connection not established".to_string())
         }
     }
+}
 
-    /// Gets the next nonce value for the given address.
-    pub async fn get_next_nonce(&self, address: &str) -> u64 {
-        // Request the next nonce from the node
-        match self.send_request("get_next_nonce", json!({ "address": address })).await {
-            Ok(result) => {
-                // Try to parse the nonce value from the result
-                match result.get("nonce").and_then(|n| n.as_u64()) {
-                    Some(nonce) => {
-                        info!("Retrieved nonce {} for address {}", nonce, address);
-                        nonce
-                    },
-                    None => {
-                        error!("Failed to parse nonce value from response: {:?}", result);
-                        // Fallback to default nonce if parsing fails
-                        1
-                    }
+/// Writes a single text frame to the node, if connected.
+async fn send_frame(ws_sink: &SharedSink, frame: String) -> Result<(), String> {
+    let mut ws_sink_lock = ws_sink.lock().await;
+    match ws_sink_lock.as_mut() {
+        Some(sink) => sink
+            .send(Message::Text(frame))
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e)),
+        None => Err("WebSocket connection not established".to_string()),
+    }
+}
+
+/// Drains queued requests and sends everything issued in the same tick as one frame.
+async fn run_batcher(
+    mut batch_rx: mpsc::UnboundedReceiver<JSONRPCRequest>,
+    ws_sink: SharedSink,
+    pending_requests: PendingRequests,
+    max_batch_size: usize,
+) {
+    while let Some(first) = batch_rx.recv().await {
+        let mut batch = vec![first];
+
+        // Give other tasks woken in this tick a chance to enqueue their requests
+        tokio::task::yield_now().await;
+        while batch.len() < max_batch_size {
+            match batch_rx.try_recv() {
+                Ok(request) => batch.push(request),
+                Err(_) => break,
+            }
+        }
+
+        let frame = if batch.len() == 1 {
+            serde_json::to_string(&batch[0])
+        } else {
+            serde_json::to_string(&batch)
+        };
+
+        let sent = match frame {
+            Ok(frame) => {
+                debug!("Sending {} coalesced request(s) to node", batch.len());
+                send_frame(&ws_sink, frame).await
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        if let Err(e) = sent {
+            let mut pending = pending_requests.lock().await;
+            for request in &batch {
+                if let Some(resp_tx) = pending.remove(&request.id) {
+                    NODE_PENDING_REQUESTS.dec();
+                    let _ = resp_tx.send(Err(e.clone()));
                 }
-            },
-            Err(e) => {
-                error!("Failed to get nonce for address {}: {}", address, e);
-                // Fallback to default nonce if request fails
-                1
             }
         }
     }
@@ -1,26 +1,41 @@
 // src/hub/clutch_node_client/connection.rs
 
-use super::types::JSONRPCResponse;
-use futures_util::stream::SplitSink;
-use futures_util::StreamExt;
+use super::client::ClientOptions;
+use super::tls::build_handshake_request;
+use super::types::{JSONRPCResponse, PendingRequests, SharedSink};
+use crate::hub::metric::{NODE_CONNECTED, NODE_PENDING_REQUESTS, NODE_RECONNECTS};
+use crate::hub::redact::redactor;
+use crate::hub::shutdown::Shutdown;
+use futures_util::{SinkExt, StreamExt};
 use serde_json;
-use std::collections::HashMap;
-use std::sync::Arc;
+use tokio::time::{sleep_until, timeout, Duration, Instant};
 use tokio::net::TcpStream;
-use tokio::sync::{Mutex, oneshot};
-use tokio::time::Duration;
-use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::error::Error as WsError;
+use tokio_tungstenite::{
+    connect_async_tls_with_config, Connector, MaybeTlsStream, WebSocketStream,
+};
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tracing::{error, info};
 
 pub async fn start_connection_loop(
     url: String,
-    ws_sink: Arc<Mutex<Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>>,
-    pending_requests: Arc<Mutex<HashMap<String, oneshot::Sender<String>>>>,
+    options: ClientOptions,
+    connector: Option<Connector>,
+    ws_sink: SharedSink,
+    pending_requests: PendingRequests,
+    closing: Shutdown,
 ) {
     loop {
-        match connect_async(&url).await {
-            Ok((ws_stream, _)) => {
+        let connected = tokio::select! {
+            connected = timeout(options.connect_timeout, connect(&url, &options, &connector)) => {
+                connected.unwrap_or_else(|_| {
+                    Err(format!("no handshake within {:?}", options.connect_timeout))
+                })
+            }
+            _ = closing.wait() => return,
+        };
+        match connected {
+            Ok(ws_stream) => {
                 info!("Connected to clutch-node at {}", url);
                 let (sink, mut stream) = ws_stream.split();
 
@@ -29,38 +44,81 @@ pub async fn start_connection_loop(
                     let mut ws_sink_lock = ws_sink.lock().await;
                     *ws_sink_lock = Some(sink);
                 }
+                NODE_CONNECTED.set(1);
 
                 // Process incoming messages until the connection is closed
+                // or the node stops answering our pings
                 let pending_requests_clone = pending_requests.clone();
                 let ws_sink_clone = ws_sink.clone();
+                let mut keepalive = Keepalive::new(options.ping_interval, options.pong_timeout);
 
-                while let Some(msg) = stream.next().await {
-                    match msg {
-                        Ok(Message::Text(text)) => {
-                            handle_incoming_message(text, pending_requests_clone.clone()).await;
+                loop {
+                    tokio::select! {
+                        msg = stream.next() => {
+                            // Any frame from the node proves the link is alive
+                            keepalive.frame_received();
+                            match msg {
+                                Some(Ok(Message::Text(text))) => {
+                                    handle_incoming_message(text, pending_requests_clone.clone()).await;
+                                }
+                                Some(Ok(Message::Close(frame))) => {
+                                    info!("clutch-node closed the connection: {:?}", frame);
+                                    break;
+                                }
+                                Some(Ok(_)) => {
+                                    // Ping replies are queued by tungstenite; pongs only refresh liveness
+                                }
+                                Some(Err(e)) => {
+                                    error!("WebSocket error: {}", e);
+                                    break;
+                                }
+                                None => break,
+                            }
                         }
-                        Ok(_) => {
-                            // Handle other message types if needed
+                        _ = keepalive.next_ping() => {
+                            if let Err(e) = send_ping(&ws_sink_clone).await {
+                                error!("Failed to ping clutch-node: {}", e);
+                                break;
+                            }
+                            keepalive.ping_sent();
                         }
-                        Err(e) => {
-                            error!("WebSocket error: {}", e);
+                        _ = closing.wait() => break,
+                        _ = keepalive.pong_deadline() => {
+                            error!(
+                                "clutch-node did not answer a ping within {:?}, forcing reconnect",
+                                options.pong_timeout
+                            );
                             break;
                         }
                     }
                 }
 
-                // Connection lost, clear ws_sink
+                // Connection lost, close and clear ws_sink
                 {
                     let mut ws_sink_lock = ws_sink_clone.lock().await;
-                    *ws_sink_lock = None;
+                    if let Some(mut sink) = ws_sink_lock.take() {
+                        let _ = timeout(Duration::from_secs(1), sink.close()).await;
+                    }
                 }
+                NODE_CONNECTED.set(0);
 
                 // Notify pending requests about the disconnection
+                let reason = if closing.is_triggered() {
+                    "Client is shutting down"
+                } else {
+                    "Connection lost before receiving response"
+                };
                 let mut pending = pending_requests.lock().await;
+                NODE_PENDING_REQUESTS.dec_by(pending.len() as i64);
                 for (_, sender) in pending.drain() {
-                    let _ = sender.send("".to_string());
+                    let _ = sender.send(Err(reason.to_string()));
                 }
+                drop(pending);
 
+                if closing.is_triggered() {
+                    info!("Closed connection to clutch-node");
+                    return;
+                }
                 info!("Connection to clutch-node lost");
             }
             Err(e) => {
@@ -69,29 +127,154 @@ pub async fn start_connection_loop(
         }
 
         // Wait before attempting to reconnect
-        let retry_seconds = 5;
-        error!("Reconnecting to clutch-node in {} seconds...", retry_seconds);
-        tokio::time::sleep(Duration::from_secs(retry_seconds)).await;
+        NODE_RECONNECTS.inc();
+        error!("Reconnecting to clutch-node in {:?}...", options.reconnect_delay);
+        tokio::select! {
+            _ = tokio::time::sleep(options.reconnect_delay) => {}
+            _ = closing.wait() => return,
+        }
     }
 }
 
-async fn handle_incoming_message(
-    text: String,
-    pending_requests: Arc<Mutex<HashMap<String, oneshot::Sender<String>>>>,
-) {
+/// Opens the WebSocket with the configured handshake headers and TLS connector.
+async fn connect(
+    url: &str,
+    options: &ClientOptions,
+    connector: &Option<Connector>,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, String> {
+    let request = build_handshake_request(url, &options.handshake_headers)?;
+    let (ws_stream, _) = connect_async_tls_with_config(request, None, false, connector.clone())
+        .await
+        .map_err(|e| match e {
+            WsError::Http(response) => format!("handshake rejected with {}", response.status()),
+            e => e.to_string(),
+        })?;
+    Ok(ws_stream)
+}
+
+/// Ping schedule and pong deadline for one connection.
+struct Keepalive {
+    ping_interval: Duration,
+    pong_timeout: Duration,
+    next_ping_at: Instant,
+    pong_due_by: Option<Instant>,
+}
+
+impl Keepalive {
+    fn new(ping_interval: Duration, pong_timeout: Duration) -> Self {
+        Keepalive {
+            ping_interval,
+            pong_timeout,
+            next_ping_at: Instant::now() + ping_interval,
+            pong_due_by: None,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !self.ping_interval.is_zero()
+    }
+
+    fn frame_received(&mut self) {
+        self.pong_due_by = None;
+    }
+
+    fn ping_sent(&mut self) {
+        self.next_ping_at = Instant::now() + self.ping_interval;
+        if self.pong_due_by.is_none() {
+            self.pong_due_by = Some(Instant::now() + self.pong_timeout);
+        }
+    }
+
+    async fn next_ping(&self) {
+        if self.enabled() {
+            sleep_until(self.next_ping_at).await
+        } else {
+            std::future::pending().await
+        }
+    }
+
+    async fn pong_deadline(&self) {
+        match self.pong_due_by {
+            Some(deadline) if self.enabled() => sleep_until(deadline).await,
+            _ => std::future::pending().await,
+        }
+    }
+}
+
+async fn send_ping(ws_sink: &SharedSink) -> Result<(), String> {
+    let mut ws_sink_lock = ws_sink.lock().await;
+    match ws_sink_lock.as_mut() {
+        Some(sink) => sink
+            .send(Message::Ping(Vec::new()))
+            .await
+            .map_err(|e| e.to_string()),
+        None => Err("WebSocket connection not established".to_string()),
+    }
+}
+
+async fn handle_incoming_message(text: String, pending_requests: PendingRequests) {
+    match serde_json::from_str::<serde_json::Value>(&text) {
+        // A batch response: route every element to its own caller
+        Ok(serde_json::Value::Array(responses)) => {
+            for response in responses {
+                dispatch_response(response.to_string(), &pending_requests).await;
+            }
+        }
+        Ok(_) => dispatch_response(text, &pending_requests).await,
+        Err(e) => {
+            error!("Failed to parse {} byte message from node: {}", text.len(), e);
+        }
+    }
+}
+
+async fn dispatch_response(text: String, pending_requests: &PendingRequests) {
     match serde_json::from_str::<JSONRPCResponse>(&text) {
         Ok(response) => {
             let mut pending = pending_requests.lock().await;
             if let Some(resp_tx) = pending.remove(&response.id) {
-                let _ = resp_tx.send(text);
+                NODE_PENDING_REQUESTS.dec();
+                let _ = resp_tx.send(Ok(text));
             } else {
                 // Handle unexpected responses or notifications
-                info!("Received unexpected message: {}", text);
+                info!(rpc.id = %response.id, "Received response for unknown request");
             }
         }
-        Err(e) => {
-            error!("Failed to parse response: {}. Error: {}", text, e);
-            // Optionally handle invalid messages
-        }
+        Err(_) => match serde_json::from_str::<serde_json::Value>(&text) {
+            Ok(message) => info!(
+                "Received unexpected message: {}",
+                redactor().redact_json(message)
+            ),
+            Err(e) => error!("Failed to parse {} byte message from node: {}", text.len(), e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::{oneshot, Mutex};
+
+    #[tokio::test]
+    async fn test_batch_response_is_demultiplexed_by_id() {
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let (tx_a, rx_a) = oneshot::channel();
+        let (tx_b, rx_b) = oneshot::channel();
+        pending.lock().await.insert("a".to_string(), tx_a);
+        pending.lock().await.insert("b".to_string(), tx_b);
+
+        // Responses arrive out of order relative to the request ids
+        let batch = r#"[
+            {"jsonrpc":"2.0","result":{"nonce":7},"error":null,"id":"b"},
+            {"jsonrpc":"2.0","result":{"nonce":3},"error":null,"id":"a"}
+        ]"#;
+        handle_incoming_message(batch.to_string(), pending.clone()).await;
+
+        let a: JSONRPCResponse = serde_json::from_str(&rx_a.await.unwrap().unwrap()).unwrap();
+        let b: JSONRPCResponse = serde_json::from_str(&rx_b.await.unwrap().unwrap()).unwrap();
+        assert_eq!(a.result.unwrap()["nonce"], 3);
+        assert_eq!(b.result.unwrap()["nonce"], 7);
+        assert!(pending.lock().await.is_empty());
     }
 }
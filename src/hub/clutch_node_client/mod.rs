@@ -1,6 +1,11 @@
 
+mod api;
 pub mod client;
 mod connection;
-mod types;
+mod tls;
+pub mod types;
 
-pub use client::ClutchNodeClient;
+pub use api::NodeApi;
+pub use client::{ClientOptions, ClutchNodeClient};
+pub use tls::TlsOptions;
+pub(crate) use tls::parse_pin;
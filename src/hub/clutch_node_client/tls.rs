@@ -0,0 +1,265 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::Connector;
+
+use crate::hub::redact::Secret;
+
+/// TLS settings for `wss://` node URLs.
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    /// PEM bundle of CAs to trust instead of the system roots.
+    pub ca_file: Option<String>,
+    /// PEM client certificate chain presented for mutual TLS.
+    pub client_cert_file: Option<String>,
+    /// PEM private key matching `client_cert_file`.
+    pub client_key_file: Option<String>,
+    /// Hex SHA-256 fingerprints of acceptable node leaf certificates.
+    pub cert_pins: Vec<String>,
+}
+
+impl TlsOptions {
+    fn is_default(&self) -> bool {
+        self.ca_file.is_none()
+            && self.client_cert_file.is_none()
+            && self.client_key_file.is_none()
+            && self.cert_pins.is_empty()
+    }
+}
+
+/// Builds the handshake request for `url` with the extra headers attached.
+pub fn build_handshake_request(url: &str, headers: &[(String, Secret)]) -> Result<Request, String> {
+    let mut request = url
+        .into_client_request()
+        .map_err(|e| format!("Invalid node URL {}: {}", url, e))?;
+
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("Invalid handshake header name {}: {}", name, e))?;
+        let value = HeaderValue::from_str(value.expose())
+            .map_err(|e| format!("Invalid value for handshake header {}: {}", name, e))?;
+        request.headers_mut().insert(name, value);
+    }
+
+    Ok(request)
+}
+
+/// Returns a custom TLS connector for `url`, or `None` to use the defaults.
+/// TLS settings for a `ws://` URL are an error rather than silently unused.
+pub fn build_connector(url: &str, options: &TlsOptions) -> Result<Option<Connector>, String> {
+    if options.is_default() {
+        return Ok(None);
+    }
+    if !url.starts_with("wss://") {
+        return Err(format!("TLS settings require a wss:// node URL, not {}", url));
+    }
+
+    if options.client_cert_file.is_some() != options.client_key_file.is_some() {
+        return Err(
+            "Both a client certificate and a client key are required for mutual TLS".to_string(),
+        );
+    }
+
+    let provider = Arc::new(ring::default_provider());
+    let roots = Arc::new(load_root_store(options.ca_file.as_deref())?);
+
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Failed to configure TLS protocol versions: {}", e))?;
+
+    let builder = if options.cert_pins.is_empty() {
+        builder.with_root_certificates(roots)
+    } else {
+        let verifier = PinnedCertVerifier::new(roots, provider, &options.cert_pins)?;
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+    };
+
+    let config = match (&options.client_cert_file, &options.client_key_file) {
+        (Some(cert_file), Some(key_file)) => builder
+            .with_client_auth_cert(load_certs(cert_file)?, load_private_key(key_file)?)
+            .map_err(|e| format!("Invalid client certificate or key: {}", e))?,
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(Some(Connector::Rustls(Arc::new(config))))
+}
+
+fn load_root_store(ca_file: Option<&str>) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(ca_file) => {
+            for cert in load_certs(ca_file)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("Invalid CA certificate in {}: {}", ca_file, e))?;
+            }
+        }
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            let (added, _ignored) = roots.add_parsable_certificates(native.certs);
+            if added == 0 {
+                return Err("No usable system root certificates found".to_string());
+            }
+        }
+    }
+    Ok(roots)
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read certificates from {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("Failed to read private key from {}: {}", path, e))?
+        .ok_or_else(|| format!("No private key found in {}", path))
+}
+
+pub(crate) fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+    // Accept both plain hex and the colon-separated form printed by openssl
+    let cleaned: String = pin
+        .trim_start_matches("sha256:")
+        .chars()
+        .filter(|c| *c != ':')
+        .collect();
+    let bytes = hex::decode(&cleaned).map_err(|_| format!("Invalid certificate pin: {}", pin))?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("Certificate pin must be a SHA-256 fingerprint: {}", pin))
+}
+
+/// Verifies the chain as usual, then requires the leaf to match a pinned fingerprint.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedCertVerifier {
+    fn new(
+        roots: Arc<RootCertStore>,
+        provider: Arc<CryptoProvider>,
+        pins: &[String],
+    ) -> Result<Self, String> {
+        let inner = WebPkiServerVerifier::builder_with_provider(roots, provider)
+            .build()
+            .map_err(|e| format!("Failed to build certificate verifier: {}", e))?;
+        let pins = pins
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PinnedCertVerifier { inner, pins })
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+
+        let fingerprint = Sha256::digest(end_entity.as_ref());
+        if self.pins.iter().any(|pin| pin[..] == fingerprint[..]) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "Node certificate {} does not match any configured pin",
+                hex::encode(fingerprint)
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pin_accepts_hex_and_openssl_format() {
+        let hex_pin = "ab".repeat(32);
+        let openssl_pin = vec!["AB"; 32].join(":");
+        assert_eq!(parse_pin(&hex_pin).unwrap(), [0xab; 32]);
+        assert_eq!(parse_pin(&openssl_pin).unwrap(), [0xab; 32]);
+        assert!(parse_pin("abcd").is_err());
+        assert!(parse_pin("not-hex").is_err());
+    }
+
+    #[test]
+    fn test_handshake_request_carries_extra_headers() {
+        let headers = vec![("Authorization".to_string(), "Bearer abc".into())];
+        let request = build_handshake_request("wss://node.example:443/ws", &headers).unwrap();
+        assert_eq!(request.headers()["authorization"], "Bearer abc");
+
+        let bad = vec![("Bad Header".to_string(), "x".into())];
+        assert!(build_handshake_request("wss://node.example/ws", &bad).is_err());
+    }
+
+    #[test]
+    fn test_client_cert_requires_key() {
+        let options = TlsOptions {
+            client_cert_file: Some("client.pem".to_string()),
+            ..TlsOptions::default()
+        };
+        assert!(build_connector("wss://node.example/ws", &options).is_err());
+    }
+
+    #[test]
+    fn test_tls_settings_require_wss() {
+        let options = TlsOptions {
+            cert_pins: vec!["ab".repeat(32)],
+            ..TlsOptions::default()
+        };
+        let err = build_connector("ws://node.example/ws", &options).err().unwrap();
+        assert!(err.contains("wss://"), "{}", err);
+        assert!(build_connector("ws://node.example/ws", &TlsOptions::default())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,11 +1,32 @@
+use futures_util::stream::SplitSink;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::sync::{oneshot, Mutex};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 
-#[derive(Serialize, Deserialize)]
+pub type WsSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+pub type SharedSink = Arc<Mutex<Option<WsSink>>>;
+
+/// In-flight requests keyed by JSON-RPC id. Each sender receives the raw
+/// response object for that id, or an error if the request could not complete.
+pub type PendingRequests = Arc<Mutex<HashMap<String, oneshot::Sender<Result<String, String>>>>>;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct JSONRPCRequest {
     pub jsonrpc: String,
     pub method: String,
     pub params: serde_json::Value,
     pub id: String,
+    /// W3C trace context of the calling span, when propagation is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+    /// `X-Request-Id` of the hub request that triggered this call, when
+    /// propagation is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -22,3 +43,80 @@ pub struct JSONRPCError {
     pub message: String,
     pub data: Option<serde_json::Value>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetNextNonceRequest {
+    pub address: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetNextNonceResponse {
+    pub nonce: u64,
+}
+
+/// The node answers a submission with either the bare transaction hash or an
+/// object describing it; both are passed on to the caller as received.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum SendRawTransactionResponse {
+    Hash(String),
+    Other(serde_json::Value),
+}
+
+impl SendRawTransactionResponse {
+    pub fn tx_hash(&self) -> Option<&str> {
+        match self {
+            SendRawTransactionResponse::Hash(hash) => Some(hash),
+            SendRawTransactionResponse::Other(result) => {
+                result.get("tx_hash").and_then(serde_json::Value::as_str)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetBlockByIndexRequest {
+    pub index: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetTransactionRequest {
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetBalanceRequest {
+    pub address: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetBalanceResponse {
+    pub balance: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Block {
+    pub index: u64,
+    pub hash: String,
+    #[serde(default)]
+    pub previous_hash: String,
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Transaction {
+    pub hash: String,
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub nonce: u64,
+    #[serde(default)]
+    pub data: serde_json::Value,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
@@ -1,34 +1,869 @@
 use config::{Config, ConfigError, Environment, File};
 use dotenv::dotenv;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use tracing::info;
+use tracing_subscriber::EnvFilter;
 
+use crate::hub::clutch_node_client::parse_pin;
+use crate::hub::graphql::NODE_FIELD_COMPLEXITY;
+use crate::hub::rate_limit::TrustedProxy;
+use crate::hub::redact::{default_redacted_fields, Secret};
+use crate::hub::secrets::{
+    resolve_secret, EnvSecretProvider, FileSecretProvider, SecretProvider,
+};
+
+/// Shortest accepted `auth.jwt_secret`, in bytes. HS256 keys should carry at
+/// least as many bits as the hash output.
+pub const MIN_JWT_SECRET_LEN: usize = 32;
+
+/// Longest accepted `auth.jwt_expiration_hours` (30 days).
+pub const MAX_JWT_EXPIRATION_HOURS: u64 = 720;
+
+/// Longest accepted `config_watch_interval_secs` (1 hour).
+pub const MAX_CONFIG_WATCH_INTERVAL_SECS: u64 = 3600;
+
+/// Directory holding `default.toml` and the per-environment overlays.
+pub const CONFIG_DIR: &str = "config";
+
+/// Environment used when none is given: `default.toml` alone, with no overlay.
+pub const DEFAULT_ENV: &str = "default";
+
+/// Local development environment. Its overlay ships a public JWT secret, so
+/// it has to be asked for with `--env development`.
+pub const DEVELOPMENT_ENV: &str = "development";
+
+/// Top-level keys of the old flat configuration and their replacements.
+/// They would otherwise be ignored, silently falling back to defaults.
+const LEGACY_KEYS: &[(&str, &str)] = &[
+    ("clutch_node_ws_url", "node.ws_url"),
+    ("seq_url", "seq.url"),
+    ("seq_api_key", "seq.api_key"),
+    ("serve_metric_addr", "metrics.addr"),
+    ("jwt_secret", "auth.jwt_secret"),
+    ("jwt_expiration_hours", "auth.jwt_expiration_hours"),
+];
+
+/// JWT secrets that have been published with this repository. Anyone can
+/// mint tokens with them, so they are only accepted in development.
+const PUBLIC_JWT_SECRETS: &[&str] = &[
+    "development-only-jwt-secret-never-deploy-this",
+    "iP8BoK3dJfTQGz5UyXq9NwL7e0vCmAhR6S2YxE1ZpDt4",
+];
+
+/// Hub settings. Every section and field is optional and falls back to the
+/// defaults below, except secrets, which have to be configured. Each secret
+/// can instead be read from a file (`*_file`) or a named variable (`*_env`).
+/// Unknown keys are rejected rather than ignored, so a typo cannot quietly
+/// leave a setting at its default.
 #[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfig {
+    /// Environment the configuration was loaded for; not read from the files.
+    #[serde(skip)]
+    pub environment: String,
     pub log_level: String,
-    pub serve_metric_addr: String,
-    pub seq_url: String,
-    pub seq_api_key: String,
-    pub clutch_node_ws_url: String,
     pub ws_addr: String,
-    pub jwt_secret: String,
+    pub shutdown_grace_period_secs: u64,
+    pub config_watch_interval_secs: u64,
+    pub log_redact_fields: Vec<String>,
+    pub log_coordinate_decimals: u32,
+    pub otlp_endpoint: Option<String>,
+    pub otlp_protocol: String,
+    pub metrics: MetricsConfig,
+    pub seq: SeqConfig,
+    pub node: NodeConfig,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub limits: LimitsConfig,
+    pub operator: OperatorConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            environment: DEFAULT_ENV.to_string(),
+            log_level: "info".to_string(),
+            ws_addr: "0.0.0.0:8080".to_string(),
+            shutdown_grace_period_secs: 30,
+            config_watch_interval_secs: 5,
+            log_redact_fields: default_redacted_fields(),
+            log_coordinate_decimals: 2,
+            otlp_endpoint: None,
+            otlp_protocol: "http".to_string(),
+            metrics: MetricsConfig::default(),
+            seq: SeqConfig::default(),
+            node: NodeConfig::default(),
+            auth: AuthConfig::default(),
+            cors: CorsConfig::default(),
+            limits: LimitsConfig::default(),
+            operator: OperatorConfig::default(),
+        }
+    }
+}
+
+/// Prometheus endpoint and the block poller feeding its chain gauges.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub addr: String,
+    pub block_poll_interval_secs: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            addr: "0.0.0.0:3000".to_string(),
+            block_poll_interval_secs: 10,
+        }
+    }
+}
+
+/// Log shipping to Seq. Off unless enabled, so running without a Seq
+/// server stays quiet.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeqConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub api_key: Secret,
+    pub api_key_file: Option<String>,
+    pub api_key_env: Option<String>,
+    pub buffer_capacity: usize,
+    pub batch_size: usize,
+    pub flush_interval_ms: u64,
+    pub max_retries: u32,
+    pub connect_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+}
+
+impl Default for SeqConfig {
+    fn default() -> Self {
+        SeqConfig {
+            enabled: false,
+            url: "http://127.0.0.1:5341".to_string(),
+            api_key: Secret::default(),
+            api_key_file: None,
+            api_key_env: None,
+            buffer_capacity: 10_000,
+            batch_size: 100,
+            flush_interval_ms: 2_000,
+            max_retries: 3,
+            connect_timeout_secs: 5,
+            request_timeout_secs: 10,
+        }
+    }
+}
+
+/// Connection to the Clutch node.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    pub ws_url: String,
+    pub auto_batch: bool,
+    pub max_batch_size: usize,
+    pub ping_interval_secs: u64,
+    pub pong_timeout_secs: u64,
+    pub reconnect_delay_secs: u64,
+    pub connect_timeout_secs: u64,
+    pub trace_propagation: bool,
+    pub ca_file: Option<String>,
+    pub client_cert_file: Option<String>,
+    pub client_key_file: Option<String>,
+    pub cert_pins: Vec<String>,
+    pub auth_token: Option<Secret>,
+    pub auth_token_file: Option<String>,
+    pub auth_token_env: Option<String>,
+    pub handshake_headers: HashMap<String, Secret>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            ws_url: "ws://127.0.0.1:8081".to_string(),
+            auto_batch: false,
+            max_batch_size: 50,
+            ping_interval_secs: 20,
+            pong_timeout_secs: 10,
+            reconnect_delay_secs: 5,
+            connect_timeout_secs: 10,
+            trace_propagation: false,
+            ca_file: None,
+            client_cert_file: None,
+            client_key_file: None,
+            cert_pins: Vec::new(),
+            auth_token: None,
+            auth_token_file: None,
+            auth_token_env: None,
+            handshake_headers: HashMap::new(),
+        }
+    }
+}
+
+/// Token issuing. `jwt_secret` has no usable default and fails validation
+/// until it is set.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: Secret,
+    pub jwt_secret_file: Option<String>,
+    pub jwt_secret_env: Option<String>,
     pub jwt_expiration_hours: u64,
 }
 
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: Secret::default(),
+            jwt_secret_file: None,
+            jwt_secret_env: None,
+            jwt_expiration_hours: 6,
+        }
+    }
+}
+
+/// Cross-origin access to the GraphQL endpoint. `"*"` allows any origin.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub max_age_secs: usize,
+}
+
+impl CorsConfig {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            max_age_secs: 3600,
+        }
+    }
+}
+
+/// Bounds on the work a single client or request can cause.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Concurrent connections accepted per server worker.
+    pub max_connections: usize,
+    /// How long a resolver waits for the node to answer one request.
+    pub node_request_timeout_secs: u64,
+    /// Largest GraphQL request body accepted, in bytes.
+    pub max_body_bytes: usize,
+    /// Deepest nesting of fields a GraphQL query may have.
+    pub max_query_depth: usize,
+    /// Most complexity a GraphQL query may have. Fields cost 1, fields that
+    /// call the node `NODE_FIELD_COMPLEXITY`.
+    pub max_query_complexity: usize,
+    /// Whether clients may query the schema. Off unless configured, so a
+    /// public endpoint does not hand out its schema.
+    pub introspection: bool,
+    /// GraphQL requests each client IP may send.
+    pub rate_limit: RateLimit,
+    /// Tighter limits on root fields such as `generateToken`, by field name.
+    /// Counted per address for authenticated requests, per client IP otherwise.
+    pub operation_rate_limits: HashMap<String, RateLimit>,
+    /// Reverse proxies, as addresses or CIDR ranges, trusted to name the
+    /// client in `X-Forwarded-For` or `X-Real-IP`.
+    pub trusted_proxies: Vec<String>,
+}
+
+impl LimitsConfig {
+    /// Limit on the root field `field`; field names match in any case.
+    pub fn operation_rate_limit(&self, field: &str) -> Option<RateLimit> {
+        self.operation_rate_limits
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(field))
+            .map(|(_, limit)| *limit)
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_connections: 25_000,
+            node_request_timeout_secs: 10,
+            max_body_bytes: 64 * 1024,
+            max_query_depth: 15,
+            max_query_complexity: 500,
+            introspection: false,
+            rate_limit: RateLimit::new(600, 100),
+            operation_rate_limits: HashMap::from([
+                ("generateToken".to_string(), RateLimit::new(10, 5)),
+                ("createUnsignedRideRequest".to_string(), RateLimit::new(60, 10)),
+                ("sendRawTransaction".to_string(), RateLimit::new(60, 10)),
+            ]),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// A token bucket holding up to `burst` requests and refilled at `per_minute`.
+/// A `per_minute` of 0 turns the limit off.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        RateLimit { per_minute, burst }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.per_minute > 0
+    }
+}
+
+/// Key the hub signs with on its own behalf, kept in an encrypted keystore
+/// so the secret key itself never appears in configuration.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OperatorConfig {
+    pub keystore_file: Option<String>,
+    pub keystore_password: Secret,
+    pub keystore_password_file: Option<String>,
+    pub keystore_password_env: Option<String>,
+}
+
 impl AppConfig {
-    fn from_env(env: &str) -> Result<Self, ConfigError> {
+    /// Reads `{dir}/default.toml`, then `{dir}/{env}.toml` on top of it, then
+    /// `APP_` variables (`APP_NODE__WS_URL` sets `node.ws_url`), and finally
+    /// resolves secrets given by reference.
+    pub fn load_from(dir: &str, env: &str) -> Result<Self, ConfigError> {
         dotenv().ok();
-        let file_path = format!("config/{}.toml", env);
-        let builder = Config::builder()
-            .add_source(File::with_name(&file_path))
-            .add_source(Environment::with_prefix("APP"));
+        let mut builder =
+            Config::builder().add_source(File::with_name(&format!("{}/default.toml", dir)));
+        if env != DEFAULT_ENV {
+            builder = builder.add_source(File::with_name(&format!("{}/{}.toml", dir, env)));
+        }
+        let settings = builder
+            .add_source(
+                Environment::with_prefix("APP")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .list_separator(",")
+                    .with_list_parse_key("cors.allowed_origins")
+                    .with_list_parse_key("node.cert_pins")
+                    .with_list_parse_key("log_redact_fields")
+                    .try_parsing(true),
+            )
+            .build()?;
+        let legacy: Vec<String> = LEGACY_KEYS
+            .iter()
+            .filter(|(old, _)| settings.get::<config::Value>(old).is_ok())
+            .map(|(old, new)| {
+                format!(
+                    "{} (or APP_{}) is no longer read, set {} instead",
+                    old,
+                    old.to_ascii_uppercase(),
+                    new
+                )
+            })
+            .collect();
+        if !legacy.is_empty() {
+            return Err(ConfigError::Message(legacy.join("; ")));
+        }
+        let mut config = settings.try_deserialize::<Self>()?;
+        config.environment = env.to_string();
+        config
+            .resolve_secrets(&FileSecretProvider, &EnvSecretProvider)
+            .map_err(|errors| ConfigError::Message(errors.join("; ")))?;
+        Ok(config)
+    }
+
+    /// Replaces secrets that have a `*_file` or `*_env` reference with the
+    /// value `files` or `env` returns for it.
+    pub fn resolve_secrets(
+        &mut self,
+        files: &dyn SecretProvider,
+        env: &dyn SecretProvider,
+    ) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        let auth = &self.auth;
+        match resolve_secret(
+            "auth.jwt_secret",
+            &[(files, &auth.jwt_secret_file), (env, &auth.jwt_secret_env)],
+        ) {
+            Ok(Some(secret)) => self.auth.jwt_secret = secret,
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+
+        let seq = &self.seq;
+        match resolve_secret(
+            "seq.api_key",
+            &[(files, &seq.api_key_file), (env, &seq.api_key_env)],
+        ) {
+            Ok(Some(secret)) => self.seq.api_key = secret,
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+
+        let node = &self.node;
+        match resolve_secret(
+            "node.auth_token",
+            &[(files, &node.auth_token_file), (env, &node.auth_token_env)],
+        ) {
+            Ok(Some(secret)) => self.node.auth_token = Some(secret),
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+
+        let operator = &self.operator;
+        match resolve_secret(
+            "operator.keystore_password",
+            &[
+                (files, &operator.keystore_password_file),
+                (env, &operator.keystore_password_env),
+            ],
+        ) {
+            Ok(Some(secret)) => self.operator.keystore_password = secret,
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Tries to bind `ws_addr` and, if enabled, `metrics.addr`, releasing
+    /// them right away. `validate` only checks that they parse; this finds
+    /// ports already in use or needing privileges, for `--check-config`.
+    pub fn check_addresses_bindable(&self) -> Result<(), Vec<String>> {
+        let mut addrs = vec![("ws_addr", &self.ws_addr)];
+        if self.metrics.enabled {
+            addrs.push(("metrics.addr", &self.metrics.addr));
+        }
+        let errors: Vec<String> = addrs
+            .into_iter()
+            .filter_map(|(name, addr)| {
+                TcpListener::bind(addr.as_str())
+                    .err()
+                    .map(|e| format!("{} {:?} cannot be bound: {}", name, addr, e))
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks settings that would otherwise only fail once they are used.
+    /// Returns every problem found rather than stopping at the first.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if EnvFilter::try_new(&self.log_level).is_err() {
+            errors.push(format!(
+                "log_level {:?} is not a valid filter, use e.g. \"info\" or \
+                 \"clutch_hub_api=debug,info\"",
+                self.log_level
+            ));
+        }
+        check_socket_addr(&mut errors, "ws_addr", &self.ws_addr);
+        // 0 is allowed and leaves reloading to SIGHUP
+        check_range(
+            &mut errors,
+            "config_watch_interval_secs",
+            self.config_watch_interval_secs,
+            0,
+            MAX_CONFIG_WATCH_INTERVAL_SECS,
+        );
+        if let Some(endpoint) = &self.otlp_endpoint {
+            check_url(&mut errors, "otlp_endpoint", endpoint, &["http", "https"]);
+        }
+        if !matches!(self.otlp_protocol.as_str(), "http" | "grpc") {
+            errors.push(format!(
+                "otlp_protocol must be \"http\" or \"grpc\", not {:?}",
+                self.otlp_protocol
+            ));
+        }
+        check_range(
+            &mut errors,
+            "log_coordinate_decimals",
+            self.log_coordinate_decimals as u64,
+            0,
+            15,
+        );
+
+        if self.metrics.enabled {
+            check_socket_addr(&mut errors, "metrics.addr", &self.metrics.addr);
+            check_at_least_one(
+                &mut errors,
+                "metrics.block_poll_interval_secs",
+                self.metrics.block_poll_interval_secs,
+            );
+            if addrs_collide(&self.ws_addr, &self.metrics.addr) {
+                errors.push(format!(
+                    "metrics.addr {:?} and ws_addr {:?} cannot share a port",
+                    self.metrics.addr, self.ws_addr
+                ));
+            }
+        }
+
+        if self.seq.enabled {
+            check_url(&mut errors, "seq.url", &self.seq.url, &["http", "https"]);
+            check_at_least_one(&mut errors, "seq.batch_size", self.seq.batch_size as u64);
+            check_at_least_one(&mut errors, "seq.flush_interval_ms", self.seq.flush_interval_ms);
+            check_at_least_one(
+                &mut errors,
+                "seq.connect_timeout_secs",
+                self.seq.connect_timeout_secs,
+            );
+            check_at_least_one(
+                &mut errors,
+                "seq.request_timeout_secs",
+                self.seq.request_timeout_secs,
+            );
+            if self.seq.buffer_capacity < self.seq.batch_size {
+                errors.push(format!(
+                    "seq.buffer_capacity ({}) must be at least seq.batch_size ({})",
+                    self.seq.buffer_capacity, self.seq.batch_size
+                ));
+            }
+        }
+
+        let node = &self.node;
+        check_url(&mut errors, "node.ws_url", &node.ws_url, &["ws", "wss"]);
+        check_at_least_one(&mut errors, "node.max_batch_size", node.max_batch_size as u64);
+        check_at_least_one(&mut errors, "node.connect_timeout_secs", node.connect_timeout_secs);
+        // A zero ping interval turns keepalive off, so the timeout is unused
+        if node.ping_interval_secs > 0 {
+            check_at_least_one(&mut errors, "node.pong_timeout_secs", node.pong_timeout_secs);
+        }
+        let uses_tls = node.ca_file.is_some()
+            || node.client_cert_file.is_some()
+            || node.client_key_file.is_some()
+            || !node.cert_pins.is_empty();
+        if uses_tls && !node.ws_url.starts_with("wss://") {
+            errors.push(format!(
+                "node.ca_file, node.client_cert_file, node.client_key_file and \
+                 node.cert_pins need a wss:// node.ws_url, not {:?}",
+                node.ws_url
+            ));
+        }
+        for (name, path) in [
+            ("node.ca_file", &node.ca_file),
+            ("node.client_cert_file", &node.client_cert_file),
+            ("node.client_key_file", &node.client_key_file),
+        ] {
+            if let Some(path) = path {
+                if !Path::new(path).is_file() {
+                    errors.push(format!("{} {:?} does not exist or is not a file", name, path));
+                }
+            }
+        }
+        if node.client_cert_file.is_some() != node.client_key_file.is_some() {
+            errors.push(
+                "node.client_cert_file and node.client_key_file must be set together".to_string(),
+            );
+        }
+        for pin in &node.cert_pins {
+            if let Err(e) = parse_pin(pin) {
+                errors.push(format!("node.cert_pins: {}", e));
+            }
+        }
+
+        let auth = &self.auth;
+        if auth.jwt_secret.is_empty() {
+            errors.push("auth.jwt_secret must be set".to_string());
+        } else if auth.jwt_secret.expose().len() < MIN_JWT_SECRET_LEN {
+            errors.push(format!(
+                "auth.jwt_secret is {} bytes long, use at least {} random bytes \
+                 (e.g. `openssl rand -base64 48`)",
+                auth.jwt_secret.expose().len(),
+                MIN_JWT_SECRET_LEN
+            ));
+        }
+        if self.environment != DEVELOPMENT_ENV
+            && PUBLIC_JWT_SECRETS.contains(&auth.jwt_secret.expose())
+        {
+            errors.push(format!(
+                "auth.jwt_secret is a publicly known default and is only allowed in the {} \
+                 environment; set APP_AUTH__JWT_SECRET or auth.jwt_secret_file",
+                DEVELOPMENT_ENV
+            ));
+        }
+        check_range(
+            &mut errors,
+            "auth.jwt_expiration_hours",
+            auth.jwt_expiration_hours,
+            1,
+            MAX_JWT_EXPIRATION_HOURS,
+        );
 
-        builder.build()?.try_deserialize::<Self>()
+        if self.cors.allowed_origins.is_empty() {
+            errors.push(
+                "cors.allowed_origins must list at least one origin, or \"*\" for any".to_string(),
+            );
+        }
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" {
+                check_url(&mut errors, "cors.allowed_origins", origin, &["http", "https"]);
+            }
+        }
+
+        check_at_least_one(
+            &mut errors,
+            "limits.max_connections",
+            self.limits.max_connections as u64,
+        );
+        check_at_least_one(
+            &mut errors,
+            "limits.node_request_timeout_secs",
+            self.limits.node_request_timeout_secs,
+        );
+        check_at_least_one(
+            &mut errors,
+            "limits.max_body_bytes",
+            self.limits.max_body_bytes as u64,
+        );
+        check_at_least_one(
+            &mut errors,
+            "limits.max_query_depth",
+            self.limits.max_query_depth as u64,
+        );
+        if self.limits.max_query_complexity < NODE_FIELD_COMPLEXITY {
+            errors.push(format!(
+                "limits.max_query_complexity must be at least {} to allow one node call",
+                NODE_FIELD_COMPLEXITY
+            ));
+        }
+        let mut rate_limits: Vec<_> = self
+            .limits
+            .operation_rate_limits
+            .iter()
+            .map(|(field, limit)| (format!("limits.operation_rate_limits.{}", field), limit))
+            .collect();
+        rate_limits.sort_by(|a, b| a.0.cmp(&b.0));
+        rate_limits.insert(0, ("limits.rate_limit".to_string(), &self.limits.rate_limit));
+        for (name, limit) in rate_limits {
+            if limit.is_enabled() {
+                check_at_least_one(&mut errors, &format!("{}.burst", name), limit.burst as u64);
+            }
+        }
+        for proxy in &self.limits.trusted_proxies {
+            if let Err(e) = proxy.parse::<TrustedProxy>() {
+                errors.push(format!("limits.trusted_proxies: {}", e));
+            }
+        }
+
+        if let Some(path) = &self.operator.keystore_file {
+            if !Path::new(path).is_file() {
+                errors.push(format!(
+                    "operator.keystore_file {:?} does not exist or is not a file",
+                    path
+                ));
+            }
+            if self.operator.keystore_password.is_empty() {
+                errors.push(
+                    "operator.keystore_password must be set when operator.keystore_file is"
+                        .to_string(),
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Names the settings that differ in `other` but are only read at
+    /// startup, so a reload cannot apply them.
+    pub fn restart_required_changes(&self, other: &AppConfig) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name, differs: bool| {
+            if differs {
+                changed.push(name);
+            }
+        };
+        check("ws_addr", self.ws_addr != other.ws_addr);
+        check(
+            "shutdown_grace_period_secs",
+            self.shutdown_grace_period_secs != other.shutdown_grace_period_secs,
+        );
+        check(
+            "config_watch_interval_secs",
+            self.config_watch_interval_secs != other.config_watch_interval_secs,
+        );
+        check("otlp_endpoint", self.otlp_endpoint != other.otlp_endpoint);
+        check("otlp_protocol", self.otlp_protocol != other.otlp_protocol);
+        // Sections are compared field by field so the error names the setting.
+        // The pattern stops compiling when a field is added but not listed.
+        macro_rules! check_fields {
+            ($section:ident: $ty:ident { $($field:ident),+ }) => {
+                let $ty { $($field: _),+ } = &self.$section;
+                $(check(
+                    concat!(stringify!($section), ".", stringify!($field)),
+                    self.$section.$field != other.$section.$field,
+                );)+
+            };
+        }
+        check_fields!(metrics: MetricsConfig { enabled, addr, block_poll_interval_secs });
+        check_fields!(seq: SeqConfig {
+            enabled, url, api_key, api_key_file, api_key_env, buffer_capacity, batch_size,
+            flush_interval_ms, max_retries, connect_timeout_secs, request_timeout_secs
+        });
+        check_fields!(node: NodeConfig {
+            ws_url, auto_batch, max_batch_size, ping_interval_secs, pong_timeout_secs,
+            reconnect_delay_secs, connect_timeout_secs, trace_propagation, ca_file,
+            client_cert_file, client_key_file, cert_pins, auth_token, auth_token_file,
+            auth_token_env, handshake_headers
+        });
+        check("auth.jwt_secret", self.auth.jwt_secret != other.auth.jwt_secret);
+        check("cors.max_age_secs", self.cors.max_age_secs != other.cors.max_age_secs);
+        check(
+            "limits.max_connections",
+            self.limits.max_connections != other.limits.max_connections,
+        );
+        check(
+            "limits.node_request_timeout_secs",
+            self.limits.node_request_timeout_secs != other.limits.node_request_timeout_secs,
+        );
+        check(
+            "limits.max_body_bytes",
+            self.limits.max_body_bytes != other.limits.max_body_bytes,
+        );
+        check(
+            "limits.max_query_depth",
+            self.limits.max_query_depth != other.limits.max_query_depth,
+        );
+        check(
+            "limits.max_query_complexity",
+            self.limits.max_query_complexity != other.limits.max_query_complexity,
+        );
+        check("limits.introspection", self.limits.introspection != other.limits.introspection);
+        check_fields!(operator: OperatorConfig {
+            keystore_file, keystore_password, keystore_password_file, keystore_password_env
+        });
+        changed
     }
 
     pub fn load_configuration(env: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let config = AppConfig::from_env(env)?;
+        let config = AppConfig::load_from(CONFIG_DIR, env)?;
         info!("Loaded configuration from env {:?}: {:?}", env, config);
         Ok(config)
     }
 }
+
+/// The configuration in effect. Clones share it, so a reload reaches every
+/// holder; read it again per use rather than keeping the snapshot.
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<RwLock<Arc<AppConfig>>>,
+    reload_errors: Arc<RwLock<Vec<String>>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: AppConfig) -> Self {
+        LiveConfig {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+            reload_errors: Arc::default(),
+        }
+    }
+
+    pub fn current(&self) -> Arc<AppConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, config: AppConfig) {
+        *self.current.write().unwrap() = Arc::new(config);
+    }
+
+    /// Why the last reload was rejected; empty if it succeeded or none ran.
+    /// The configuration in effect was validated at startup.
+    pub fn reload_errors(&self) -> Vec<String> {
+        self.reload_errors.read().unwrap().clone()
+    }
+
+    pub fn set_reload_errors(&self, errors: Vec<String>) {
+        *self.reload_errors.write().unwrap() = errors;
+    }
+}
+
+impl From<AppConfig> for LiveConfig {
+    fn from(config: AppConfig) -> Self {
+        LiveConfig::new(config)
+    }
+}
+
+/// Only checks that `addr` parses and resolves; whether it can be bound is
+/// left to `check_addresses_bindable`.
+fn check_socket_addr(errors: &mut Vec<String>, name: &str, addr: &str) {
+    if let Err(e) = addr.to_socket_addrs() {
+        errors.push(format!(
+            "{} {:?} is not a valid host:port address: {}",
+            name, addr, e
+        ));
+    }
+}
+
+/// Whether listening on both addresses would claim the same port. Port 0
+/// picks a free port, so it never collides.
+fn addrs_collide(a: &str, b: &str) -> bool {
+    let resolve = |addr: &str| -> Vec<SocketAddr> {
+        addr.to_socket_addrs().map(Iterator::collect).unwrap_or_default()
+    };
+    let (a, b) = (resolve(a), resolve(b));
+    a.iter().any(|a| {
+        b.iter().any(|b| {
+            a.port() != 0
+                && a.port() == b.port()
+                && (a.ip() == b.ip() || a.ip().is_unspecified() || b.ip().is_unspecified())
+        })
+    })
+}
+
+fn check_url(errors: &mut Vec<String>, name: &str, value: &str, schemes: &[&str]) {
+    match url::Url::parse(value) {
+        Ok(url) if schemes.contains(&url.scheme()) => {}
+        Ok(url) => errors.push(format!(
+            "{} must use {}, not {}://",
+            name,
+            schemes
+                .iter()
+                .map(|scheme| format!("{}://", scheme))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            url.scheme()
+        )),
+        Err(e) => errors.push(format!("{} {:?} is not a valid URL: {}", name, value, e)),
+    }
+}
+
+fn check_at_least_one(errors: &mut Vec<String>, name: &str, value: u64) {
+    if value == 0 {
+        errors.push(format!("{} must be at least 1", name));
+    }
+}
+
+fn check_range(errors: &mut Vec<String>, name: &str, value: u64, min: u64, max: u64) {
+    if value < min || value > max {
+        errors.push(format!(
+            "{} must be between {} and {}, got {}",
+            name, min, max, value
+        ));
+    }
+}
@@ -1,19 +1,22 @@
-use actix_web::{web, HttpRequest};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{web, HttpMessage, HttpRequest};
+use async_graphql::http::{receive_body, MultipartOptions};
+use async_graphql::parser::types::DocumentOperations;
 use async_graphql::{Schema, EmptySubscription};
-use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use async_graphql_actix_web::GraphQLResponse;
 use crate::hub::graphql::{Query, Mutation};
+use crate::hub::auth::Claims;
 use crate::hub::graphql::types::AuthUser;
 use crate::hub::configuration::AppConfig;
+use crate::hub::keystore::OperatorKey;
+use crate::hub::metric::JWT_VALIDATION_FAILURES;
+use crate::hub::telemetry::{
+    extract_context, request_id_from_headers, with_request_id, REQUEST_ID_HEADER,
+};
 use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use serde::{Deserialize, Serialize};
-use tracing::error;
-
-// Define JWT claim structure
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    pub pk: String,      // public key
-    pub exp: usize,      // expiration time
-}
+use tracing::field::Empty;
+use tracing::{error, info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 // Extract JWT token from Authorization header and validate it
 fn extract_auth_user(req: &HttpRequest, config: &AppConfig) -> Option<AuthUser> {
@@ -29,7 +32,7 @@ fn extract_auth_user(req: &HttpRequest, config: &AppConfig) -> Option<AuthUser>
     let token = &auth_str["Bearer ".len()..];
     
     // Use JWT secret from configuration
-    let secret = config.jwt_secret.as_bytes();
+    let secret = config.auth.jwt_secret.expose().as_bytes();
     
     // Validate the JWT token and extract claims
     match decode::<Claims>(
@@ -43,31 +46,120 @@ fn extract_auth_user(req: &HttpRequest, config: &AppConfig) -> Option<AuthUser>
             
             // Create and return AuthUser from JWT claims
             Some(AuthUser {
-                public_key: claims.pk.clone(),
+                address: claims.pk,
             })
         },
         Err(err) => {
             // Log the error and return None
+            JWT_VALIDATION_FAILURES.inc();
             error!("JWT validation failed: {}", err);
             None
         }
     }
 }
 
+/// Outcome of checking a request's bearer token, kept with the request so it
+/// is checked (and its failure counted) only once.
+#[derive(Clone)]
+struct Authentication(Option<AuthUser>);
+
+/// The user a request's bearer token belongs to, if it has a valid one.
+pub fn authenticate(req: &HttpRequest, config: &AppConfig) -> Option<AuthUser> {
+    if let Some(Authentication(user)) = req.extensions().get::<Authentication>() {
+        return user.clone();
+    }
+    let user = extract_auth_user(req, config);
+    req.extensions_mut().insert(Authentication(user.clone()));
+    user
+}
+
+/// The `graphql.request` span of an HTTP request and its correlation id.
+#[derive(Clone)]
+pub struct RequestSpan {
+    pub span: Span,
+    pub request_id: String,
+}
+
+/// Returns the request's span, creating it on first use. Middleware that runs
+/// before the handler, such as the rate limiter, logs inside the same span,
+/// so authentication failures carry the request id too.
+pub fn request_span(req: &HttpRequest) -> RequestSpan {
+    if let Some(existing) = req.extensions().get::<RequestSpan>() {
+        return existing.clone();
+    }
+    let request_id = request_id_from_headers(req.headers());
+    // Everything logged while serving this request carries these fields
+    let span = info_span!(
+        "graphql.request",
+        otel.kind = "server",
+        request_id = %request_id,
+        operation = Empty,
+        auth_key = Empty,
+    );
+    // Continue the caller's trace when it sent a W3C traceparent header
+    span.set_parent(extract_context(req.headers()));
+    let created = RequestSpan { span, request_id };
+    req.extensions_mut().insert(created.clone());
+    created
+}
+
+/// Name of the operation a request will run: the explicit `operationName`, or
+/// the name of the only operation in the document.
+fn operation_name(request: &async_graphql::Request) -> Option<String> {
+    if let Some(name) = &request.operation_name {
+        return Some(name.clone());
+    }
+    let document = async_graphql::parser::parse_query(&request.query).ok()?;
+    match document.operations {
+        DocumentOperations::Single(_) => None,
+        DocumentOperations::Multiple(operations) if operations.len() == 1 => {
+            operations.keys().next().map(|name| name.to_string())
+        }
+        DocumentOperations::Multiple(_) => None,
+    }
+}
+
+/// Runs a GraphQL request. The body is read through `web::Bytes`, so the
+/// app's `web::PayloadConfig` (`limits.max_body_bytes`) caps its size.
 pub async fn graphql_handler(
     schema: web::Data<Schema<Query, Mutation, EmptySubscription>>,
     config: web::Data<AppConfig>,
-    req: GraphQLRequest,
+    operator_key: Option<web::Data<OperatorKey>>,
+    body: web::Bytes,
     http_req: HttpRequest,
-) -> GraphQLResponse {
+) -> actix_web::Result<GraphQLResponse> {
+    let content_type = http_req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let mut request = receive_body(content_type, body.as_ref(), MultipartOptions::default())
+        .await
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let RequestSpan { span, request_id } = request_span(&http_req);
+
     // Extract auth user from JWT token
-    let auth_user = extract_auth_user(&http_req, &config);
-    
-    // Build GraphQL request with auth data
-    let mut request = req.into_inner();
+    let auth_user = span.in_scope(|| authenticate(&http_req, &config));
+
+    // Add auth data to the GraphQL request
+    if let Some(name) = operation_name(&request) {
+        span.record("operation", name.as_str());
+    }
     if let Some(user) = auth_user {
+        span.record("auth_key", user.address.to_string().as_str());
         request = request.data(user);
     }
-    
-    schema.execute(request).await.into()
+    if let Some(operator_key) = operator_key {
+        request = request.data(operator_key.get_ref().clone());
+    }
+
+    let mut response = with_request_id(
+        request_id.clone(),
+        schema.execute(request).instrument(span),
+    )
+    .await;
+    if let Ok(value) = request_id.parse() {
+        response.http_headers.insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(response.into())
 }
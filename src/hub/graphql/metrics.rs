@@ -0,0 +1,76 @@
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery,
+};
+use async_graphql::parser::types::ExecutableDocument;
+use async_graphql::{Response, ServerResult, Variables};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::hub::metric::record_graphql_operation;
+use crate::hub::rate_limit::document_root_field_counts;
+
+/// Label for operations that select several different root fields.
+const MULTIPLE_FIELDS_LABEL: &str = "multiple";
+
+/// Label for operations whose root field is unknown.
+const OTHER_LABEL: &str = "other";
+
+/// Records operation counts and latency for every executed GraphQL operation.
+/// Operations are labelled by the root field they select rather than by the
+/// client-chosen operation name, so the number of series stays bounded by
+/// the schema.
+pub struct OperationMetrics;
+
+impl ExtensionFactory for OperationMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(OperationMetricsExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct OperationMetricsExtension {
+    document: Mutex<Option<ExecutableDocument>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for OperationMetricsExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        *self.document.lock().unwrap() = Some(document.clone());
+        Ok(document)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let started = Instant::now();
+        let response = next.run(ctx, operation_name).await;
+        let label = match self.document.lock().unwrap().as_ref() {
+            Some(document) => operation_label(document, operation_name),
+            None => OTHER_LABEL.to_string(),
+        };
+        record_graphql_operation(&label, response.is_ok(), started.elapsed());
+        response
+    }
+}
+
+/// The root field the operation selects. Only validated documents are
+/// executed, so it is always a field of the schema.
+fn operation_label(document: &ExecutableDocument, operation_name: Option<&str>) -> String {
+    let counts = document_root_field_counts(document, operation_name);
+    let mut fields = counts.into_keys();
+    match (fields.next(), fields.next()) {
+        (Some(field), None) => field,
+        (Some(_), Some(_)) => MULTIPLE_FIELDS_LABEL.to_string(),
+        (None, _) => OTHER_LABEL.to_string(),
+    }
+}
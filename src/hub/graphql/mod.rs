@@ -1,3 +1,4 @@
+pub mod metrics;
 pub mod mutation;
 pub mod query;
 pub mod types;
@@ -7,14 +8,35 @@ use std::sync::Arc;
 pub use mutation::Mutation;
 pub use query::Query;
 
+use async_graphql::extensions::Tracing;
 use async_graphql::{Schema, EmptySubscription};
 
-use super::clutch_node_client::ClutchNodeClient;
-use super::configuration::AppConfig;
+use super::clutch_node_client::NodeApi;
+use super::configuration::LiveConfig;
 
-pub fn build_schema(ws_manager: Arc<ClutchNodeClient>, config: AppConfig) -> Schema<Query, Mutation, EmptySubscription> {
-    Schema::build(Query::default(), Mutation::default(), EmptySubscription)
-        .data(ws_manager)
+/// Complexity of a field whose resolver calls the node; other fields cost 1.
+/// `limits.max_query_complexity` thus bounds the node calls per request.
+pub const NODE_FIELD_COMPLEXITY: usize = 100;
+
+/// Builds the schema with the depth, complexity and introspection settings
+/// of `limits`, which take effect at startup only.
+pub fn build_schema(
+    node: Arc<dyn NodeApi>,
+    config: impl Into<LiveConfig>,
+) -> Schema<Query, Mutation, EmptySubscription> {
+    let config = config.into();
+    let limits = config.current().limits.clone();
+    let builder = Schema::build(Query, Mutation, EmptySubscription)
+        .data(node)
         .data(config)
-        .finish()
+        .limit_depth(limits.max_query_depth)
+        .limit_complexity(limits.max_query_complexity)
+        .extension(metrics::OperationMetrics)
+        // Spans carry the query text, with arguments marked `secret` elided
+        .extension(Tracing);
+    if limits.introspection {
+        builder.finish()
+    } else {
+        builder.disable_introspection().finish()
+    }
 }
\ No newline at end of file
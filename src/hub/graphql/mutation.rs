@@ -2,9 +2,12 @@ use std::sync::Arc;
 
 use crate::hub::{
     auth,
-    clutch_node_client::ClutchNodeClient,
-    configuration::AppConfig,
+    clutch_node_client::NodeApi,
+    configuration::LiveConfig,
     graphql::types::{get_auth_user, AuthGuard, TokenResponse},
+    graphql::NODE_FIELD_COMPLEXITY,
+    metric::{JWT_ISSUED, JWT_ISSUE_FAILURES},
+    typed_data::TypedData,
 };
 use async_graphql::{Context, Json, Object};
 use serde_json::json;
@@ -26,33 +29,50 @@ pub struct Mutation;
 
 #[Object]
 impl Mutation {
+    /// Issues a token to whoever signed `challenge`, from `signInChallenge`,
+    /// with the 65-byte hex `signature` that `eth_signTypedData_v4` returns
     pub async fn generate_token(
         &self,
         ctx: &Context<'_>,
-        public_key: String,
+        challenge: Json<TypedData>,
+        #[graphql(secret)] signature: String,
     ) -> async_graphql::Result<TokenResponse> {
         let config = ctx
-            .data::<AppConfig>()
-            .map_err(|_| async_graphql::Error::new("Failed to get app config"))?;
-
-        let (token, expires_at) = auth::generate_jwt_token(
-            &public_key,
-            config.jwt_expiration_hours,
-            config.jwt_secret.as_str(),
-        )
-        .map_err(|e| async_graphql::Error::new(format!("Failed to generate token: {}", e)))?;
-
-        Ok(TokenResponse { token, expires_at })
+            .data::<LiveConfig>()
+            .map_err(|_| async_graphql::Error::new("Failed to get app config"))?
+            .current();
+
+        let secret = config.auth.jwt_secret.expose();
+        let (address, (token, expires_at)) = auth::verify_sign_in(&challenge, &signature, secret)
+            .and_then(|address| {
+                auth::generate_jwt_token(
+                    &address,
+                    config.auth.jwt_expiration_hours,
+                    secret,
+                )
+                .map(|token| (address, token))
+            })
+            .map_err(|e| {
+                JWT_ISSUE_FAILURES.inc();
+                async_graphql::Error::new(format!("Failed to generate token: {}", e))
+            })?;
+        JWT_ISSUED.inc();
+
+        Ok(TokenResponse {
+            token,
+            expires_at,
+            address,
+        })
     }
 
-    #[graphql(guard = "AuthGuard")]
+    #[graphql(guard = "AuthGuard", complexity = "NODE_FIELD_COMPLEXITY")]
     pub async fn create_unsigned_ride_request(
         &self,
         ctx: &Context<'_>,
-        pickup_latitude: f64,
-        pickup_longitude: f64,
-        dropoff_latitude: f64,
-        dropoff_longitude: f64,
+        #[graphql(secret)] pickup_latitude: f64,
+        #[graphql(secret)] pickup_longitude: f64,
+        #[graphql(secret)] dropoff_latitude: f64,
+        #[graphql(secret)] dropoff_longitude: f64,
         fare: i32,
     ) -> async_graphql::Result<Json<serde_json::Value>> {
         // Get authenticated user from context
@@ -60,21 +80,35 @@ impl Mutation {
             .ok_or_else(|| async_graphql::Error::new("User not authenticated"))?;
 
         info!(
-            "Processing ride request for user with public key: {}",
-            auth_user.public_key
+            pickup_latitude,
+            pickup_longitude,
+            dropoff_latitude,
+            dropoff_longitude,
+            fare,
+            "Processing ride request for user with address: {}",
+            auth_user.address
         );
 
-        let client = ctx
-            .data::<Arc<ClutchNodeClient>>()
-            .map_err(|_| async_graphql::Error::new("WebSocket manager not found"))?
+        let node = ctx
+            .data::<Arc<dyn NodeApi>>()
+            .map_err(|_| async_graphql::Error::new("Node client not found"))?
             .clone();
 
-        // Get the next nonce for this user using the client method
-        let nonce = client.get_next_nonce(&auth_user.public_key).await;
+        // The node keys accounts by lowercase address
+        let from = auth_user.address.to_lower_hex();
+
+        // Get the next nonce for this user from the node
+        let nonce = node
+            .get_next_nonce(&from)
+            .await
+            .map_err(|e| {
+                error!("Failed to get nonce for address {}: {}", auth_user.address, e);
+                async_graphql::Error::new(format!("Failed to get nonce: {}", e))
+            })?;
 
         // Create request parameters
         let params = json!({
-            "from": auth_user.public_key,
+            "from": from,
             "nonce": nonce,
             "data": {
                 "function_call_type": "RideRequest",
@@ -95,23 +129,23 @@ impl Mutation {
         Ok(Json(params))
     }
 
-    #[graphql(guard = "AuthGuard")]
+    #[graphql(guard = "AuthGuard", complexity = "NODE_FIELD_COMPLEXITY")]
     pub async fn send_raw_transaction(
         &self,
         ctx: &Context<'_>,
-        raw_transaction: String,
+        #[graphql(secret)] raw_transaction: String,
     ) -> async_graphql::Result<Json<serde_json::Value>> {
         let auth_user = get_auth_user(ctx)
             .ok_or_else(|| async_graphql::Error::new("User not authenticated"))?;
 
         info!(
-            "Submitting transaction for user with public key: {}",
-            auth_user.public_key
+            "Submitting transaction for user with address: {}",
+            auth_user.address
         );
 
-        let client = ctx
-            .data::<Arc<ClutchNodeClient>>()
-            .map_err(|_| async_graphql::Error::new("WebSocket manager not found"))?
+        let node = ctx
+            .data::<Arc<dyn NodeApi>>()
+            .map_err(|_| async_graphql::Error::new("Node client not found"))?
             .clone();
 
         // Ensure the raw transaction is properly formatted (has 0x prefix)
@@ -122,13 +156,12 @@ impl Mutation {
         };
 
         // Send the transaction to the node
-        // The client.send_request method will handle formatting the request properly
-        let result = client
-            .send_request("send_raw_transaction", serde_json::Value::String(formatted_tx))
+        let result = node
+            .send_raw_transaction(&formatted_tx)
             .await
             .map_err(|e| async_graphql::Error::new(format!("Failed to send transaction: {}", e)))?;
 
         // Return the result as JSON
-        Ok(Json(result))
+        Ok(Json(serde_json::to_value(result)?))
     }
 }
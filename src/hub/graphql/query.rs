@@ -1,5 +1,10 @@
+use crate::hub::address::Address;
+use crate::hub::auth;
+use crate::hub::configuration::LiveConfig;
 use crate::hub::graphql::types::{RideRequest, AuthGuard, get_auth_user};
-use async_graphql::{Context, Object};
+use crate::hub::keystore::OperatorKey;
+use crate::hub::typed_data::TypedData;
+use async_graphql::{Context, Json, Object};
 
 #[derive(Default)]
 pub struct Query;
@@ -25,4 +30,37 @@ impl Query {
             dropoff_location: "0".to_string(),
         })
     }
+
+    /// EIP-712 typed data of an unsigned ride request from
+    /// `createUnsignedRideRequest`, to pass to a wallet's `eth_signTypedData_v4`
+    pub async fn ride_request_typed_data(
+        &self,
+        #[graphql(secret)] transaction: Json<serde_json::Value>,
+    ) -> async_graphql::Result<Json<serde_json::Value>> {
+        let typed_data = TypedData::ride_request(&transaction).map_err(async_graphql::Error::new)?;
+        Ok(Json(serde_json::to_value(typed_data)?))
+    }
+
+    /// EIP-712 challenge for `address` (or its public key) to sign with
+    /// `eth_signTypedData_v4` and pass to `generateToken`
+    pub async fn sign_in_challenge(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+    ) -> async_graphql::Result<Json<TypedData>> {
+        let config = ctx
+            .data::<LiveConfig>()
+            .map_err(|_| async_graphql::Error::new("Failed to get app config"))?
+            .current();
+        // Addresses and public keys of the same account get the same challenge
+        let address = Address::from_address_or_public_key(&address)
+            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        Ok(Json(auth::sign_in_challenge(&address, config.auth.jwt_secret.expose())))
+    }
+
+    /// Address of the hub's operator key, or null when none is configured
+    pub async fn operator_address(&self, ctx: &Context<'_>) -> Option<String> {
+        ctx.data_opt::<OperatorKey>()
+            .map(|OperatorKey(keys)| keys.address_key.clone())
+    }
 }
@@ -1,6 +1,11 @@
-use async_graphql::{SimpleObject, Guard, Context, Result, Error};
+use async_graphql::{
+    Context, Error, Guard, InputValueError, InputValueResult, Result, Scalar, ScalarType,
+    SimpleObject, Value,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::hub::address::Address;
+
 #[derive(SimpleObject, Serialize, Deserialize)]
 pub struct RideRequest {
     pub pickup_location: String,
@@ -11,14 +16,32 @@ pub struct RideRequest {
 pub struct TokenResponse {
     pub token: String,
     pub expires_at: usize,
+    /// Address the token was issued to, whether a key or an address was given
+    pub address: Address,
 }
 
 #[derive(Clone, Debug)]
 pub struct AuthUser {
-    pub public_key: String,
+    pub address: Address,
     // Add additional user fields as needed (name, email, etc.)
 }
 
+/// An Ethereum address, returned with its EIP-55 checksum. Input may be in
+/// any case, with or without `0x`, but mixed case must match the checksum.
+#[Scalar]
+impl ScalarType for Address {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(text) => Ok(text.parse()?),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
 /// Authentication guard for GraphQL operations
 pub struct AuthGuard;
 
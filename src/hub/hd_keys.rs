@@ -0,0 +1,244 @@
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+use crate::hub::redact::REDACTED;
+use crate::hub::signature_keys::SignatureKeys;
+
+/// First hardened child index, written `0'` (or `0h`) in paths.
+pub const HARDENED: u32 = 1 << 31;
+
+/// Word counts BIP-39 defines, from 128 to 256 bits of entropy.
+pub const MNEMONIC_WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+
+/// Generates a random English mnemonic of `word_count` words.
+pub fn generate_mnemonic(word_count: usize) -> Result<String, String> {
+    if !MNEMONIC_WORD_COUNTS.contains(&word_count) {
+        return Err(format!(
+            "A mnemonic has 12, 15, 18, 21 or 24 words, not {}",
+            word_count
+        ));
+    }
+    Mnemonic::generate(word_count)
+        .map(|mnemonic| mnemonic.to_string())
+        .map_err(|e| format!("Failed to generate mnemonic: {}", e))
+}
+
+/// Checks the words and checksum of an English mnemonic.
+pub fn validate_mnemonic(phrase: &str) -> Result<(), String> {
+    parse_mnemonic(phrase).map(|_| ())
+}
+
+/// The 64-byte BIP-39 seed of `phrase`; `passphrase` may be empty.
+pub fn mnemonic_to_seed(phrase: &str, passphrase: &str) -> Result<Zeroizing<[u8; 64]>, String> {
+    Ok(Zeroizing::new(parse_mnemonic(phrase)?.to_seed(passphrase)))
+}
+
+fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, String> {
+    Mnemonic::parse(phrase.trim()).map_err(|e| format!("Invalid mnemonic: {}", e))
+}
+
+/// Derives the key pair at `path` from a mnemonic, as wallets do when they
+/// restore accounts from a recovery phrase.
+pub fn derive_keys(
+    phrase: &str,
+    passphrase: &str,
+    path: &DerivationPath,
+) -> Result<SignatureKeys, String> {
+    let seed = mnemonic_to_seed(phrase, passphrase)?;
+    Ok(ExtendedKey::master(&*seed)?.derive(path)?.keys())
+}
+
+/// A BIP-32 path such as `m/44'/60'/0'/0/0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// BIP-44 path of the `index`-th Ethereum account, `m/44'/60'/0'/0/{index}`,
+    /// the one MetaMask, Ledger and Hardhat use.
+    pub fn ethereum(index: u32) -> Self {
+        DerivationPath(vec![44 | HARDENED, 60 | HARDENED, HARDENED, 0, index])
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, String> {
+        let mut parts = path.trim().split('/');
+        if parts.next() != Some("m") {
+            return Err(format!("Derivation path {:?} must start with m/", path));
+        }
+        parts
+            .map(|part| {
+                let (number, hardened) = match part.strip_suffix(['\'', 'h', 'H']) {
+                    Some(number) => (number, HARDENED),
+                    None => (part, 0),
+                };
+                match number.parse::<u32>() {
+                    Ok(index) if index < HARDENED => Ok(index | hardened),
+                    _ => Err(format!("Derivation path {:?} has an invalid index {:?}", path, part)),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("m")?;
+        for index in &self.0 {
+            if index & HARDENED != 0 {
+                write!(f, "/{}'", index & !HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A BIP-32 extended private key: a secret key plus the chain code its
+/// children are derived with.
+pub struct ExtendedKey {
+    secret_key: SecretKey,
+    chain_code: Zeroizing<[u8; 32]>,
+}
+
+impl fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl ExtendedKey {
+    /// The master key of a 16 to 64 byte seed.
+    pub fn master(seed: &[u8]) -> Result<Self, String> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(format!("Seed must be 16 to 64 bytes, not {}", seed.len()));
+        }
+        Self::from_hmac(b"Bitcoin seed", seed, Ok)
+    }
+
+    /// Derives child `index`; indices from `HARDENED` up are hardened.
+    pub fn derive_child(&self, index: u32) -> Result<Self, String> {
+        let mut data = Zeroizing::new(Vec::with_capacity(37));
+        if index & HARDENED != 0 {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key.secret_bytes());
+        } else {
+            let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &self.secret_key);
+            data.extend_from_slice(&public_key.serialize());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        Self::from_hmac(&*self.chain_code, &data, |tweak| {
+            let tweak = Scalar::from_be_bytes(tweak.secret_bytes())
+                .map_err(|_| "Derived key is out of range".to_string())?;
+            self.secret_key
+                .add_tweak(&tweak)
+                .map_err(|_| "Derived key is out of range".to_string())
+        })
+        .map_err(|e| format!("{} for child {}, use another index", e, index))
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<Self, String> {
+        path.indices().iter().try_fold(
+            ExtendedKey {
+                secret_key: self.secret_key,
+                chain_code: self.chain_code.clone(),
+            },
+            |key, index| key.derive_child(*index),
+        )
+    }
+
+    pub fn keys(&self) -> SignatureKeys {
+        SignatureKeys::from_secret_key(&hex::encode(self.secret_key.secret_bytes()))
+            .expect("a SecretKey is always a valid secret key")
+    }
+
+    /// HMAC-SHA512 of `data` under `key`: the left half, passed through
+    /// `to_secret`, becomes the secret key and the right half the chain code.
+    fn from_hmac(
+        key: &[u8],
+        data: &[u8],
+        to_secret: impl FnOnce(SecretKey) -> Result<SecretKey, String>,
+    ) -> Result<Self, String> {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(data);
+        let output = Zeroizing::new(<[u8; 64]>::from(mac.finalize().into_bytes()));
+        let left = SecretKey::from_slice(&output[..32])
+            .map_err(|_| "Derived key is out of range".to_string())?;
+        let mut chain_code = Zeroizing::new([0u8; 32]);
+        chain_code.copy_from_slice(&output[32..]);
+        Ok(ExtendedKey {
+            secret_key: to_secret(left)?,
+            chain_code,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The mnemonic Hardhat and Anvil derive their default accounts from
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn test_bip32_vector() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed).unwrap();
+        for (path, secret_key) in [
+            ("m", "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"),
+            ("m/0'", "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"),
+            ("m/0'/1", "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"),
+        ] {
+            let path: DerivationPath = path.parse().unwrap();
+            assert_eq!(master.derive(&path).unwrap().keys().secret_key, secret_key);
+        }
+    }
+
+    #[test]
+    fn test_bip39_seed_vector() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                      abandon abandon about";
+        assert_eq!(
+            hex::encode(*mnemonic_to_seed(phrase, "TREZOR").unwrap()),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d1\
+             8264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        assert!(validate_mnemonic("abandon abandon abandon abandon abandon abandon abandon \
+                                   abandon abandon abandon abandon abandon")
+            .is_err());
+    }
+
+    #[test]
+    fn test_ethereum_accounts_match_wallets() {
+        let first = derive_keys(TEST_MNEMONIC, "", &DerivationPath::ethereum(0)).unwrap();
+        assert_eq!(first.address_key, "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266");
+        assert_eq!(
+            first.secret_key,
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        );
+        let second = derive_keys(TEST_MNEMONIC, "", &"m/44h/60h/0h/0/1".parse().unwrap()).unwrap();
+        assert_eq!(second.address_key, "0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+    }
+
+    #[test]
+    fn test_path_round_trip() {
+        let path: DerivationPath = "m/44'/60'/0'/0/7".parse().unwrap();
+        assert_eq!(path, DerivationPath::ethereum(7));
+        assert_eq!(path.to_string(), "m/44'/60'/0'/0/7");
+        assert!("44'/60'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use actix_web::{http::StatusCode, web, HttpResponse};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::hub::clutch_node_client::ClutchNodeClient;
+use crate::hub::configuration::LiveConfig;
+use crate::hub::shutdown::Shutdown;
+
+struct Worker {
+    max_silence: Duration,
+    critical: bool,
+    last_beat: Instant,
+}
+
+/// Heartbeats of the hub's background tasks.
+///
+/// A worker that stays silent for longer than its `max_silence` has died or
+/// hung. Stale critical workers take the hub out of rotation; the others are
+/// only reported.
+#[derive(Default)]
+pub struct Workers {
+    workers: Mutex<HashMap<String, Worker>>,
+}
+
+impl Workers {
+    pub fn register(&self, name: &str, max_silence: Duration, critical: bool) {
+        self.workers.lock().unwrap().insert(
+            name.to_string(),
+            Worker {
+                max_silence,
+                critical,
+                last_beat: Instant::now(),
+            },
+        );
+    }
+
+    pub fn heartbeat(&self, name: &str) {
+        if let Some(worker) = self.workers.lock().unwrap().get_mut(name) {
+            worker.last_beat = Instant::now();
+        }
+    }
+
+    /// Returns whether every critical worker is alive, and a per-worker breakdown.
+    pub fn report(&self) -> (bool, Value) {
+        let workers = self.workers.lock().unwrap();
+        let mut healthy = true;
+        let mut breakdown = Map::new();
+        for (name, worker) in workers.iter() {
+            let silent_for = worker.last_beat.elapsed();
+            let alive = silent_for <= worker.max_silence;
+            healthy &= alive || !worker.critical;
+            breakdown.insert(
+                name.clone(),
+                json!({
+                    "status": if alive { "up" } else { "stale" },
+                    "critical": worker.critical,
+                    "last_heartbeat_secs": silent_for.as_secs(),
+                }),
+            );
+        }
+        (healthy, Value::Object(breakdown))
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref WORKERS: Workers = Workers::default();
+}
+
+fn component(up: bool) -> &'static str {
+    if up {
+        "up"
+    } else {
+        "down"
+    }
+}
+
+/// Liveness probe: the process is running and serving HTTP.
+pub async fn livez() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "up" }))
+}
+
+/// Readiness probe: the hub can serve requests end to end. The config
+/// component reports the last reload, as the hub only starts with a valid one.
+/// A draining hub is never ready, so balancers stop sending it requests.
+pub async fn readyz(
+    node: web::Data<Arc<ClutchNodeClient>>,
+    config: web::Data<LiveConfig>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    let node_up = node.is_connected().await;
+    let config_errors = config.reload_errors();
+    let config_up = config_errors.is_empty();
+    let (workers_up, workers) = WORKERS.report();
+    let draining = shutdown.is_triggered();
+
+    let ready = node_up && config_up && workers_up && !draining;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    HttpResponse::build(status).json(json!({
+        "status": component(ready),
+        "components": {
+            "node": { "status": component(node_up) },
+            "config": { "status": component(config_up), "errors": config_errors },
+            "workers": { "status": component(workers_up), "workers": workers },
+            "shutdown": { "status": component(!draining) },
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_stale_critical_workers_fail_the_report() {
+        let workers = Workers::default();
+        workers.register("poller", Duration::from_secs(60), true);
+        workers.register("shipper", Duration::ZERO, false);
+        std::thread::sleep(Duration::from_millis(5));
+
+        let (healthy, breakdown) = workers.report();
+        assert!(healthy);
+        assert_eq!(breakdown["poller"]["status"], "up");
+        assert_eq!(breakdown["shipper"]["status"], "stale");
+
+        workers.register("poller", Duration::ZERO, true);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!workers.report().0);
+
+        workers.register("poller", Duration::from_secs(60), true);
+        workers.heartbeat("poller");
+        assert!(workers.report().0);
+    }
+}
@@ -0,0 +1,574 @@
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+use crate::hub::configuration::OperatorConfig;
+use crate::hub::signature_keys::SignatureKeys;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+const CIPHER: &str = "aes-128-ctr";
+const PBKDF2_PRF: &str = "hmac-sha256";
+const DERIVED_KEY_LEN: usize = 32;
+
+// Bounds on the KDF parameters of a keystore file, so a crafted file cannot
+// make unlocking allocate gigabytes or run for hours. They leave room well
+// above geth's standard parameters (n = 2^18, r = 8, p = 1).
+const MAX_DKLEN: usize = 64;
+const MAX_SCRYPT_N: u64 = 1 << 20;
+const MAX_SCRYPT_RP: u64 = 1 << 16;
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_PBKDF2_ROUNDS: u32 = 10_000_000;
+
+/// Key derivation used to turn the password into the encryption key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// scrypt with `n = 2^log_n`.
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    /// PBKDF2 with HMAC-SHA256.
+    Pbkdf2 { iterations: u32 },
+}
+
+impl Kdf {
+    /// The "standard" scrypt parameters geth and most wallets write.
+    pub const SCRYPT: Kdf = Kdf::Scrypt {
+        log_n: 18,
+        r: 8,
+        p: 1,
+    };
+
+    /// geth's `--lightkdf` parameters, for keys on low-memory machines.
+    pub const SCRYPT_LIGHT: Kdf = Kdf::Scrypt {
+        log_n: 12,
+        r: 8,
+        p: 6,
+    };
+
+    /// The PBKDF2 iteration count of the Web3 Secret Storage test vectors.
+    pub const PBKDF2: Kdf = Kdf::Pbkdf2 {
+        iterations: 262_144,
+    };
+}
+
+/// A secret key encrypted in the Web3 Secret Storage (version 3) format used
+/// by geth, MetaMask exports, MyEtherWallet and ethers/web3 libraries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    // Some older wallets capitalize this key
+    #[serde(alias = "Crypto")]
+    crypto: CryptoSection,
+    pub id: String,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CryptoSection {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    #[serde(flatten)]
+    kdf: KdfParams,
+    mac: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kdf", content = "kdfparams", rename_all = "lowercase")]
+enum KdfParams {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        p: u32,
+        r: u32,
+        salt: String,
+    },
+    Pbkdf2 {
+        c: u32,
+        dklen: usize,
+        prf: String,
+        salt: String,
+    },
+}
+
+impl KdfParams {
+    fn derive(&self, password: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+        match self {
+            KdfParams::Scrypt {
+                dklen,
+                n,
+                p,
+                r,
+                salt,
+            } => {
+                check_dklen(*dklen)?;
+                if *n < 2 || !n.is_power_of_two() {
+                    return Err(format!("Keystore scrypt n must be a power of two, not {}", n));
+                }
+                check_scrypt_cost(*n, *r, *p)?;
+                let salt = decode_hex("salt", salt)?;
+                let mut key = Zeroizing::new(vec![0u8; *dklen]);
+                let log_n = n.trailing_zeros();
+                if log_n < 16 * r {
+                    let invalid = |_| {
+                        format!("Keystore scrypt parameters n={} r={} p={} are invalid", n, r, p)
+                    };
+                    let params =
+                        scrypt::Params::new(log_n as u8, *r, *p, *dklen).map_err(invalid)?;
+                    scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key)
+                        .expect("dklen is checked above");
+                } else {
+                    geth_scrypt(password.as_bytes(), &salt, *n, *r, *p, &mut key)?;
+                }
+                Ok(key)
+            }
+            KdfParams::Pbkdf2 {
+                c,
+                dklen,
+                prf,
+                salt,
+            } => {
+                check_dklen(*dklen)?;
+                if prf != PBKDF2_PRF {
+                    return Err(format!("Keystore PBKDF2 prf {:?} is not supported", prf));
+                }
+                if *c == 0 || *c > MAX_PBKDF2_ROUNDS {
+                    return Err(format!(
+                        "Keystore PBKDF2 c must be between 1 and {}, not {}",
+                        MAX_PBKDF2_ROUNDS, c
+                    ));
+                }
+                let mut key = Zeroizing::new(vec![0u8; *dklen]);
+                pbkdf2::pbkdf2_hmac::<Sha256>(
+                    password.as_bytes(),
+                    &decode_hex("salt", salt)?,
+                    *c,
+                    &mut key,
+                );
+                Ok(key)
+            }
+        }
+    }
+}
+
+impl Keystore {
+    /// Encrypts the secret key of `keys` with `password`, using a fresh salt
+    /// and IV.
+    pub fn encrypt(keys: &SignatureKeys, password: &str, kdf: Kdf) -> Result<Self, String> {
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+        let kdf = match kdf {
+            Kdf::Scrypt { log_n, r, p } => KdfParams::Scrypt {
+                dklen: DERIVED_KEY_LEN,
+                n: 1u64
+                    .checked_shl(log_n as u32)
+                    .ok_or_else(|| format!("scrypt log_n {} is too large", log_n))?,
+                p,
+                r,
+                salt: hex::encode(salt),
+            },
+            Kdf::Pbkdf2 { iterations } => KdfParams::Pbkdf2 {
+                c: iterations,
+                dklen: DERIVED_KEY_LEN,
+                prf: PBKDF2_PRF.to_string(),
+                salt: hex::encode(salt),
+            },
+        };
+
+        let derived = kdf.derive(password)?;
+        let mut ciphertext = Zeroizing::new(
+            hex::decode(&keys.secret_key).map_err(|_| "Secret key is not valid hex".to_string())?,
+        );
+        Aes128Ctr::new(derived[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+        let mac = mac(&derived, &ciphertext);
+
+        Ok(Keystore {
+            address: Some(keys.address_key.trim_start_matches("0x").to_string()),
+            crypto: CryptoSection {
+                cipher: CIPHER.to_string(),
+                cipherparams: CipherParams {
+                    iv: hex::encode(iv),
+                },
+                ciphertext: hex::encode(&ciphertext),
+                kdf,
+                mac: hex::encode(mac),
+            },
+            id: uuid::Uuid::new_v4().to_string(),
+            version: 3,
+        })
+    }
+
+    /// Recovers the key pair. The MAC is checked before anything is
+    /// decrypted, so a wrong password is reported as such.
+    pub fn decrypt(&self, password: &str) -> Result<SignatureKeys, String> {
+        if self.version != 3 {
+            return Err(format!("Keystore version {} is not supported", self.version));
+        }
+        let crypto = &self.crypto;
+        if crypto.cipher != CIPHER {
+            return Err(format!("Keystore cipher {:?} is not supported", crypto.cipher));
+        }
+        let iv = decode_hex("iv", &crypto.cipherparams.iv)?;
+        let iv: [u8; 16] = iv
+            .try_into()
+            .map_err(|_| "Keystore iv must be 16 bytes".to_string())?;
+        let ciphertext = decode_hex("ciphertext", &crypto.ciphertext)?;
+        let expected_mac = decode_hex("mac", &crypto.mac)?;
+
+        let derived = crypto.kdf.derive(password)?;
+        if !constant_time_eq(&mac(&derived, &ciphertext), &expected_mac) {
+            return Err("Wrong keystore password, or the keystore is corrupted".to_string());
+        }
+        let mut secret_key = Zeroizing::new(ciphertext);
+        Aes128Ctr::new(derived[..16].into(), (&iv).into()).apply_keystream(&mut secret_key);
+        let keys = SignatureKeys::from_secret_key(&hex::encode(&*secret_key))?;
+
+        if let Some(address) = &self.address {
+            let address = address.trim_start_matches("0x");
+            if !keys.address_key[2..].eq_ignore_ascii_case(address) {
+                return Err(format!(
+                    "Keystore address 0x{} does not match its key ({})",
+                    address, keys.address_key
+                ));
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Not a valid keystore: {}", e))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("keystores always serialize")
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read keystore {}: {}", path.display(), e))?;
+        Self::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes the keystore to a new file that only the owner can read.
+    /// Existing files are never overwritten.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(path)
+            .map_err(|e| format!("Failed to create keystore {}: {}", path.display(), e))?;
+        file.write_all(self.to_json().as_bytes())
+            .map_err(|e| format!("Failed to write keystore {}: {}", path.display(), e))
+    }
+}
+
+/// The hub's own key pair, unlocked from `operator.keystore_file` at startup
+/// and shared with the server as `web::Data` and GraphQL request data.
+#[derive(Clone)]
+pub struct OperatorKey(pub Arc<SignatureKeys>);
+
+/// Unlocks the operator keystore, if one is configured.
+pub fn unlock_operator_key(config: &OperatorConfig) -> Result<Option<OperatorKey>, String> {
+    let Some(path) = &config.keystore_file else {
+        return Ok(None);
+    };
+    Keystore::read(Path::new(path))?
+        .decrypt(config.keystore_password.expose())
+        .map(|keys| Some(OperatorKey(Arc::new(keys))))
+        .map_err(|e| format!("operator.keystore_file: {}", e))
+}
+
+/// scrypt for the parameters geth accepts beyond RFC 7914, which requires
+/// `n < 2^(16 * r)`. Keystores written with `n = 2^18, r = 1` exist in the
+/// wild and the `scrypt` crate refuses them, so those alone are derived here
+/// with the same algorithm; everything else goes through the crate.
+fn geth_scrypt(
+    password: &[u8],
+    salt: &[u8],
+    n: u64,
+    r: u32,
+    p: u32,
+    output: &mut [u8],
+) -> Result<(), String> {
+    let invalid = || format!("Keystore scrypt parameters n={} r={} p={} are invalid", n, r, p);
+    let (r, p) = (r as usize, p as usize);
+    let n = usize::try_from(n).map_err(|_| invalid())?;
+    if r == 0 || p == 0 || r * p >= 1 << 30 {
+        return Err(invalid());
+    }
+    let words = 32 * r;
+    let scratch_len = n.checked_mul(words).ok_or_else(invalid)?;
+
+    let mut blocks = Zeroizing::new(vec![0u8; 128 * r * p]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, 1, &mut blocks);
+    let mut scratch = Zeroizing::new(vec![0u32; scratch_len]);
+    for block in blocks.chunks_mut(128 * r) {
+        ro_mix(block, n, r, &mut scratch);
+    }
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, &blocks, 1, output);
+    Ok(())
+}
+
+fn ro_mix(block: &mut [u8], n: usize, r: usize, scratch: &mut [u32]) {
+    let words = 32 * r;
+    let mut x: Zeroizing<Vec<u32>> = Zeroizing::new(
+        block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().expect("4-byte chunk")))
+            .collect(),
+    );
+    let mut y = Zeroizing::new(vec![0u32; words]);
+    for i in 0..n {
+        scratch[i * words..(i + 1) * words].copy_from_slice(&x);
+        block_mix(&x, &mut y, r);
+        std::mem::swap(&mut x, &mut y);
+    }
+    for _ in 0..n {
+        let last = &x[words - 16..];
+        let j = ((last[1] as u64) << 32 | last[0] as u64) as usize & (n - 1);
+        for (x, v) in x.iter_mut().zip(&scratch[j * words..(j + 1) * words]) {
+            *x ^= v;
+        }
+        block_mix(&x, &mut y, r);
+        std::mem::swap(&mut x, &mut y);
+    }
+    for (bytes, word) in block.chunks_exact_mut(4).zip(x.iter()) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn block_mix(input: &[u32], output: &mut [u32], r: usize) {
+    let mut t: [u32; 16] = input[(2 * r - 1) * 16..].try_into().expect("64-byte block");
+    for i in 0..2 * r {
+        for (t, word) in t.iter_mut().zip(&input[i * 16..(i + 1) * 16]) {
+            *t ^= word;
+        }
+        salsa20_8(&mut t);
+        // Even blocks go to the first half of the output, odd ones to the second
+        let target = if i % 2 == 0 { i / 2 } else { r + i / 2 };
+        output[target * 16..(target + 1) * 16].copy_from_slice(&t);
+    }
+}
+
+fn salsa20_8(block: &mut [u32; 16]) {
+    fn quarter(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    }
+    let mut x = *block;
+    for _ in 0..4 {
+        quarter(&mut x, 0, 4, 8, 12);
+        quarter(&mut x, 5, 9, 13, 1);
+        quarter(&mut x, 10, 14, 2, 6);
+        quarter(&mut x, 15, 3, 7, 11);
+        quarter(&mut x, 0, 1, 2, 3);
+        quarter(&mut x, 5, 6, 7, 4);
+        quarter(&mut x, 10, 11, 8, 9);
+        quarter(&mut x, 15, 12, 13, 14);
+    }
+    for (word, mixed) in block.iter_mut().zip(x) {
+        *word = word.wrapping_add(mixed);
+    }
+}
+
+/// Keccak-256 over the second half of the derived key and the ciphertext.
+fn mac(derived: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(&derived[16..32]);
+    hasher.update(ciphertext);
+    hasher.finalize().into()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn check_dklen(dklen: usize) -> Result<(), String> {
+    if !(DERIVED_KEY_LEN..=MAX_DKLEN).contains(&dklen) {
+        return Err(format!(
+            "Keystore dklen must be between {} and {}, not {}",
+            DERIVED_KEY_LEN, MAX_DKLEN, dklen
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses scrypt parameters whose memory or work exceed the bounds above.
+fn check_scrypt_cost(n: u64, r: u32, p: u32) -> Result<(), String> {
+    let (r, p) = (r as u64, p as u64);
+    if n > MAX_SCRYPT_N {
+        return Err(format!("Keystore scrypt n must be at most {}, not {}", MAX_SCRYPT_N, n));
+    }
+    if r * p > MAX_SCRYPT_RP {
+        return Err(format!(
+            "Keystore scrypt r * p must be at most {}, not {} * {}",
+            MAX_SCRYPT_RP, r, p
+        ));
+    }
+    if 128 * r * n > MAX_SCRYPT_MEMORY {
+        return Err(format!(
+            "Keystore scrypt n={} r={} needs more than {} MiB",
+            n,
+            r,
+            MAX_SCRYPT_MEMORY >> 20
+        ));
+    }
+    Ok(())
+}
+
+fn decode_hex(name: &str, value: &str) -> Result<Vec<u8>, String> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|_| format!("Keystore {} is not valid hex", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from the Web3 Secret Storage Definition
+    const PASSWORD: &str = "testpassword";
+    const SECRET_KEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    #[test]
+    fn test_decrypts_reference_vectors() {
+        let pbkdf2 = r#"{
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+                "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf": "pbkdf2",
+                "kdfparams": {
+                    "c": 262144,
+                    "dklen": 32,
+                    "prf": "hmac-sha256",
+                    "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        }"#;
+        let scrypt = r#"{
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+                "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+                "kdf": "scrypt",
+                "kdfparams": {
+                    "dklen": 32,
+                    "n": 262144,
+                    "p": 8,
+                    "r": 1,
+                    "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+                },
+                "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        }"#;
+        for json in [pbkdf2, scrypt] {
+            let keystore = Keystore::from_json(json).unwrap();
+            assert_eq!(keystore.decrypt(PASSWORD).unwrap().secret_key, SECRET_KEY);
+        }
+    }
+
+    #[test]
+    fn test_geth_scrypt_matches_rfc_7914() {
+        // The RFC vectors are within the crate's range, but they check that
+        // the fallback computes the same function
+        let mut output = [0u8; 64];
+        geth_scrypt(b"", b"", 16, 1, 1, &mut output).unwrap();
+        assert_eq!(
+            hex::encode(output),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+        geth_scrypt(b"password", b"NaCl", 1024, 8, 16, &mut output).unwrap();
+        assert_eq!(
+            hex::encode(output),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let keys = SignatureKeys::from_secret_key(SECRET_KEY).unwrap();
+        for kdf in [
+            Kdf::Scrypt {
+                log_n: 10,
+                r: 8,
+                p: 1,
+            },
+            Kdf::Pbkdf2 { iterations: 1024 },
+        ] {
+            let json = Keystore::encrypt(&keys, PASSWORD, kdf).unwrap().to_json();
+            let keystore = Keystore::from_json(&json).unwrap();
+            assert_eq!(
+                keystore.address.as_deref(),
+                Some(keys.address_key.trim_start_matches("0x"))
+            );
+            assert_eq!(keystore.decrypt(PASSWORD).unwrap().secret_key, SECRET_KEY);
+            assert_eq!(
+                keystore.decrypt("wrongpassword").unwrap_err(),
+                "Wrong keystore password, or the keystore is corrupted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_refuses_unbounded_kdf_parameters() {
+        let keystore = |kdf: &str, params: &str| {
+            Keystore::from_json(&format!(
+                r#"{{
+                    "crypto": {{
+                        "cipher": "aes-128-ctr",
+                        "cipherparams": {{ "iv": "83dbcc02d8ccb40e466191a123791e0e" }},
+                        "ciphertext": "00",
+                        "kdf": "{}",
+                        "kdfparams": {{ {}, "salt": "ab0c" }},
+                        "mac": "00"
+                    }},
+                    "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                    "version": 3
+                }}"#,
+                kdf, params
+            ))
+            .unwrap()
+        };
+        for (kdf, params, error) in [
+            ("scrypt", r#""dklen": 32, "n": 2097152, "r": 1, "p": 1"#, "n must be at most"),
+            ("scrypt", r#""dklen": 32, "n": 2, "r": 65536, "p": 2"#, "r * p must be at most"),
+            ("scrypt", r#""dklen": 32, "n": 1048576, "r": 16, "p": 1"#, "needs more than"),
+            ("scrypt", r#""dklen": 4294967296, "n": 2, "r": 1, "p": 1"#, "dklen must be"),
+            ("pbkdf2", r#""dklen": 32, "c": 4294967295, "prf": "hmac-sha256""#, "c must be"),
+            ("pbkdf2", r#""dklen": 32, "c": 0, "prf": "hmac-sha256""#, "c must be"),
+        ] {
+            let err = keystore(kdf, params).decrypt(PASSWORD).unwrap_err();
+            assert!(err.contains(error), "{}: {}", params, err);
+        }
+    }
+}
@@ -1,18 +1,70 @@
 use axum::{routing::get, Router};
+use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Registry;
 use prometheus_client::{encoding::text::encode as prometheus_encode, metrics::gauge::Gauge};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+use crate::hub::clutch_node_client::types::Block;
+use crate::hub::clutch_node_client::NodeApi;
+use crate::hub::health::WORKERS;
+use crate::hub::shutdown::Shutdown;
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, prometheus_client::encoding::EncodeLabelSet)]
 pub struct BlockLabels {
     pub block_hash: String,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, prometheus_client::encoding::EncodeLabelSet)]
+pub struct OperationLabels {
+    pub operation: String,
+    pub outcome: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, prometheus_client::encoding::EncodeLabelSet)]
+pub struct NodeMethodLabels {
+    pub method: String,
+    pub outcome: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, prometheus_client::encoding::EncodeLabelSet)]
+pub struct RateLimitLabels {
+    pub limit: String,
+}
+
+fn latency_histogram() -> Histogram {
+    // 1ms .. ~16s
+    Histogram::new(exponential_buckets(0.001, 2.0, 15))
+}
+
 lazy_static::lazy_static! {
     pub static ref LATEST_BLOCK_INDEX: Gauge = Gauge::default();
     pub static ref LATEST_BLOCK: Family<BlockLabels, Gauge> = Family::default();
 
+    pub static ref GRAPHQL_OPERATIONS: Family<OperationLabels, Counter> = Family::default();
+    pub static ref GRAPHQL_OPERATION_DURATION: Family<OperationLabels, Histogram> =
+        Family::new_with_constructor(latency_histogram);
+
+    pub static ref NODE_RPC_REQUESTS: Family<NodeMethodLabels, Counter> = Family::default();
+    pub static ref NODE_RPC_DURATION: Family<NodeMethodLabels, Histogram> =
+        Family::new_with_constructor(latency_histogram);
+    pub static ref NODE_PENDING_REQUESTS: Gauge = Gauge::default();
+    pub static ref NODE_RECONNECTS: Counter = Counter::default();
+    pub static ref NODE_CONNECTED: Gauge = Gauge::default();
+
+    pub static ref JWT_ISSUED: Counter = Counter::default();
+    pub static ref JWT_ISSUE_FAILURES: Counter = Counter::default();
+    pub static ref JWT_VALIDATION_FAILURES: Counter = Counter::default();
+
+    pub static ref RATE_LIMITED_REQUESTS: Family<RateLimitLabels, Counter> = Family::default();
+
+    pub static ref SEQ_DROPPED_EVENTS: Counter = Counter::default();
+    pub static ref SEQ_FAILED_BATCHES: Counter = Counter::default();
+
     static ref REGISTRY: Arc<Mutex<Registry>> = {
         let mut registry = Registry::default();
         registry.register(
@@ -25,29 +77,167 @@ lazy_static::lazy_static! {
             "Current block of the clutch node",
             LATEST_BLOCK.clone(),
         );
+        registry.register(
+            "graphql_operations",
+            "GraphQL operations executed, by root field and outcome",
+            GRAPHQL_OPERATIONS.clone(),
+        );
+        registry.register(
+            "graphql_operation_duration_seconds",
+            "GraphQL operation latency, by root field and outcome",
+            GRAPHQL_OPERATION_DURATION.clone(),
+        );
+        registry.register(
+            "node_rpc_requests",
+            "JSON-RPC requests sent to the clutch node, by method and outcome",
+            NODE_RPC_REQUESTS.clone(),
+        );
+        registry.register(
+            "node_rpc_duration_seconds",
+            "JSON-RPC round-trip latency to the clutch node, by method and outcome",
+            NODE_RPC_DURATION.clone(),
+        );
+        registry.register(
+            "node_pending_requests",
+            "JSON-RPC requests awaiting a response from the clutch node",
+            NODE_PENDING_REQUESTS.clone(),
+        );
+        registry.register(
+            "node_reconnects",
+            "Times the connection to the clutch node was lost or could not be opened",
+            NODE_RECONNECTS.clone(),
+        );
+        registry.register(
+            "node_connected",
+            "1 while the WebSocket connection to the clutch node is open",
+            NODE_CONNECTED.clone(),
+        );
+        registry.register(
+            "jwt_issued",
+            "JWT tokens issued",
+            JWT_ISSUED.clone(),
+        );
+        registry.register(
+            "jwt_issue_failures",
+            "JWT token requests that were rejected",
+            JWT_ISSUE_FAILURES.clone(),
+        );
+        registry.register(
+            "jwt_validation_failures",
+            "Bearer tokens that failed validation",
+            JWT_VALIDATION_FAILURES.clone(),
+        );
+        registry.register(
+            "rate_limited_requests",
+            "Requests refused with 429, by the limit they exceeded",
+            RATE_LIMITED_REQUESTS.clone(),
+        );
+        registry.register(
+            "seq_dropped_events",
+            "Log events dropped because the Seq buffer was full",
+            SEQ_DROPPED_EVENTS.clone(),
+        );
+        registry.register(
+            "seq_failed_batches",
+            "Log batches discarded after Seq kept rejecting them",
+            SEQ_FAILED_BATCHES.clone(),
+        );
         Arc::new(Mutex::new(registry))
     };
 }
 
-pub fn serve_metrics(addr: &str) {
-    let addr = addr.to_string();
-    tokio::spawn(async move {
-        let app = Router::new()
-            .route("/", get(track_and_respond))
-            .route("/metrics", get(metrics_handler));
+pub fn outcome_label(success: bool) -> String {
+    if success { "ok" } else { "error" }.to_string()
+}
+
+/// Records one JSON-RPC round trip to the node.
+pub fn record_node_rpc(method: &str, success: bool, elapsed: Duration) {
+    let labels = NodeMethodLabels {
+        method: method.to_string(),
+        outcome: outcome_label(success),
+    };
+    NODE_RPC_REQUESTS.get_or_create(&labels).inc();
+    NODE_RPC_DURATION
+        .get_or_create(&labels)
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Records one executed GraphQL operation.
+pub fn record_graphql_operation(operation: &str, success: bool, elapsed: Duration) {
+    let labels = OperationLabels {
+        operation: operation.to_string(),
+        outcome: outcome_label(success),
+    };
+    GRAPHQL_OPERATIONS.get_or_create(&labels).inc();
+    GRAPHQL_OPERATION_DURATION
+        .get_or_create(&labels)
+        .observe(elapsed.as_secs_f64());
+}
 
-        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+/// Publishes the node's latest block, keeping a single `latest_block` series.
+pub fn record_latest_block(block: &Block) {
+    LATEST_BLOCK_INDEX.set(block.index as i64);
+    LATEST_BLOCK.clear();
+    LATEST_BLOCK
+        .get_or_create(&BlockLabels {
+            block_hash: block.hash.clone(),
+        })
+        .set(block.index as i64);
+}
+
+const BLOCK_POLLER: &str = "block_poller";
+
+/// Polls the node for its latest block and keeps the block gauges current
+/// until `shutdown` is triggered.
+pub fn poll_latest_block(node: Arc<dyn NodeApi>, interval: Duration, shutdown: Shutdown) {
+    if interval.is_zero() {
+        return;
+    }
+    // A tick plus a slow node call; anything longer means the poller is stuck
+    WORKERS.register(BLOCK_POLLER, interval * 2 + Duration::from_secs(10), true);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.wait() => return,
+            }
+            WORKERS.heartbeat(BLOCK_POLLER);
+            match node.get_latest_block().await {
+                Ok(block) => record_latest_block(&block),
+                Err(e) => error!("Failed to poll latest block: {}", e),
+            }
+        }
     });
 }
 
+/// Binds `addr` and serves `/metrics` until `shutdown` is triggered.
+pub async fn serve_metrics(addr: &str, shutdown: Shutdown) -> Result<JoinHandle<()>, String> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to bind metrics server to {}: {}", addr, e))?;
+    let app = Router::new()
+        .route("/", get(track_and_respond))
+        .route("/metrics", get(metrics_handler));
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await
+        {
+            error!("Metrics server failed: {}", e);
+        }
+    }))
+}
+
 async fn track_and_respond() -> &'static str {
     "Hello, World!"
 }
 
-async fn metrics_handler() -> String {
+pub async fn metrics_handler() -> String {
     let mut buffer = String::new();
     let registry = REGISTRY.lock().unwrap();
-    prometheus_encode(&mut buffer, &*registry).unwrap();
+    prometheus_encode(&mut buffer, &registry).unwrap();
     buffer
 }
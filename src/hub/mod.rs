@@ -1,9 +1,21 @@
+pub mod address;
 pub mod auth;
+pub mod cli;
 pub mod clutch_node_client;
 pub mod configuration;
 pub mod graphql;
+pub mod hd_keys;
+pub mod health;
+pub mod keystore;
 pub mod metric;
+pub mod rate_limit;
+pub mod redact;
+pub mod reload;
+pub mod secrets;
 pub mod seq;
 pub mod server;
+pub mod shutdown;
 pub mod signature_keys;
-pub mod tracing;
\ No newline at end of file
+pub mod telemetry;
+pub mod tracing;
+pub mod typed_data;
\ No newline at end of file
@@ -0,0 +1,610 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{web, Error, HttpResponse, ResponseError};
+use async_graphql::http::{receive_body, MultipartOptions};
+use async_graphql::parser::types::{DocumentOperations, ExecutableDocument, Selection, SelectionSet};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{warn, Instrument};
+
+use crate::hub::address::Address;
+use crate::hub::configuration::{AppConfig, LiveConfig, RateLimit};
+use crate::hub::graphql::handler::{authenticate, request_span};
+use crate::hub::metric::{RateLimitLabels, RATE_LIMITED_REQUESTS};
+
+/// Name of `limits.rate_limit`, the limit on all requests from one client IP,
+/// in `Retry-After` responses and metrics.
+pub const CLIENT_LIMIT: &str = "client";
+
+/// How often buckets that have filled up again are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who a request is counted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    Ip(IpAddr),
+    Address(Address),
+}
+
+impl fmt::Display for ClientKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientKey::Ip(ip) => write!(f, "{}", ip),
+            ClientKey::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+/// A request over one of the limits.
+#[derive(Debug, Error)]
+pub enum RateLimited {
+    /// The bucket is short now and refills in `retry_after`.
+    #[error(
+        "Rate limit for {limit} exceeded, retry in {} seconds",
+        retry_after_secs(*retry_after)
+    )]
+    Exhausted { limit: String, retry_after: Duration },
+    /// The request costs more than the bucket ever holds, so waiting would
+    /// not help.
+    #[error("Request uses {limit} {cost} times, more than its limit of {burst} at once")]
+    OverBurst { limit: String, cost: u32, burst: u32 },
+}
+
+impl RateLimited {
+    /// Name of the limit the request ran into.
+    pub fn limit(&self) -> &str {
+        match self {
+            RateLimited::Exhausted { limit, .. } | RateLimited::OverBurst { limit, .. } => limit,
+        }
+    }
+}
+
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+impl ResponseError for RateLimited {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RateLimited::Exhausted { .. } => StatusCode::TOO_MANY_REQUESTS,
+            RateLimited::OverBurst { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// A GraphQL-shaped error body, so clients report it like any other error.
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let RateLimited::Exhausted { retry_after, .. } = self {
+            response.insert_header((RETRY_AFTER, retry_after_secs(*retry_after).to_string()));
+        }
+        response.json(serde_json::json!({ "errors": [{ "message": self.to_string() }] }))
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    limit: RateLimit,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_rate(limit)).min(limit.burst as f64);
+        self.updated = now;
+        self.limit = limit;
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * refill_rate(self.limit) >= self.limit.burst as f64
+    }
+}
+
+/// Tokens added per second.
+fn refill_rate(limit: RateLimit) -> f64 {
+    limit.per_minute as f64 / 60.0
+}
+
+/// One bucket per limit and client. Limits are passed in on every call, so
+/// a reloaded configuration applies to existing buckets right away.
+#[derive(Default)]
+pub struct Buckets {
+    state: Mutex<BucketState>,
+}
+
+#[derive(Default)]
+struct BucketState {
+    buckets: HashMap<(String, ClientKey), Bucket>,
+    swept: Option<Instant>,
+}
+
+/// `cost` requests against `limit` for `client`.
+pub struct Charge {
+    pub limit_name: String,
+    pub limit: RateLimit,
+    pub client: ClientKey,
+    pub cost: u32,
+}
+
+impl Buckets {
+    /// Takes every charge, or none of them if any bucket is short. A charge
+    /// larger than its burst is refused outright.
+    pub fn acquire(&self, charges: &[Charge], now: Instant) -> Result<(), RateLimited> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        if state.swept.is_none_or(|swept| now.duration_since(swept) >= SWEEP_INTERVAL) {
+            // A full bucket behaves exactly like a missing one
+            state.buckets.retain(|_, bucket| !bucket.is_full(now));
+            state.swept = Some(now);
+        }
+
+        let charges: Vec<_> = charges.iter().filter(|c| c.limit.is_enabled()).collect();
+        if let Some(charge) = charges.iter().find(|c| c.cost > c.limit.burst) {
+            return Err(RateLimited::OverBurst {
+                limit: charge.limit_name.clone(),
+                cost: charge.cost,
+                burst: charge.limit.burst,
+            });
+        }
+        let mut longest_wait: Option<(String, Duration)> = None;
+        for charge in &charges {
+            let bucket = state
+                .buckets
+                .entry((charge.limit_name.clone(), charge.client))
+                .or_insert_with(|| Bucket {
+                    tokens: charge.limit.burst as f64,
+                    updated: now,
+                    limit: charge.limit,
+                });
+            bucket.refill(charge.limit, now);
+            let missing = charge.cost as f64 - bucket.tokens;
+            if missing > 0.0 {
+                let retry_after = Duration::from_secs_f64(missing / refill_rate(charge.limit));
+                if longest_wait.as_ref().is_none_or(|(_, wait)| *wait < retry_after) {
+                    longest_wait = Some((charge.limit_name.clone(), retry_after));
+                }
+            }
+        }
+        if let Some((limit, retry_after)) = longest_wait {
+            return Err(RateLimited::Exhausted { limit, retry_after });
+        }
+        for charge in charges {
+            if let Some(bucket) = state.buckets.get_mut(&(charge.limit_name.clone(), charge.client))
+            {
+                bucket.tokens -= charge.cost as f64;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A proxy address or CIDR range from `limits.trusted_proxies`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl TrustedProxy {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (address, prefix_len) = match text.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (text, None),
+        };
+        let network = address
+            .trim()
+            .parse::<IpAddr>()
+            .map_err(|_| format!("{:?} is not an IP address or CIDR range", text))?
+            .to_canonical();
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("{:?} has an invalid prefix length", text))?,
+            None => max_len,
+        };
+        Ok(TrustedProxy {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// The client a request came from. Behind trusted proxies that is the last
+/// address in `X-Forwarded-For` not itself a trusted proxy, as every proxy
+/// appends the address it saw; anything further left was sent by the client
+/// and could be forged. `X-Real-IP` is used when there is no
+/// `X-Forwarded-For`.
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[TrustedProxy]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|proxy| proxy.contains(ip));
+    let mut client = peer.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ip| ip.to_canonical());
+    if !is_trusted(client) {
+        return client;
+    }
+
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    if forwarded.is_empty() {
+        return headers
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok())
+            .map_or(client, |ip| ip.to_canonical());
+    }
+    for hop in forwarded.iter().rev() {
+        // An unreadable entry ends the chain; the last proxy is all we know
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        client = ip.to_canonical();
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    client
+}
+
+/// How often each root field is selected by the operation a GraphQL request
+/// body will run, counting aliases and fields inside fragments. The body is
+/// read like the handler reads it, as JSON or multipart depending on
+/// `content_type`. Bodies that do not parse give nothing; the handler
+/// rejects them anyway.
+pub async fn root_field_counts(content_type: Option<&str>, body: &[u8]) -> HashMap<String, u32> {
+    let Ok(request) = receive_body(content_type, body, MultipartOptions::default()).await else {
+        return HashMap::new();
+    };
+    let Ok(document) = async_graphql::parser::parse_query(&request.query) else {
+        return HashMap::new();
+    };
+    document_root_field_counts(&document, request.operation_name.as_deref())
+}
+
+/// How often each root field is selected by the operation of `document`
+/// named `operation_name`, or by its only operation.
+pub fn document_root_field_counts(
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+) -> HashMap<String, u32> {
+    let mut counts = HashMap::new();
+    let operation = match &document.operations {
+        DocumentOperations::Single(operation) => Some(operation),
+        DocumentOperations::Multiple(operations) => match operation_name {
+            Some(name) => operations.get(name),
+            None if operations.len() == 1 => operations.values().next(),
+            None => None,
+        },
+    };
+    if let Some(operation) = operation {
+        count_fields(
+            document,
+            &operation.node.selection_set.node,
+            &mut HashSet::new(),
+            &mut counts,
+        );
+    }
+    counts
+}
+
+fn count_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    spread: &mut HashSet<&'a str>,
+    counts: &mut HashMap<String, u32>,
+) {
+    for selection in &selection_set.items {
+        match &selection.node {
+            Selection::Field(field) => {
+                *counts.entry(field.node.name.node.to_string()).or_default() += 1;
+            }
+            Selection::InlineFragment(fragment) => {
+                count_fields(document, &fragment.node.selection_set.node, spread, counts);
+            }
+            Selection::FragmentSpread(fragment) => {
+                let name = fragment.node.fragment_name.node.as_str();
+                // Each fragment once, so cycles cannot recurse forever
+                if !spread.insert(name) {
+                    continue;
+                }
+                if let Some(definition) = document.fragments.get(name) {
+                    count_fields(document, &definition.node.selection_set.node, spread, counts);
+                }
+            }
+        }
+    }
+}
+
+/// Middleware applying `limits.rate_limit` per client IP and
+/// `limits.operation_rate_limits` per root field, answering `429 Too Many
+/// Requests` with `Retry-After` when a bucket is empty, and `400 Bad Request`
+/// when a request costs more than a bucket holds. Operation limits are
+/// counted per address for requests with a valid token. Clones share their
+/// buckets, so create it once and clone it into each worker.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: LiveConfig,
+    buckets: Arc<Buckets>,
+    trusted_proxies: Arc<Mutex<Option<ParsedProxies>>>,
+}
+
+/// `limits.trusted_proxies` of one configuration, parsed.
+struct ParsedProxies {
+    config: Arc<AppConfig>,
+    proxies: Arc<[TrustedProxy]>,
+}
+
+impl RateLimiter {
+    pub fn new(config: LiveConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Arc::default(),
+            trusted_proxies: Arc::default(),
+        }
+    }
+
+    /// The trusted proxies of `config`, parsed again only after a reload.
+    fn trusted_proxies(&self, config: &Arc<AppConfig>) -> Arc<[TrustedProxy]> {
+        let mut parsed = self.trusted_proxies.lock().unwrap();
+        if let Some(parsed) = parsed.as_ref().filter(|p| Arc::ptr_eq(&p.config, config)) {
+            return parsed.proxies.clone();
+        }
+        // Validation refuses bad entries before a configuration goes live
+        let proxies: Arc<[TrustedProxy]> = config
+            .limits
+            .trusted_proxies
+            .iter()
+            .filter_map(|proxy| match proxy.parse() {
+                Ok(proxy) => Some(proxy),
+                Err(e) => {
+                    warn!("Ignoring limits.trusted_proxies entry: {}", e);
+                    None
+                }
+            })
+            .collect();
+        *parsed = Some(ParsedProxies {
+            config: config.clone(),
+            proxies: proxies.clone(),
+        });
+        proxies
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+        let span = request_span(req.request()).span;
+        let checked = async move {
+            let config = limiter.config.current();
+            let limits = &config.limits;
+            let trusted = limiter.trusted_proxies(&config);
+            let ip = client_ip(req.peer_addr().map(|addr| addr.ip()), req.headers(), &trusted);
+
+            // The operation is in the body, which is put back for the handler
+            let body = match req.extract::<web::Bytes>().await {
+                Ok(body) => body,
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            };
+            req.set_payload(body.clone().into());
+            let client = match authenticate(req.request(), &config) {
+                Some(user) => ClientKey::Address(user.address),
+                None => ClientKey::Ip(ip),
+            };
+
+            let mut charges = vec![Charge {
+                limit_name: CLIENT_LIMIT.to_string(),
+                limit: limits.rate_limit,
+                client: ClientKey::Ip(ip),
+                cost: 1,
+            }];
+            let content_type = req
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok());
+            for (field, count) in root_field_counts(content_type, &body).await {
+                if let Some(limit) = limits.operation_rate_limit(&field) {
+                    charges.push(Charge {
+                        limit_name: field,
+                        limit,
+                        client,
+                        cost: count,
+                    });
+                }
+            }
+            if let Err(limited) = limiter.buckets.acquire(&charges, Instant::now()) {
+                RATE_LIMITED_REQUESTS
+                    .get_or_create(&RateLimitLabels {
+                        limit: limited.limit().to_string(),
+                    })
+                    .inc();
+                warn!(client = %client, ip = %ip, "{}", limited);
+                let response = limited.error_response();
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            service.call(req).await.map(ServiceResponse::map_into_left_body)
+        };
+        // Logs from authentication and limiting belong to the request's span
+        Box::pin(checked.instrument(span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(HeaderName::from_static(name), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_trusted_proxy_ranges() {
+        let private: TrustedProxy = "172.16.0.0/12".parse().unwrap();
+        assert!(private.contains(ip("172.18.0.5")));
+        assert!(private.contains(ip("::ffff:172.18.0.5")));
+        assert!(!private.contains(ip("172.32.0.1")));
+        let loopback: TrustedProxy = "::1".parse().unwrap();
+        assert!(loopback.contains(ip("::1")));
+        assert!(!loopback.contains(ip("127.0.0.1")));
+        assert!("0.0.0.0/0".parse::<TrustedProxy>().unwrap().contains(ip("8.8.8.8")));
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("nginx".parse::<TrustedProxy>().is_err());
+    }
+
+    #[test]
+    fn test_client_ip_only_trusts_configured_proxies() {
+        let trusted = ["10.0.0.0/8".parse().unwrap()];
+        let forwarded = headers(&[("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.7")]);
+
+        // Sent straight to the hub, the header is the client's own claim
+        assert_eq!(client_ip(Some(ip("5.5.5.5")), &forwarded, &trusted), ip("5.5.5.5"));
+        // Through proxies, the first hop they did not add is the client
+        assert_eq!(client_ip(Some(ip("10.0.0.2")), &forwarded, &trusted), ip("1.2.3.4"));
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &headers(&[("x-real-ip", "1.2.3.4")]), &trusted),
+            ip("1.2.3.4")
+        );
+        assert_eq!(client_ip(Some(ip("10.0.0.2")), &HeaderMap::new(), &trusted), ip("10.0.0.2"));
+        assert_eq!(client_ip(Some(ip("10.0.0.2")), &forwarded, &[]), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_buckets_refill_and_charge_all_or_nothing() {
+        let buckets = Buckets::default();
+        let client = ClientKey::Ip(ip("1.2.3.4"));
+        let charge = |name: &str, limit, cost| Charge {
+            limit_name: name.to_string(),
+            limit,
+            client,
+            cost,
+        };
+        let start = Instant::now();
+        let tokens = RateLimit::new(60, 2);
+        let client_limit = RateLimit::new(60, 100);
+
+        for _ in 0..2 {
+            let charges = [charge("client", client_limit, 1), charge("generateToken", tokens, 1)];
+            assert!(buckets.acquire(&charges, start).is_ok());
+        }
+        let charges = [charge("client", client_limit, 1), charge("generateToken", tokens, 1)];
+        let limited = buckets.acquire(&charges, start).unwrap_err();
+        let RateLimited::Exhausted { limit, retry_after } = limited else {
+            panic!("expected an exhausted bucket, got {:?}", limited);
+        };
+        assert_eq!(limit, "generateToken");
+        assert_eq!(retry_after_secs(retry_after), 1);
+
+        // The refused request cost nothing, and a token is back after a second
+        let later = start + Duration::from_secs(1);
+        assert!(buckets.acquire(&charges, later).is_ok());
+        let client_tokens = buckets.state.lock().unwrap().buckets[&("client".to_string(), client)]
+            .tokens;
+        assert_eq!(client_tokens.round(), 98.0);
+
+        let off = [charge("generateToken", RateLimit::new(0, 0), 5)];
+        assert!(buckets.acquire(&off, later).is_ok());
+
+        // Three calls never fit a bucket of two, however long the client waits
+        let much_later = later + Duration::from_secs(3600);
+        let over = [charge("client", client_limit, 1), charge("generateToken", tokens, 3)];
+        assert!(matches!(
+            buckets.acquire(&over, much_later),
+            Err(RateLimited::OverBurst { cost: 3, burst: 2, .. })
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_root_fields_include_aliases_and_fragments() {
+        let body = serde_json::json!({
+            "query": "mutation A { a: generateToken(publicKey: \"x\") { token } \
+                      b: generateToken(publicKey: \"y\") { token } ...F ... on Mutation { \
+                      sendRawTransaction(rawTransaction: \"0x\") } } \
+                      fragment F on Mutation { generateToken(publicKey: \"z\") { token } ...F } \
+                      query B { rideRequest { pickupLocation } }",
+            "operationName": "A",
+        });
+        let json = Some("application/json");
+        let counts = root_field_counts(json, body.to_string().as_bytes()).await;
+        assert_eq!(counts["generateToken"], 3);
+        assert_eq!(counts["sendRawTransaction"], 1);
+        assert!(!counts.contains_key("rideRequest"));
+        assert!(root_field_counts(json, b"not json").await.is_empty());
+    }
+}
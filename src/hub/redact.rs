@@ -0,0 +1,181 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Placeholder written in place of redacted values.
+pub const REDACTED: &str = "[redacted]";
+
+/// A configuration value that must never end up in logs.
+///
+/// `Debug` prints a placeholder; call `expose` where the value is actually used.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+/// Masks sensitive fields and coarsens coordinates before they are logged.
+#[derive(Clone, Debug)]
+pub struct Redactor {
+    fields: HashSet<String>,
+    coordinate_decimals: u32,
+}
+
+impl Redactor {
+    /// `fields` are matched case-insensitively against field and JSON key names.
+    pub fn new(fields: &[String], coordinate_decimals: u32) -> Self {
+        Redactor {
+            fields: fields.iter().map(|name| name.to_ascii_lowercase()).collect(),
+            coordinate_decimals,
+        }
+    }
+
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        self.fields.contains(&name.to_ascii_lowercase())
+    }
+
+    /// Whether `name` looks like a latitude or longitude, e.g. `pickup_latitude`.
+    pub fn is_coordinate(name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        name.contains("latitude") || name.contains("longitude") || name == "lat" || name == "lng"
+    }
+
+    /// Rounds a coordinate to the configured number of decimals.
+    pub fn coarsen(&self, coordinate: f64) -> f64 {
+        let scale = 10f64.powi(self.coordinate_decimals as i32);
+        (coordinate * scale).round() / scale
+    }
+
+    /// Applies the rules to a single named value.
+    pub fn redact_field(&self, name: &str, value: Value) -> Value {
+        if self.is_sensitive(name) {
+            return Value::String(REDACTED.to_string());
+        }
+        match value {
+            Value::Number(number) if Self::is_coordinate(name) => number
+                .as_f64()
+                .map(|coordinate| serde_json::json!(self.coarsen(coordinate)))
+                .unwrap_or(Value::Number(number)),
+            Value::Object(_) | Value::Array(_) => self.redact_json(value),
+            other => other,
+        }
+    }
+
+    /// Applies the rules to every key of a JSON document.
+    pub fn redact_json(&self, value: Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| {
+                        let value = self.redact_field(&key, value);
+                        (key, value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|item| self.redact_json(item)).collect())
+            }
+            other => other,
+        }
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Redactor::new(&default_redacted_fields(), 2)
+    }
+}
+
+/// Field names masked unless configured otherwise.
+pub fn default_redacted_fields() -> Vec<String> {
+    [
+        "authorization",
+        "jwt_secret",
+        "seq_api_key",
+        "password",
+        "secret_key",
+        "private_key",
+        "mnemonic",
+        "token",
+        "raw_transaction",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect()
+}
+
+lazy_static::lazy_static! {
+    static ref REDACTOR: RwLock<Arc<Redactor>> = RwLock::new(Arc::new(Redactor::default()));
+}
+
+/// Replaces the process-wide redaction rules used by the log layers.
+pub fn set_redactor(redactor: Redactor) {
+    *REDACTOR.write().unwrap() = Arc::new(redactor);
+}
+
+/// Returns the process-wide redaction rules.
+pub fn redactor() -> Arc<Redactor> {
+    REDACTOR.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_secret_debug_is_masked() {
+        let secret = Secret::new("hunter2");
+        assert_eq!(format!("{:?}", secret), REDACTED);
+        assert_eq!(format!("{:?}", Some(secret.clone())), "Some([redacted])");
+        assert_eq!(secret.expose(), "hunter2");
+    }
+
+    #[test]
+    fn test_json_is_redacted_recursively() {
+        let redactor = Redactor::new(&["Token".to_string()], 2);
+        let redacted = redactor.redact_json(json!({
+            "token": "abc",
+            "pickup_location": { "latitude": 52.520008, "longitude": 13.404954 },
+            "items": [{ "TOKEN": "def", "fare": 100 }],
+        }));
+
+        assert_eq!(redacted["token"], REDACTED);
+        assert_eq!(redacted["pickup_location"]["latitude"], 52.52);
+        assert_eq!(redacted["pickup_location"]["longitude"], 13.4);
+        assert_eq!(redacted["items"][0]["TOKEN"], REDACTED);
+        assert_eq!(redacted["items"][0]["fare"], 100);
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::hub::configuration::{AppConfig, LiveConfig, DEFAULT_ENV};
+use crate::hub::redact::{set_redactor, Redactor};
+use crate::hub::shutdown::Shutdown;
+use crate::hub::tracing::LogFilterHandle;
+
+/// Re-reads the configuration files and applies the settings that can change
+/// while the hub runs: log level and redaction, CORS origins and token lifetime.
+///
+/// A reload that fails validation or touches a startup-only setting is
+/// rejected as a whole and the running configuration stays in place.
+pub struct ConfigReloader {
+    dir: String,
+    env: String,
+    live: LiveConfig,
+    log_filter: Option<LogFilterHandle>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        dir: &str,
+        env: &str,
+        live: LiveConfig,
+        log_filter: Option<LogFilterHandle>,
+    ) -> Self {
+        ConfigReloader {
+            dir: dir.to_string(),
+            env: env.to_string(),
+            live,
+            log_filter,
+        }
+    }
+
+    /// Loads, validates and applies the configuration on disk. The outcome is
+    /// kept in `LiveConfig::reload_errors` for the readiness probe.
+    pub fn reload(&self) -> Result<(), Vec<String>> {
+        let applied = self.apply();
+        self.live
+            .set_reload_errors(applied.as_ref().err().cloned().unwrap_or_default());
+        applied
+    }
+
+    fn apply(&self) -> Result<(), Vec<String>> {
+        let next = AppConfig::load_from(&self.dir, &self.env).map_err(|e| vec![e.to_string()])?;
+        next.validate()?;
+
+        let current = self.live.current();
+        let rejected = current.restart_required_changes(&next);
+        if !rejected.is_empty() {
+            return Err(rejected
+                .into_iter()
+                .map(|name| format!("{} cannot change without a restart", name))
+                .collect());
+        }
+
+        if next.log_level != current.log_level {
+            if let Some(log_filter) = &self.log_filter {
+                let filter = EnvFilter::try_new(&next.log_level).map_err(|e| vec![e.to_string()])?;
+                log_filter
+                    .reload(filter)
+                    .map_err(|e| vec![format!("Failed to apply log_level: {}", e)])?;
+            }
+        }
+        if next.log_redact_fields != current.log_redact_fields
+            || next.log_coordinate_decimals != current.log_coordinate_decimals
+        {
+            set_redactor(Redactor::new(
+                &next.log_redact_fields,
+                next.log_coordinate_decimals,
+            ));
+        }
+        self.live.replace(next);
+        Ok(())
+    }
+
+    /// Reloads on SIGHUP and whenever one of the files changes, checking
+    /// every `poll_interval` (`Duration::ZERO` watches for SIGHUP only).
+    pub fn watch(self, poll_interval: Duration, shutdown: Shutdown) -> JoinHandle<()> {
+        let hangup = Arc::new(Notify::new());
+        forward_hangup(hangup.clone());
+
+        tokio::spawn(async move {
+            let mut seen = self.file_versions();
+            let polling = !poll_interval.is_zero();
+            let mut ticker = tokio::time::interval(poll_interval.max(Duration::from_millis(1)));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = shutdown.wait() => return,
+                    _ = hangup.notified() => info!("SIGHUP received, reloading configuration"),
+                    _ = ticker.tick(), if polling => {
+                        let versions = self.file_versions();
+                        if versions == seen {
+                            continue;
+                        }
+                        info!("Configuration files changed, reloading");
+                    }
+                }
+                // Remember what was attempted so a rejected edit is not retried every tick
+                seen = self.file_versions();
+                match self.reload() {
+                    Ok(()) => info!("Reloaded configuration for env {:?}", self.env),
+                    Err(errors) => {
+                        for e in &errors {
+                            warn!("Configuration reload rejected: {}", e);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from(&self.dir).join("default.toml")];
+        if self.env != DEFAULT_ENV {
+            files.push(PathBuf::from(&self.dir).join(format!("{}.toml", self.env)));
+        }
+        files
+    }
+
+    fn file_versions(&self) -> Vec<Option<(SystemTime, u64)>> {
+        self.files()
+            .iter()
+            .map(|file| {
+                let metadata = std::fs::metadata(file).ok()?;
+                Some((metadata.modified().ok()?, metadata.len()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+fn forward_hangup(hangup: Arc<Notify>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::hangup()) {
+        Ok(mut signals) => {
+            tokio::spawn(async move {
+                while signals.recv().await.is_some() {
+                    hangup.notify_one();
+                }
+            });
+        }
+        Err(e) => error!("Failed to listen for SIGHUP: {}", e),
+    }
+}
+
+#[cfg(not(unix))]
+fn forward_hangup(_hangup: Arc<Notify>) {}
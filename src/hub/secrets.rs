@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use crate::hub::redact::Secret;
+
+/// Looks up secret values that the configuration only refers to.
+pub trait SecretProvider {
+    /// Short name used in error messages ("file", "env", ...).
+    fn kind(&self) -> &'static str;
+
+    /// Returns the secret behind `reference`, e.g. a path or variable name.
+    fn fetch(&self, reference: &str) -> Result<Secret, String>;
+}
+
+/// Reads secrets from files such as Docker or Kubernetes secret mounts.
+///
+/// A single trailing newline is dropped, since most tools write one.
+pub struct FileSecretProvider;
+
+impl SecretProvider for FileSecretProvider {
+    fn kind(&self) -> &'static str {
+        "file"
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Secret, String> {
+        let contents = std::fs::read_to_string(Path::new(reference))
+            .map_err(|e| format!("cannot read {:?}: {}", reference, e))?;
+        let value = contents
+            .strip_suffix('\n')
+            .map(|value| value.strip_suffix('\r').unwrap_or(value))
+            .unwrap_or(&contents);
+        if value.is_empty() {
+            return Err(format!("{:?} is empty", reference));
+        }
+        Ok(Secret::new(value))
+    }
+}
+
+/// Reads secrets from environment variables named in the configuration.
+pub struct EnvSecretProvider;
+
+impl SecretProvider for EnvSecretProvider {
+    fn kind(&self) -> &'static str {
+        "env"
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Secret, String> {
+        match std::env::var(reference) {
+            Ok(value) if !value.is_empty() => Ok(Secret::new(value)),
+            Ok(_) => Err(format!("environment variable {} is empty", reference)),
+            Err(_) => Err(format!("environment variable {} is not set", reference)),
+        }
+    }
+}
+
+/// Resolves one secret setting. `name` is its config key, and `references`
+/// pairs each provider with the reference configured for it (`name_file`,
+/// `name_env`, ...). Returns `None` when no reference is set; setting more
+/// than one is an error.
+pub fn resolve_secret(
+    name: &str,
+    references: &[(&dyn SecretProvider, &Option<String>)],
+) -> Result<Option<Secret>, String> {
+    let set: Vec<_> = references
+        .iter()
+        .filter_map(|(provider, reference)| reference.as_ref().map(|r| (*provider, r)))
+        .collect();
+    match set.as_slice() {
+        [] => Ok(None),
+        [(provider, reference)] => provider
+            .fetch(reference)
+            .map(Some)
+            .map_err(|e| format!("{}_{}: {}", name, provider.kind(), e)),
+        _ => Err(format!(
+            "{}: set only one of {}",
+            name,
+            set.iter()
+                .map(|(provider, _)| format!("{}_{}", name, provider.kind()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_provider_drops_one_trailing_newline() {
+        let path = std::env::temp_dir().join(format!("clutch-secret-{}", std::process::id()));
+        std::fs::write(&path, "s3cret \r\n").unwrap();
+        let secret = FileSecretProvider.fetch(path.to_str().unwrap()).unwrap();
+        assert_eq!(secret.expose(), "s3cret ");
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(FileSecretProvider.fetch(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_only_one_reference_may_be_set() {
+        let file = Some("/run/secrets/jwt".to_string());
+        let var = Some("JWT_SECRET".to_string());
+        let error = resolve_secret(
+            "auth.jwt_secret",
+            &[(&FileSecretProvider, &file), (&EnvSecretProvider, &var)],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "auth.jwt_secret: set only one of auth.jwt_secret_file, auth.jwt_secret_env"
+        );
+        assert_eq!(resolve_secret("auth.jwt_secret", &[(&FileSecretProvider, &None)]), Ok(None));
+    }
+}
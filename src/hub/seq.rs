@@ -1,12 +1,46 @@
 use chrono::Utc;
 use reqwest::Client;
 use serde_json::json;
-use std::collections::HashMap;
 use std::error::Error;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{Event, Subscriber};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{timeout, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
 use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::hub::health::WORKERS;
+use crate::hub::metric::{SEQ_DROPPED_EVENTS, SEQ_FAILED_BATCHES};
+use crate::hub::redact::{redactor, Redactor, Secret};
+
+/// Name of the background thread that ships batches to Seq. Events emitted
+/// on it (e.g. by reqwest) are ignored so shipping never logs to itself.
+const SHIPPER_THREAD: &str = "seq-shipper";
+
+/// Name the shipper reports its heartbeat under.
+const SEQ_WORKER: &str = "seq_shipper";
+
+#[derive(Clone, Debug)]
+pub struct SeqOptions {
+    pub seq_url: String,
+    pub api_key: Secret,
+    /// Events buffered in memory before new ones are dropped.
+    pub buffer_capacity: usize,
+    /// Events per CLEF payload.
+    pub batch_size: usize,
+    /// Maximum time an event waits before its batch is posted.
+    pub flush_interval: Duration,
+    /// Retries for a failed batch before it is discarded.
+    pub max_retries: u32,
+    /// Longest wait for a connection to Seq.
+    pub connect_timeout: Duration,
+    /// Longest wait for Seq to answer one post, connecting included.
+    pub request_timeout: Duration,
+}
 
 pub struct SeqLogger {
     seq_url: String,
@@ -15,40 +49,32 @@ pub struct SeqLogger {
 }
 
 impl SeqLogger {
-    pub fn new(seq_url: &str, api_key: &str) -> Self {
-        SeqLogger {
-            seq_url: seq_url.to_string(),
-            api_key: api_key.to_string(),
-            client: Client::new(),
-        }
+    /// A logger whose posts fail after the timeouts in `options` rather
+    /// than waiting on an unresponsive Seq.
+    pub fn new(options: &SeqOptions) -> reqwest::Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .timeout(options.request_timeout)
+            .build()?;
+        Ok(SeqLogger {
+            seq_url: options.seq_url.clone(),
+            api_key: options.api_key.expose().to_string(),
+            client,
+        })
     }
 
-    pub async fn log_to_seq(
-        &self,
-        message: &str,
-        level: &str,
-        fields: &serde_json::Value,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut event = json!({
-            "@t": Utc::now().to_rfc3339(),  // Timestamp
-            "@mt": message,   // Message template
-            "@l": level,      // Log level
-        });
-
-        if let Some(fields_map) = fields.as_object() {
-            for (key, value) in fields_map {
-                event[key] = value.clone();
-            }
-        }
-
+    /// Posts a batch of CLEF events as one newline-delimited payload.
+    pub async fn log_batch_to_seq(&self, events: &[serde_json::Value]) -> Result<(), Box<dyn Error>> {
         let seq_address = format!("{}/ingest/clef", self.seq_url);
-        let payload = format!("{}\n", event.to_string());
+        let payload: String = events.iter().map(|event| format!("{}\n", event)).collect();
         let mut request = self
             .client
             .post(&seq_address)
             .header("Content-Type", "application/vnd.serilog.clef");
 
-        request = request.header("X-Seq-ApiKey", self.api_key.to_string());
+        if !self.api_key.is_empty() {
+            request = request.header("X-Seq-ApiKey", self.api_key.to_string());
+        }
 
         let response = request.body(payload).send().await?;
 
@@ -59,52 +85,408 @@ impl SeqLogger {
             Err(format!("Failed to send log: {}", error_message).into())
         }
     }
+
+    async fn ship_with_retry(&self, events: &[serde_json::Value], max_retries: u32) {
+        let mut backoff = Duration::from_millis(200);
+        for attempt in 0..=max_retries {
+            match self.log_batch_to_seq(events).await {
+                Ok(()) => return,
+                Err(err) if attempt < max_retries => {
+                    eprintln!(
+                        "[SeqLayer] failed to send {} events to Seq (attempt {}): {}",
+                        events.len(),
+                        attempt + 1,
+                        err
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(5));
+                }
+                Err(err) => {
+                    // Avoid tracing here to prevent recursive logging loops if Seq is down
+                    eprintln!(
+                        "[SeqLayer] dropping {} events after {} attempts: {}",
+                        events.len(),
+                        attempt + 1,
+                        err
+                    );
+                    SEQ_FAILED_BATCHES.inc();
+                }
+            }
+        }
+    }
+}
+
+enum SeqMessage {
+    Event(serde_json::Value),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle used to enqueue events for the shipper and to flush it.
+#[derive(Clone)]
+pub struct SeqHandle {
+    sender: mpsc::Sender<SeqMessage>,
+}
+
+impl SeqHandle {
+    /// Starts the shipper thread and returns a handle to it.
+    pub fn spawn(options: SeqOptions) -> std::io::Result<Self> {
+        let (sender, receiver) = mpsc::channel(options.buffer_capacity.max(1));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let logger = SeqLogger::new(&options).map_err(std::io::Error::other)?;
+
+        // Posting a batch may take several retries; Seq being slow must not
+        // make the hub unready, so the shipper is not critical
+        let max_silence = options.flush_interval
+            + (options.request_timeout + Duration::from_secs(5)) * (options.max_retries + 1)
+            + Duration::from_secs(30);
+        WORKERS.register(SEQ_WORKER, max_silence, false);
+
+        thread::Builder::new()
+            .name(SHIPPER_THREAD.to_string())
+            .spawn(move || runtime.block_on(run_shipper(logger, receiver, options)))?;
+
+        Ok(SeqHandle { sender })
+    }
+
+    /// Queues an event without blocking; drops it if the buffer is full.
+    pub fn enqueue(&self, event: serde_json::Value) {
+        if self.sender.try_send(SeqMessage::Event(event)).is_err() {
+            SEQ_DROPPED_EVENTS.inc();
+        }
+    }
+
+    /// Posts everything queued so far, waiting at most `deadline`.
+    pub async fn flush(&self, deadline: Duration) -> bool {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        let flushed = async {
+            self.sender.send(SeqMessage::Flush(ack_tx)).await.ok()?;
+            ack_rx.await.ok()
+        };
+        matches!(timeout(deadline, flushed).await, Ok(Some(())))
+    }
+}
+
+async fn run_shipper(
+    logger: SeqLogger,
+    mut receiver: mpsc::Receiver<SeqMessage>,
+    options: SeqOptions,
+) {
+    let batch_size = options.batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+        let message = match deadline {
+            Some(at) => match tokio::time::timeout_at(at, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    // Flush interval elapsed
+                    logger.ship_with_retry(&batch, options.max_retries).await;
+                    batch.clear();
+                    deadline = None;
+                    continue;
+                }
+            },
+            None => match timeout(options.flush_interval, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    // Idle; wake up anyway so the heartbeat stays fresh
+                    WORKERS.heartbeat(SEQ_WORKER);
+                    continue;
+                }
+            },
+        };
+        WORKERS.heartbeat(SEQ_WORKER);
+
+        match message {
+            Some(SeqMessage::Event(event)) => {
+                batch.push(event);
+                if batch.len() >= batch_size {
+                    logger.ship_with_retry(&batch, options.max_retries).await;
+                    batch.clear();
+                    deadline = None;
+                } else if deadline.is_none() {
+                    deadline = Some(Instant::now() + options.flush_interval);
+                }
+            }
+            Some(SeqMessage::Flush(ack)) => {
+                if !batch.is_empty() {
+                    logger.ship_with_retry(&batch, options.max_retries).await;
+                    batch.clear();
+                }
+                deadline = None;
+                let _ = ack.send(());
+            }
+            None => {
+                if !batch.is_empty() {
+                    logger.ship_with_retry(&batch, options.max_retries).await;
+                }
+                return;
+            }
+        }
+    }
 }
 
 pub struct SeqLayer {
-    logger: Arc<Mutex<SeqLogger>>,
+    handle: SeqHandle,
 }
 
 impl SeqLayer {
-    pub fn new(logger: Arc<Mutex<SeqLogger>>) -> Self {
-        Self { logger }
+    pub fn new(handle: SeqHandle) -> Self {
+        Self { handle }
+    }
+}
+
+/// Fields recorded on a span, stored in its extensions.
+struct SpanFields(serde_json::Map<String, serde_json::Value>);
+
+/// Records tracing fields as typed JSON values.
+struct JsonVisitor<'a> {
+    fields: &'a mut serde_json::Map<String, serde_json::Value>,
+    message: Option<String>,
+    exception: Option<String>,
+    redactor: Arc<Redactor>,
+}
+
+impl<'a> JsonVisitor<'a> {
+    fn new(fields: &'a mut serde_json::Map<String, serde_json::Value>) -> Self {
+        JsonVisitor {
+            fields,
+            message: None,
+            exception: None,
+            redactor: redactor(),
+        }
+    }
+
+    fn insert(&mut self, field: &Field, value: serde_json::Value) {
+        let name = field.name();
+        if name.starts_with("log.") {
+            // Metadata added by the `log` bridge, already part of the event
+            return;
+        }
+        let value = self.redactor.redact_field(name, value);
+        self.fields.insert(property_name(name), value);
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.insert(field, json!(value));
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        let mut rendered = value.to_string();
+        let mut source = value.source();
+        while let Some(cause) = source {
+            rendered.push_str(&format!("\nCaused by: {}", cause));
+            source = cause.source();
+        }
+        self.exception = Some(rendered);
+        self.insert(field, json!(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else {
+            self.insert(field, json!(format!("{:?}", value)));
+        }
+    }
+}
+
+/// CLEF reserves names starting with `@`; user properties escape it as `@@`.
+fn property_name(name: &str) -> String {
+    if name.starts_with('@') {
+        format!("@{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Literal braces would be read as template holes by Seq.
+fn escape_template(message: &str) -> String {
+    message.replace('{', "{{").replace('}', "}}")
+}
+
+/// Maps tracing levels onto the Serilog level names Seq expects.
+fn clef_level(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE => "Verbose",
+        Level::DEBUG => "Debug",
+        Level::INFO => "Information",
+        Level::WARN => "Warning",
+        Level::ERROR => "Error",
     }
 }
 
 impl<S> Layer<S> for SeqLayer
 where
-    S: Subscriber,
+    S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
-        let logger = self.logger.clone();
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = serde_json::Map::new();
+        attrs.record(&mut JsonVisitor::new(&mut fields));
+        span.extensions_mut().insert(SpanFields(fields));
+    }
 
-        // Create a JSON object to hold the fields
-        let mut fields_map = HashMap::new();
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut JsonVisitor::new(fields));
+        }
+    }
 
-        // Record the fields from the event using the correct closure signature
-        event.record(
-            &mut |field: &tracing::field::Field, value: &dyn std::fmt::Debug| {
-                fields_map.insert(field.name().to_string(), format!("{:?}", value));
-            },
-        );
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if thread::current().name() == Some(SHIPPER_THREAD) {
+            return;
+        }
+
+        let metadata = event.metadata();
+        let mut properties = serde_json::Map::new();
 
-        // Serialize the fields map to a JSON object
-        let fields_json = serde_json::to_value(fields_map).unwrap();
-
-        let message = format!("Log event: {}", event.metadata().target());
-        let level = event.metadata().level().as_str();
-
-        // Spawn an asynchronous task to send the log to Seq
-        tokio::spawn(async move {
-            if let Err(err) = logger
-                .lock()
-                .await
-                .log_to_seq(&message, level, &fields_json)
-                .await
-            {
-                // Avoid tracing here to prevent recursive logging loops if Seq is down
-                eprintln!("[SeqLayer] failed to send log to Seq: {}", err);
+        // Span fields first, outermost to innermost, so inner spans and the
+        // event itself win on name clashes
+        if let Some(scope) = ctx.event_scope(event) {
+            let mut span_names = Vec::new();
+            for span in scope.from_root() {
+                span_names.push(span.name());
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    for (key, value) in fields {
+                        properties.insert(key.clone(), value.clone());
+                    }
+                }
             }
+            properties.insert("Spans".to_string(), json!(span_names.join(" > ")));
+        }
+
+        let mut visitor = JsonVisitor::new(&mut properties);
+        event.record(&mut visitor);
+        let message = visitor.message.take();
+        let exception = visitor.exception.take();
+
+        properties.insert("SourceContext".to_string(), json!(metadata.target()));
+
+        let mut clef_event = serde_json::Map::new();
+        clef_event.insert("@t".to_string(), json!(Utc::now().to_rfc3339())); // Timestamp
+        clef_event.insert(
+            "@mt".to_string(), // Message template
+            json!(escape_template(
+                message.as_deref().unwrap_or_else(|| metadata.name())
+            )),
+        );
+        clef_event.insert("@l".to_string(), json!(clef_level(metadata.level()))); // Log level
+        if let Some(exception) = exception {
+            clef_event.insert("@x".to_string(), json!(exception));
+        }
+        clef_event.extend(properties);
+
+        // Hand the event to the shipper; never blocks the caller
+        self.handle.enqueue(serde_json::Value::Object(clef_event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn capture<F: FnOnce()>(emit: F) -> Vec<serde_json::Value> {
+        let (sender, mut receiver) = mpsc::channel(16);
+        let subscriber =
+            tracing_subscriber::registry().with(SeqLayer::new(SeqHandle { sender }));
+        tracing::subscriber::with_default(subscriber, emit);
+
+        let mut events = Vec::new();
+        while let Ok(SeqMessage::Event(event)) = receiver.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_event_message_and_typed_fields() {
+        let events = capture(|| {
+            tracing::warn!(nonce = 7u64, retry = true, ratio = 0.5, "Nonce {} for {{x}}", 7);
+        });
+
+        let event = &events[0];
+        assert_eq!(event["@mt"], "Nonce 7 for {{x}}");
+        assert_eq!(event["@l"], "Warning");
+        assert_eq!(event["nonce"], 7);
+        assert_eq!(event["retry"], true);
+        assert_eq!(event["ratio"], 0.5);
+        assert_eq!(event["SourceContext"], module_path!());
+        assert!(event.get("message").is_none());
+    }
+
+    #[test]
+    fn test_errors_become_exceptions() {
+        let events = capture(|| {
+            let err = std::io::Error::new(std::io::ErrorKind::Other, "disk on fire");
+            tracing::error!(error = &err as &dyn std::error::Error, "Write failed");
+        });
+
+        assert_eq!(events[0]["@x"], "disk on fire");
+        assert_eq!(events[0]["@l"], "Error");
+    }
+
+    #[test]
+    fn test_span_fields_are_attached() {
+        let events = capture(|| {
+            let request = tracing::info_span!("request", request_id = "abc", user_key = 1u64);
+            let _request = request.enter();
+            let operation = tracing::info_span!("operation", operation_name = tracing::field::Empty);
+            operation.record("operation_name", "createRide");
+            let _operation = operation.enter();
+            tracing::info!("Inside");
         });
+
+        let event = &events[0];
+        assert_eq!(event["request_id"], "abc");
+        assert_eq!(event["user_key"], 1);
+        assert_eq!(event["operation_name"], "createRide");
+        assert_eq!(event["Spans"], "request > operation");
+    }
+
+    #[test]
+    fn test_sensitive_fields_are_redacted() {
+        let events = capture(|| {
+            let request = tracing::info_span!("request", token = "abc");
+            let _request = request.enter();
+            tracing::info!(raw_transaction = "0xdead", pickup_latitude = 52.520008, "Ride");
+        });
+
+        let event = &events[0];
+        assert_eq!(event["token"], "[redacted]");
+        assert_eq!(event["raw_transaction"], "[redacted]");
+        assert_eq!(event["pickup_latitude"], 52.52);
     }
 }
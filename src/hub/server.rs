@@ -1,14 +1,66 @@
-use crate::hub::clutch_node_client::ClutchNodeClient;
-use crate::hub::configuration::AppConfig;
+use crate::hub::clutch_node_client::{ClientOptions, ClutchNodeClient, TlsOptions};
+use crate::hub::configuration::{AppConfig, LiveConfig};
 use crate::hub::graphql::build_schema;
 use crate::hub::graphql::handler::graphql_handler;
+use crate::hub::health::{livez, readyz};
+use crate::hub::keystore::OperatorKey;
+use crate::hub::rate_limit::RateLimiter;
+use crate::hub::redact::Secret;
+use crate::hub::shutdown::{InFlight, Shutdown};
+use actix_web::dev::Service;
 use actix_web::{web, App, HttpServer, HttpResponse, Result};
 use std::sync::Arc;
+use std::time::Duration;
 use actix_cors::Cors;
+use tracing::warn;
 
-pub async fn connect_websocket(wss_url: &str) -> Arc<ClutchNodeClient> {
-    let url = wss_url.to_string();
-    ClutchNodeClient::new(url)
+pub async fn connect_websocket(config: &AppConfig) -> Result<Arc<ClutchNodeClient>, String> {
+    let node = &config.node;
+    let mut handshake_headers: Vec<(String, Secret)> = node
+        .handshake_headers
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    if let Some(token) = &node.auth_token {
+        handshake_headers.push((
+            "Authorization".to_string(),
+            Secret::new(format!("Bearer {}", token.expose())),
+        ));
+    }
+
+    let options = ClientOptions {
+        request_timeout: Duration::from_secs(config.limits.node_request_timeout_secs),
+        auto_batch: node.auto_batch,
+        max_batch_size: node.max_batch_size,
+        ping_interval: Duration::from_secs(node.ping_interval_secs),
+        pong_timeout: Duration::from_secs(node.pong_timeout_secs),
+        reconnect_delay: Duration::from_secs(node.reconnect_delay_secs),
+        connect_timeout: Duration::from_secs(node.connect_timeout_secs),
+        tls: TlsOptions {
+            ca_file: node.ca_file.clone(),
+            client_cert_file: node.client_cert_file.clone(),
+            client_key_file: node.client_key_file.clone(),
+            cert_pins: node.cert_pins.clone(),
+        },
+        handshake_headers,
+        propagate_trace_context: node.trace_propagation,
+    };
+    ClutchNodeClient::with_options(node.ws_url.clone(), options)
+}
+
+/// Checks origins against the live configuration, so reloads apply to
+/// running workers.
+fn build_cors(config: LiveConfig, max_age_secs: usize) -> Cors {
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin
+                .to_str()
+                .map(|origin| config.current().cors.allows(origin))
+                .unwrap_or(false)
+        })
+        .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+        .allow_any_header()
+        .max_age(max_age_secs)
 }
 
 async fn health_check() -> Result<HttpResponse> {
@@ -19,26 +71,91 @@ async fn health_check() -> Result<HttpResponse> {
     })))
 }
 
+/// Serves GraphQL until `shutdown` is triggered, then stops accepting
+/// connections and lets in-flight requests finish within the grace period.
+/// The operator key, when one is configured, is available to handlers and
+/// resolvers as [`OperatorKey`].
 pub async fn run_graphql_server(
     ws_addr: &str,
     ws_manager: Arc<ClutchNodeClient>,
-    config: AppConfig,
+    config: impl Into<LiveConfig>,
+    operator_key: Option<OperatorKey>,
+    shutdown: Shutdown,
 ) -> std::io::Result<()> {
-    let schema = build_schema(ws_manager.clone(), config.clone());
-    HttpServer::new(move || {
+    let live = config.into();
+    let config: AppConfig = (*live.current()).clone();
+    let schema = build_schema(ws_manager.clone(), live.clone());
+    let grace_period = Duration::from_secs(config.shutdown_grace_period_secs);
+    let max_connections = config.limits.max_connections;
+    let in_flight = InFlight::default();
+    let in_flight_requests = in_flight.clone();
+    let rate_limiter = RateLimiter::new(live.clone());
+    let draining = shutdown.clone();
+    let operator_key = operator_key.map(web::Data::new);
+    let server = HttpServer::new(move || {
+        let in_flight = in_flight_requests.clone();
+        let operator_key = operator_key.clone();
         App::new()
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST", "OPTIONS"])
-                    .allow_any_header()
-            )
+            .wrap_fn(move |req, srv| {
+                let guard = in_flight.enter();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await;
+                    drop(guard);
+                    response
+                }
+            })
+            .wrap(build_cors(live.clone(), config.cors.max_age_secs))
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(live.clone()))
+            .app_data(web::Data::new(draining.clone()))
+            .app_data(web::PayloadConfig::new(config.limits.max_body_bytes))
             .app_data(web::Data::new(schema.clone()))
+            .app_data(web::Data::new(ws_manager.clone()))
+            .configure(|app| {
+                if let Some(operator_key) = operator_key {
+                    app.app_data(operator_key);
+                }
+            })
             .service(web::resource("/health").route(web::get().to(health_check)))
-            .service(web::resource("/graphql").route(web::post().to(graphql_handler)))
+            .service(web::resource("/livez").route(web::get().to(livez)))
+            .service(web::resource("/readyz").route(web::get().to(readyz)))
+            .service(
+                web::resource("/graphql")
+                    .wrap(rate_limiter.clone())
+                    .route(web::post().to(graphql_handler)),
+            )
     })
-    .bind(ws_addr)?
-    .run()
-    .await
+    .bind(ws_addr)
+    .map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to bind GraphQL server to {}: {}", ws_addr, e),
+        )
+    })?
+    // Signals are handled by the caller so every component stops together
+    .disable_signals()
+    .max_connections(max_connections)
+    .shutdown_timeout(grace_period.as_secs())
+    .run();
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown.wait().await;
+        handle.pause().await;
+        // actix workers exit as soon as the acceptor is gone, even during a
+        // graceful stop, so wait for in-flight requests ourselves first
+        let idle = in_flight.wait_idle(shutdown.remaining(grace_period)).await;
+        if !idle {
+            warn!(
+                "{} requests still in flight after {:?}, stopping anyway",
+                in_flight.count(),
+                grace_period
+            );
+        }
+        // Stragglers already had the whole grace period
+        handle.stop(idle).await;
+    });
+
+    server.await
 }
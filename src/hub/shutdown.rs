@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::time::Instant;
+
+/// Shared shutdown flag. Cloning hands out another view of the same flag.
+#[derive(Clone)]
+pub struct Shutdown {
+    /// When `trigger` was first called.
+    sender: Arc<watch::Sender<Option<Instant>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(None);
+        Shutdown {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Asks every task waiting on this flag to stop.
+    pub fn trigger(&self) {
+        self.sender.send_if_modified(|triggered_at| {
+            triggered_at.get_or_insert_with(Instant::now);
+            true
+        });
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.sender.borrow().is_some()
+    }
+
+    /// What is left of `grace` since the first `trigger`, so successive
+    /// shutdown stages share one deadline. All of it before a trigger.
+    pub fn remaining(&self, grace: Duration) -> Duration {
+        match *self.sender.borrow() {
+            Some(triggered_at) => grace.saturating_sub(triggered_at.elapsed()),
+            None => grace,
+        }
+    }
+
+    /// Resolves once `trigger` has been called, immediately if it already was.
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = receiver.wait_for(Option::is_some).await;
+    }
+}
+
+/// Counts requests currently being served so shutdown can wait for them.
+#[derive(Clone, Default)]
+pub struct InFlight {
+    inner: Arc<InFlightInner>,
+}
+
+#[derive(Default)]
+struct InFlightInner {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+/// Marks one request as in flight until dropped.
+pub struct InFlightGuard {
+    inner: Arc<InFlightInner>,
+}
+
+impl InFlight {
+    pub fn enter(&self) -> InFlightGuard {
+        self.inner.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.inner.count.load(Ordering::SeqCst)
+    }
+
+    /// Waits until no request is in flight. Returns `false` if some were
+    /// still running after `deadline`.
+    pub async fn wait_idle(&self, deadline: Duration) -> bool {
+        let idle = async {
+            loop {
+                let notified = self.inner.idle.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if self.count() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(deadline, idle).await.is_ok()
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// Resolves on SIGTERM or SIGINT (Ctrl-C).
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
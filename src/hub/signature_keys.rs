@@ -1,119 +1,453 @@
-use hex::FromHex;
 use rand::rngs::OsRng;
 use secp256k1::{
-    ecdsa::RecoverableSignature, ecdsa::RecoveryId, Message, PublicKey, Secp256k1, SecretKey,
+    ecdsa::RecoverableSignature, ecdsa::RecoveryId, ecdsa::Signature, Message, PublicKey,
+    Secp256k1, SecretKey,
 };
+use serde_json::{Map, Value};
 use sha3::{Digest, Keccak256};
+use std::fmt;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::hub::address::Address;
+use crate::hub::redact::REDACTED;
+use crate::hub::typed_data::TypedData;
 
-#[derive(Debug)]
-#[allow(dead_code)]
 pub struct SignatureKeys {
     pub secret_key: String,
     pub public_key: String,
     pub address_key: String,
 }
 
+impl fmt::Debug for SignatureKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignatureKeys")
+            .field("secret_key", &format_args!("{}", REDACTED))
+            .field("public_key", &self.public_key)
+            .field("address_key", &self.address_key)
+            .finish()
+    }
+}
+
 impl SignatureKeys {
-    #[allow(dead_code)]
     pub fn generate_new_keypair() -> Self {
         let secp = Secp256k1::new();
-        let mut rng = OsRng::default();
+        let mut rng = OsRng;
         let (secret_key, public_key) = secp.generate_keypair(&mut rng);
         let address_key = Self::derive_address(&public_key);
 
         SignatureKeys {
             secret_key: hex::encode(secret_key.as_ref()),
             public_key: hex::encode(public_key.serialize_uncompressed()),
-            address_key: address_key,
+            address_key,
         }
     }
 
-    fn derive_address(public_key: &PublicKey) -> String {
-        let serialized_pubkey = public_key.serialize_uncompressed();
-        let mut hasher = Keccak256::new();
-        hasher.update(&serialized_pubkey[1..]);
-        let hash = hasher.finalize();
+    /// Rebuilds the key set from a hex secret key, with or without `0x`.
+    pub fn from_secret_key(secret_key: &str) -> Result<Self, SignatureError> {
+        let secret_key = parse_secret_key(secret_key)?;
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
 
-        let address_key = format!("0x{}", hex::encode(&hash[12..32]));
-        address_key
+        Ok(SignatureKeys {
+            secret_key: hex::encode(secret_key.as_ref()),
+            public_key: hex::encode(public_key.serialize_uncompressed()),
+            address_key: Self::derive_address(&public_key),
+        })
     }
 
-    pub fn sign(secret_key: &str, data: &[u8]) -> (String, String, i32) {
-        let secp = Secp256k1::new();
-
-        let secret_key_bytes = hex::decode(secret_key).unwrap();
-        let secret_key = SecretKey::from_slice(&secret_key_bytes).unwrap();
+    /// Derives the address of a hex public key, compressed or uncompressed.
+    pub fn address_from_public_key(public_key: &str) -> Result<String, SignatureError> {
+        Address::from_public_key_hex(public_key).map(|address| address.to_lower_hex())
+    }
 
-        // Create a message hash (Keccak-256 of the data)
-        let mut hasher = Keccak256::new();
-        hasher.update(data);
-        let message_hash = hasher.finalize();
+    fn derive_address(public_key: &PublicKey) -> String {
+        Address::from_public_key(public_key).to_lower_hex()
+    }
 
-        // Create a message object for secp256k1
-        let message = Message::from_digest_slice(&message_hash)
-            .expect("Message could not be created from hash");
+    pub fn sign(secret_key: &str, data: &[u8]) -> Result<(String, String, i32), SignatureError> {
+        Self::sign_hash(secret_key, &keccak(data))
+    }
 
-        // Sign the message
-        let recoverable_sig = secp.sign_ecdsa_recoverable(&message, &secret_key);
+    pub fn verify(
+        address: &str,
+        data: &[u8],
+        r: &str,
+        s: &str,
+        v: i32,
+    ) -> Result<bool, SignatureError> {
+        let signer = Self::recover_address(&keccak(data), r, s, v)?;
+        Ok(same_address(&signer, address))
+    }
 
-        // Serialize the signature to compact format
+    /// Signs a 32-byte digest, returning hex `r` and `s` and `v` as 27 or 28.
+    /// `s` is always in the lower half of the curve order.
+    pub fn sign_hash(
+        secret_key: &str,
+        hash: &[u8; 32],
+    ) -> Result<(String, String, i32), SignatureError> {
+        let secret_key = parse_secret_key(secret_key)?;
+        let message = Message::from_digest(*hash);
+
+        let recoverable_sig =
+            Secp256k1::signing_only().sign_ecdsa_recoverable(&message, &secret_key);
         let (recid, sig) = recoverable_sig.serialize_compact();
+        // libsecp256k1 already signs with a low s, normalizing makes that a
+        // promise of this function rather than of the library
+        let (sig, recid) = to_low_s(sig, recid.to_i32())?;
+        Ok(encode_signature(&sig, recid))
+    }
 
-        // Convert signature and recovery ID to appropriate formats
-        let r = hex::encode(&sig[0..32]); // r component
-        let s = hex::encode(&sig[32..64]); // s component
-        let v = recid.to_i32() + 27; // recovery ID, adjusted for Ethereum (v = 27 or 28)
+    /// Address of the key that signed `hash`. `r` and `s` must be 32 bytes
+    /// of hex, with or without `0x`, and `v` 27 or 28 (or 0 or 1). Signatures
+    /// with a high `s` are rejected as malleable, see `normalize_signature`.
+    pub fn recover_address(
+        hash: &[u8; 32],
+        r: &str,
+        s: &str,
+        v: i32,
+    ) -> Result<String, SignatureError> {
+        let (signature_data, recid) = decode_signature(r, s, v)?;
+        if is_high_s(&signature_data) {
+            return Err(SignatureError::MalleableSignature);
+        }
+        let recovery_id =
+            RecoveryId::from_i32(recid).map_err(|_| SignatureError::InvalidRecoveryId(v.into()))?;
+        let recoverable_sig = RecoverableSignature::from_compact(&signature_data, recovery_id)
+            .map_err(|_| SignatureError::InvalidSignature)?;
 
-        (r, s, v)
+        Secp256k1::verification_only()
+            .recover_ecdsa(&Message::from_digest(*hash), &recoverable_sig)
+            .map(|public_key| Self::derive_address(&public_key))
+            .map_err(|_| SignatureError::RecoveryFailed)
     }
 
-    pub fn verify(
-        derive_address: &str,
-        data: &[u8],
+    /// Rewrites a signature with a high `s` into the equivalent one with
+    /// `n - s` and the other `v`, as EIP-2 requires. Low-s signatures are
+    /// returned unchanged, with `v` as 27 or 28.
+    pub fn normalize_signature(
         r: &str,
         s: &str,
         v: i32,
-    ) -> Result<bool, String> {
-        let secp = Secp256k1::new();
+    ) -> Result<(String, String, i32), SignatureError> {
+        let (signature_data, recid) = decode_signature(r, s, v)?;
+        let (signature_data, recid) = to_low_s(signature_data, recid)?;
+        Ok(encode_signature(&signature_data, recid))
+    }
+
+    /// Splits a 65-byte `r ‖ s ‖ v` hex signature as wallets return it.
+    pub fn split_signature(signature: &str) -> Result<(String, String, i32), SignatureError> {
+        let bytes: [u8; 65] = decode_exact("Signature", signature)?;
+        let v = bytes[64] as i32;
+        // Some wallets (Ledger, older geth) return v as 0 or 1
+        let recid = recovery_id(v)?;
+        Ok(encode_signature(&bytes[..64], recid))
+    }
+
+    /// EIP-191 digest of a message signed with `personal_sign`:
+    /// `keccak256("\x19Ethereum Signed Message:\n" ‖ len ‖ message)`.
+    pub fn personal_message_hash(message: &[u8]) -> [u8; 32] {
         let mut hasher = Keccak256::new();
-        hasher.update(data);
-        let message_hash = hasher.finalize();
-        let message = Message::from_digest_slice(&message_hash)
-            .map_err(|_| "Message could not be created from hash".to_string())?;
-
-        let sig_r = Vec::from_hex(r).map_err(|_| "Invalid hex in r".to_string())?;
-        let sig_s = Vec::from_hex(s).map_err(|_| "Invalid hex in s".to_string())?;
-        let signature_data = [&sig_r[..], &sig_s[..]].concat();
-        let recovery_id =
-            RecoveryId::from_i32(v - 27).map_err(|_| "Invalid recovery ID".to_string())?;
-        let recoverable_sig = RecoverableSignature::from_compact(&signature_data, recovery_id)
-            .map_err(|_| "Valid signature could not be created".to_string())?;
+        hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+        hasher.update(message);
+        hasher.finalize().into()
+    }
+
+    /// Signs `message` the way wallets do for `personal_sign`.
+    pub fn sign_personal_message(
+        secret_key: &str,
+        message: &[u8],
+    ) -> Result<(String, String, i32), SignatureError> {
+        Self::sign_hash(secret_key, &Self::personal_message_hash(message))
+    }
 
-        match secp.recover_ecdsa(&message, &recoverable_sig) {
-            Ok(recovered_public_key) => {
-                let derived_address = Self::derive_address(&recovered_public_key);
-                Ok(derived_address == derive_address)
+    pub fn verify_personal_message(
+        address: &str,
+        message: &[u8],
+        r: &str,
+        s: &str,
+        v: i32,
+    ) -> Result<bool, SignatureError> {
+        let signer = Self::recover_address(&Self::personal_message_hash(message), r, s, v)?;
+        Ok(same_address(&signer, address))
+    }
+
+    /// Signs EIP-712 typed data the way wallets do for `eth_signTypedData_v4`.
+    pub fn sign_typed_data(
+        secret_key: &str,
+        typed_data: &TypedData,
+    ) -> Result<(String, String, i32), SignatureError> {
+        let hash = typed_data.signing_hash().map_err(SignatureError::InvalidPayload)?;
+        Self::sign_hash(secret_key, &hash)
+    }
+
+    pub fn verify_typed_data(
+        address: &str,
+        typed_data: &TypedData,
+        r: &str,
+        s: &str,
+        v: i32,
+    ) -> Result<bool, SignatureError> {
+        let hash = typed_data.signing_hash().map_err(SignatureError::InvalidPayload)?;
+        let signer = Self::recover_address(&hash, r, s, v)?;
+        Ok(same_address(&signer, address))
+    }
+
+    /// Signs an unsigned transaction as returned by `createUnsignedRideRequest`.
+    ///
+    /// With `SignatureScheme::Keccak` the signature covers the compact JSON of
+    /// the transaction with its keys sorted; with `Eip712` it covers the
+    /// transaction's typed data, as a wallet would sign it. The signature is
+    /// added as `signature_r`, `signature_s` and `signature_v`, and `hash`
+    /// holds the Keccak-256 of the sorted JSON.
+    pub fn sign_transaction(
+        secret_key: &str,
+        unsigned: &Value,
+        scheme: SignatureScheme,
+    ) -> Result<Value, SignatureError> {
+        let keys = Self::from_secret_key(secret_key)?;
+        let Value::Object(fields) = unsigned else {
+            return Err(invalid_payload("Transaction must be a JSON object"));
+        };
+        if fields.keys().any(|key| SIGNED_TRANSACTION_FIELDS.contains(&key.as_str())) {
+            return Err(invalid_payload("Transaction is already signed"));
+        }
+
+        let payload = canonical_json(unsigned);
+        let (r, s, v) = match scheme {
+            SignatureScheme::Keccak => Self::sign_hash(&keys.secret_key, &keccak(&payload))?,
+            SignatureScheme::Eip712 => {
+                let typed_data =
+                    TypedData::ride_request(unsigned).map_err(SignatureError::InvalidPayload)?;
+                Self::sign_typed_data(&keys.secret_key, &typed_data)?
             }
-            Err(_) => Err("Public key could not be recovered".to_string()),
+        };
+        let mut signed = fields.clone();
+        if scheme == SignatureScheme::Eip712 {
+            signed.insert("signature_scheme".to_string(), Value::from("eip712"));
         }
+        signed.insert("signature_r".to_string(), Value::from(r));
+        signed.insert("signature_s".to_string(), Value::from(s));
+        signed.insert("signature_v".to_string(), Value::from(v));
+        signed.insert(
+            "hash".to_string(),
+            Value::from(format!("0x{}", hex::encode(keccak(&payload)))),
+        );
+        Ok(Value::Object(signed))
     }
 
-    pub fn validate_public_key(public_key: &str) -> Result<(), String> {
-        // Remove "0x" prefix if present
-        let cleaned_key = public_key.trim_start_matches("0x");
-        
-        // Ethereum addresses are 40 characters (20 bytes)
-        // Uncompressed secp256k1 public keys are 130 characters (65 bytes)
-        if cleaned_key.len() != 40 && cleaned_key.len() != 130 {
-            return Err(format!("Invalid public key length. Expected 40 or 130 characters, got {} for key: {}", cleaned_key.len(), public_key));
+    /// Checks that a transaction from `sign_transaction` was signed by the key
+    /// behind its `from` field, which may be an address or a public key.
+    pub fn verify_transaction(signed: &Value) -> Result<bool, SignatureError> {
+        let Value::Object(fields) = signed else {
+            return Err(invalid_payload("Transaction must be a JSON object"));
+        };
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .ok_or_else(|| invalid_payload(format!("Transaction has no {} field", name)))
+        };
+        let text = |name: &str| {
+            field(name)?.as_str().ok_or_else(|| {
+                invalid_payload(format!("Transaction field {} must be a string", name))
+            })
+        };
+        let from = text("from")?;
+        let (r, s) = (text("signature_r")?, text("signature_s")?);
+        let v = field("signature_v")?
+            .as_i64()
+            .ok_or_else(|| invalid_payload("Transaction field signature_v must be a number"))?;
+        let v = i32::try_from(v).map_err(|_| SignatureError::InvalidRecoveryId(v))?;
+
+        let address = Address::from_address_or_public_key(from)?.to_lower_hex();
+        let unsigned: Map<String, Value> = fields
+            .iter()
+            .filter(|(key, _)| !SIGNED_TRANSACTION_FIELDS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let unsigned = Value::Object(unsigned);
+        match fields.get("signature_scheme").and_then(Value::as_str) {
+            None => Self::verify(&address, &canonical_json(&unsigned), r, s, v),
+            Some("eip712") => Self::verify_typed_data(
+                &address,
+                &TypedData::ride_request(&unsigned).map_err(SignatureError::InvalidPayload)?,
+                r,
+                s,
+                v,
+            ),
+            Some(other) => Err(invalid_payload(format!(
+                "Signature scheme {:?} is not supported",
+                other
+            ))),
         }
+    }
+
+    /// Hex encoding of a signed transaction, as accepted by `sendRawTransaction`.
+    pub fn encode_raw_transaction(signed: &Value) -> String {
+        format!("0x{}", hex::encode(canonical_json(signed)))
+    }
+
+    /// Checks that `public_key` is an address or a compressed or
+    /// uncompressed secp256k1 public key.
+    pub fn validate_public_key(public_key: &str) -> Result<(), SignatureError> {
+        Address::from_address_or_public_key(public_key).map(|_| ())
+    }
+}
+
+/// Why a key, a signature or a signed payload was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SignatureError {
+    #[error("{0} is not valid hex")]
+    InvalidHex(&'static str),
+    #[error("{field} must be {expected} bytes, not {actual}")]
+    InvalidLength {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Address {0} does not match its EIP-55 checksum")]
+    InvalidChecksum(String),
+    #[error("Secret key is not a valid secp256k1 key")]
+    InvalidSecretKey,
+    #[error("Public key is not a valid secp256k1 key")]
+    InvalidPublicKey,
+    #[error("Invalid recovery ID: v is {0}, expected 27 or 28 (or 0 or 1)")]
+    InvalidRecoveryId(i64),
+    #[error("Signature is malleable: s is in the upper half of the curve order")]
+    MalleableSignature,
+    #[error("Signature is not a valid secp256k1 signature")]
+    InvalidSignature,
+    #[error("Public key could not be recovered")]
+    RecoveryFailed,
+    #[error("{0}")]
+    InvalidPayload(String),
+}
 
-        // Validate hex format
-        Vec::from_hex(cleaned_key)
-            .map_err(|_| format!("Invalid hex format for public key: {}", public_key))?;
+impl From<SignatureError> for String {
+    fn from(error: SignatureError) -> Self {
+        error.to_string()
+    }
+}
+
+fn invalid_payload(message: impl Into<String>) -> SignatureError {
+    SignatureError::InvalidPayload(message.into())
+}
+
+/// Half the secp256k1 group order `n`. For every signature `(r, s)` the pair
+/// `(r, n - s)` is valid too, so only the one with `s` at most this is accepted.
+const HALF_CURVE_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+pub(crate) fn strip_hex_prefix(text: &str) -> &str {
+    text.strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text)
+}
+
+/// Decodes hex, with or without `0x`, that must be exactly `N` bytes long.
+pub(crate) fn decode_exact<const N: usize>(
+    field: &'static str,
+    text: &str,
+) -> Result<[u8; N], SignatureError> {
+    let bytes =
+        hex::decode(strip_hex_prefix(text)).map_err(|_| SignatureError::InvalidHex(field))?;
+    <[u8; N]>::try_from(bytes.as_slice()).map_err(|_| SignatureError::InvalidLength {
+        field,
+        expected: N,
+        actual: bytes.len(),
+    })
+}
+
+fn parse_secret_key(secret_key: &str) -> Result<SecretKey, SignatureError> {
+    let bytes = Zeroizing::new(decode_exact::<32>("Secret key", secret_key)?);
+    SecretKey::from_slice(&*bytes).map_err(|_| SignatureError::InvalidSecretKey)
+}
+
+/// Recovery ID, 0 or 1, of an Ethereum `v` of 27 or 28 or of a raw 0 or 1.
+fn recovery_id(v: i32) -> Result<i32, SignatureError> {
+    match v {
+        27 | 28 => Ok(v - 27),
+        0 | 1 => Ok(v),
+        _ => Err(SignatureError::InvalidRecoveryId(v.into())),
+    }
+}
+
+/// `r ‖ s` and the recovery ID of a hex signature.
+fn decode_signature(r: &str, s: &str, v: i32) -> Result<([u8; 64], i32), SignatureError> {
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&decode_exact::<32>("r", r)?);
+    signature[32..].copy_from_slice(&decode_exact::<32>("s", s)?);
+    Ok((signature, recovery_id(v)?))
+}
+
+fn encode_signature(signature: &[u8], recid: i32) -> (String, String, i32) {
+    (hex::encode(&signature[..32]), hex::encode(&signature[32..64]), recid + 27)
+}
+
+fn is_high_s(signature: &[u8; 64]) -> bool {
+    signature[32..] > HALF_CURVE_ORDER[..]
+}
+
+/// Replaces a high `s` with `n - s`, which flips the parity of the recovered
+/// point and so the recovery ID.
+fn to_low_s(signature: [u8; 64], recid: i32) -> Result<([u8; 64], i32), SignatureError> {
+    if !is_high_s(&signature) {
+        return Ok((signature, recid));
+    }
+    let mut normalized =
+        Signature::from_compact(&signature).map_err(|_| SignatureError::InvalidSignature)?;
+    normalized.normalize_s();
+    Ok((normalized.serialize_compact(), recid ^ 1))
+}
+
+/// The same address whatever the case or `0x` prefix.
+fn same_address(a: &str, b: &str) -> bool {
+    strip_hex_prefix(a).eq_ignore_ascii_case(strip_hex_prefix(b))
+}
 
-        Ok(())
+/// How a transaction signature is computed. Transactions signed with a
+/// wallet use `Eip712` and say so in their `signature_scheme` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// Keccak-256 of the transaction's sorted compact JSON.
+    Keccak,
+    /// EIP-712 typed data, see `TypedData::ride_request`.
+    Eip712,
+}
+
+/// Fields `sign_transaction` adds, which are not part of the signed payload.
+const SIGNED_TRANSACTION_FIELDS: &[&str] = &[
+    "signature_r",
+    "signature_s",
+    "signature_v",
+    "signature_scheme",
+    "hash",
+];
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Compact JSON with object keys in sorted order at every level.
+fn canonical_json(value: &Value) -> Vec<u8> {
+    fn sorted(value: &Value) -> Value {
+        match value {
+            Value::Object(fields) => {
+                let mut entries: Vec<_> = fields.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key.clone(), sorted(value)))
+                        .collect(),
+                )
+            }
+            Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+            other => other.clone(),
+        }
     }
+    serde_json::to_vec(&sorted(value)).expect("JSON values always serialize")
 }
 
 #[cfg(test)]
@@ -140,7 +474,7 @@ mod tests {
         info!("Secret key: {:?}", keys.secret_key);
 
         // Test signing
-        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, data);
+        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, data).unwrap();
         info!("Signature: r={:?}, s={:?}, v={:?}", r, s, v);
 
         match SignatureKeys::verify(&keys.address_key, data, &r, &s, v) {
@@ -156,7 +490,7 @@ mod tests {
         let modified_data = b"Altered data";
 
         // Test signing with the original data
-        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, original_data);
+        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, original_data).unwrap();
 
         // Attempt to verify signature against modified data
         match SignatureKeys::verify(&keys.address_key, modified_data, &r, &s, v) {
@@ -164,7 +498,7 @@ mod tests {
                 !is_verified,
                 "Signature verification should fail on modified data"
             ),
-            Err(_) => assert!(true, "Expected verification failure on modified data"),
+            Err(_) => {} // Expected verification failure on modified data
         }
     }
 
@@ -175,7 +509,7 @@ mod tests {
         let data = b"Blockchain technology";
 
         // Test signing with the first key
-        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, data);
+        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, data).unwrap();
 
         // Attempt to verify signature with a different public key
         match SignatureKeys::verify(&other_keys.address_key, data, &r, &s, v) {
@@ -183,10 +517,7 @@ mod tests {
                 !is_verified,
                 "Signature verification should fail with a different public key"
             ),
-            Err(_) => assert!(
-                true,
-                "Expected verification failure with a different public key"
-            ),
+            Err(_) => {} // Expected verification failure with a different public key
         }
     }
 
@@ -207,6 +538,16 @@ mod tests {
         let result = SignatureKeys::validate_public_key(&keys.public_key);
         assert!(result.is_ok(), "Valid secp256k1 public key should be accepted");
 
+        // Compressed keys, as some wallets export them, identify the same account
+        let uncompressed = hex::decode(strip_hex_prefix(&keys.public_key)).unwrap();
+        let compressed = PublicKey::from_slice(&uncompressed).unwrap().serialize();
+        let result = SignatureKeys::validate_public_key(&hex::encode(compressed));
+        assert!(result.is_ok(), "Compressed public key should be accepted");
+
+        // 65 bytes that are not a point on the curve
+        let result = SignatureKeys::validate_public_key(&format!("04{}", "11".repeat(64)));
+        assert!(result.is_err(), "Invalid curve point should be rejected");
+
         // Test with invalid hex string
         let invalid_hex = "not_a_valid_hex_string";
         let result = SignatureKeys::validate_public_key(invalid_hex);
@@ -217,4 +558,170 @@ mod tests {
         let result = SignatureKeys::validate_public_key(wrong_length);
         assert!(result.is_err(), "Wrong length key should be rejected");
     }
+
+    #[test]
+    fn test_from_secret_key_matches_generated_keys() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let restored = SignatureKeys::from_secret_key(&format!("0x{}", keys.secret_key)).unwrap();
+        assert_eq!(restored.public_key, keys.public_key);
+        assert_eq!(restored.address_key, keys.address_key);
+        assert_eq!(
+            SignatureKeys::address_from_public_key(&keys.public_key).unwrap(),
+            keys.address_key
+        );
+        assert!(SignatureKeys::from_secret_key("00").is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_transaction() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let location = serde_json::json!({ "latitude": 1.5, "longitude": -2.25 });
+        let unsigned = serde_json::json!({
+            "from": keys.address_key,
+            "nonce": 3,
+            "data": {
+                "function_call_type": "RideRequest",
+                "arguments": {
+                    "fare": 100,
+                    "pickup_location": location,
+                    "dropoff_location": location
+                }
+            },
+        });
+
+        for scheme in [SignatureScheme::Keccak, SignatureScheme::Eip712] {
+            let signed =
+                SignatureKeys::sign_transaction(&keys.secret_key, &unsigned, scheme).unwrap();
+            assert_eq!(signed["nonce"], 3);
+            assert!(signed["hash"].as_str().unwrap().starts_with("0x"));
+            assert!(SignatureKeys::verify_transaction(&signed).unwrap());
+            assert!(SignatureKeys::sign_transaction(&keys.secret_key, &signed, scheme).is_err());
+
+            let mut tampered = signed.clone();
+            tampered["data"]["arguments"]["fare"] = serde_json::json!(1);
+            assert!(!SignatureKeys::verify_transaction(&tampered).unwrap_or(false));
+        }
+    }
+
+    #[test]
+    fn test_malformed_input_is_an_error() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, b"data").unwrap();
+        let recover =
+            |r: &str, s: &str, v| SignatureKeys::verify(&keys.address_key, b"data", r, s, v);
+
+        assert_eq!(
+            SignatureKeys::sign("not hex", b"data").unwrap_err(),
+            SignatureError::InvalidHex("Secret key")
+        );
+        assert_eq!(
+            SignatureKeys::sign(&"00".repeat(32), b"data").unwrap_err(),
+            SignatureError::InvalidSecretKey
+        );
+        assert!(matches!(
+            SignatureKeys::sign(&keys.secret_key[2..], b"data"),
+            Err(SignatureError::InvalidLength { expected: 32, actual: 31, .. })
+        ));
+        assert!(matches!(
+            recover(&format!("00{}", r), &s, v),
+            Err(SignatureError::InvalidLength { field: "r", expected: 32, actual: 33 })
+        ));
+        assert!(matches!(
+            recover(&r, &s[2..], v),
+            Err(SignatureError::InvalidLength { field: "s", expected: 32, actual: 31 })
+        ));
+        assert_eq!(recover(&r, &s, 29), Err(SignatureError::InvalidRecoveryId(29)));
+        assert_eq!(recover(&"00".repeat(32), &s, v), Err(SignatureError::RecoveryFailed));
+    }
+
+    #[test]
+    fn test_prefixes_and_recovery_id_conventions_are_accepted() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let (r, s, v) = SignatureKeys::sign(&format!("0x{}", keys.secret_key), b"data").unwrap();
+        let address = keys.address_key.to_ascii_uppercase().replace("0X", "");
+        for (r, s, v) in [
+            (r.clone(), s.clone(), v),
+            (format!("0x{}", r), format!("0x{}", s), v - 27),
+        ] {
+            assert!(SignatureKeys::verify(&address, b"data", &r, &s, v).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_high_s_signatures_are_rejected_and_normalized() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let (r, s, v) = SignatureKeys::sign(&keys.secret_key, b"data").unwrap();
+        assert!(s.as_str() <= "7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b20a0");
+
+        // The malleable twin of (r, s, v) is (r, n - s, v ^ 1)
+        let s_scalar = SecretKey::from_slice(&hex::decode(&s).unwrap()).unwrap();
+        let high_s = hex::encode(s_scalar.negate().secret_bytes());
+        let other_v = 55 - v;
+
+        assert_eq!(
+            SignatureKeys::verify(&keys.address_key, b"data", &r, &high_s, other_v),
+            Err(SignatureError::MalleableSignature)
+        );
+        assert_eq!(
+            SignatureKeys::normalize_signature(&r, &high_s, other_v).unwrap(),
+            (r.clone(), s.clone(), v)
+        );
+        assert_eq!(
+            SignatureKeys::normalize_signature(&r, &s, v - 27).unwrap(),
+            (r, s, v)
+        );
+    }
+
+    #[test]
+    fn test_personal_message_matches_wallets() {
+        assert_eq!(
+            hex::encode(SignatureKeys::personal_message_hash(b"Hello World")),
+            "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2"
+        );
+        let keys = SignatureKeys::generate_new_keypair();
+        let (r, s, v) = SignatureKeys::sign_personal_message(&keys.secret_key, b"hi").unwrap();
+        let signature = format!("0x{}{}{:02x}", r, s, v - 27);
+        let (r, s, v) = SignatureKeys::split_signature(&signature).unwrap();
+        let checksummed = keys.address_key.to_ascii_uppercase().replace("0X", "0x");
+        assert!(SignatureKeys::verify_personal_message(&checksummed, b"hi", &r, &s, v).unwrap());
+        assert!(!SignatureKeys::verify(&keys.address_key, b"hi", &r, &s, v).unwrap());
+    }
+
+    #[test]
+    fn test_typed_data_signature_matches_specification() {
+        // The EIP-712 example, signed by the key keccak256("cow")
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "types": {
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" }
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person" },
+                    { "name": "contents", "type": "string" }
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+                "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+                "contents": "Hello, Bob!"
+            }
+        }))
+        .unwrap();
+        let cow = hex::encode(keccak(b"cow"));
+        let (r, s, v) = SignatureKeys::sign_typed_data(&cow, &typed_data).unwrap();
+        assert_eq!(r, "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d");
+        assert_eq!(s, "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562");
+        assert_eq!(v, 28);
+        let cow_address = "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826";
+        assert!(SignatureKeys::verify_typed_data(cow_address, &typed_data, &r, &s, v).unwrap());
+    }
 }
@@ -0,0 +1,168 @@
+use actix_web::http::header::HeaderMap;
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::trace::TraceResult;
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{BatchSpanProcessor, Span, SpanProcessor, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::collections::HashMap;
+use std::future::Future;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+use crate::hub::redact::{redactor, Redactor, REDACTED};
+
+const SERVICE_NAME: &str = "clutch-hub-api";
+
+/// Header carrying the correlation id of an HTTP request.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Builds a tracer provider that batches spans to an OTLP collector.
+///
+/// `protocol` is `"http"` (protobuf over HTTP, `endpoint` is the collector base
+/// URL and `/v1/traces` is appended) or `"grpc"`.
+pub fn build_otlp_provider(endpoint: &str, protocol: &str) -> Result<TracerProvider, String> {
+    let exporter = match protocol {
+        "http" => {
+            let endpoint = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+            SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+        }
+        "grpc" => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build(),
+        other => return Err(format!("Unsupported OTLP protocol: {}", other)),
+    }
+    .map_err(|e| format!("Failed to build OTLP exporter: {}", e))?;
+
+    let batch = BatchSpanProcessor::builder(exporter, runtime::Tokio).build();
+    Ok(TracerProvider::builder()
+        .with_span_processor(RedactingProcessor(batch))
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            SERVICE_NAME,
+        )]))
+        .build())
+}
+
+pub fn tracer(provider: &TracerProvider) -> Tracer {
+    provider.tracer(SERVICE_NAME)
+}
+
+/// Applies the log redaction rules to span and event attributes before the
+/// wrapped processor exports them.
+#[derive(Debug)]
+struct RedactingProcessor<P>(P);
+
+impl<P: SpanProcessor> SpanProcessor for RedactingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.0.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let redactor = redactor();
+        redact_attributes(&redactor, &mut span.attributes);
+        for event in span.events.events.iter_mut() {
+            redact_attributes(&redactor, &mut event.attributes);
+        }
+        self.0.on_end(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        self.0.shutdown()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.set_resource(resource);
+    }
+}
+
+fn redact_attributes(redactor: &Redactor, attributes: &mut [KeyValue]) {
+    for attribute in attributes {
+        let name = attribute.key.as_str();
+        if redactor.is_sensitive(name) {
+            attribute.value = Value::from(REDACTED);
+        } else if Redactor::is_coordinate(name) {
+            let coordinate = match &attribute.value {
+                Value::F64(coordinate) => Some(*coordinate),
+                Value::String(text) => text.as_str().parse().ok(),
+                _ => None,
+            };
+            if let Some(coordinate) = coordinate {
+                attribute.value = Value::F64(redactor.coarsen(coordinate));
+            }
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+struct TraceparentInjector(HashMap<String, String>);
+
+impl Injector for TraceparentInjector {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+}
+
+/// Reads the W3C `traceparent`/`tracestate` headers of an incoming request.
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+/// Returns the W3C `traceparent` of the current span, if it is being traced.
+pub fn current_traceparent() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let mut injector = TraceparentInjector(HashMap::new());
+    TraceContextPropagator::new().inject_context(&context, &mut injector);
+    injector.0.remove("traceparent")
+}
+
+/// Returns the caller's `X-Request-Id`, or a fresh one if it is missing or unusable.
+pub fn request_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+// Incoming ids end up in logs and node requests, so keep them short and printable
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Runs `future` with `request_id` as the current correlation id.
+pub async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Returns the correlation id of the request being served on this task, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
@@ -1,26 +1,125 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use opentelemetry_sdk::trace::TracerProvider;
+use std::time::Duration;
+use tracing_subscriber::field::MakeExt;
+use tracing_subscriber::fmt::format::{self, FormatFields};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
 
-use crate::hub::seq::{SeqLayer, SeqLogger};
+use crate::hub::configuration::AppConfig;
+use crate::hub::redact::{redactor, set_redactor, Redactor, REDACTED};
+use crate::hub::seq::{SeqHandle, SeqLayer, SeqOptions};
+use crate::hub::telemetry::{build_otlp_provider, tracer};
 
-pub fn setup_tracing(
-    log_level: &str,
-    seq_url: &str,
-    seq_api_key: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let seq_logger = Arc::new(Mutex::new(SeqLogger::new(seq_url, seq_api_key)));
-    let seq_layer = SeqLayer::new(seq_logger);
+/// Swaps the active log filter at runtime.
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
 
+/// Keeps the background log shippers reachable so they can be flushed on shutdown.
+pub struct TracingGuard {
+    seq: Option<SeqHandle>,
+    otlp: Option<TracerProvider>,
+    log_filter: LogFilterHandle,
+}
+
+impl TracingGuard {
+    pub fn log_filter(&self) -> LogFilterHandle {
+        self.log_filter.clone()
+    }
+
+    /// Flushes buffered log events and spans, waiting at most `deadline` for each.
+    pub async fn flush(&self, deadline: Duration) {
+        if let Some(seq) = &self.seq {
+            if !seq.flush(deadline).await {
+                eprintln!("[tracing] timed out flushing logs to Seq");
+            }
+        }
+
+        if let Some(provider) = self.otlp.clone() {
+            // The SDK flushes synchronously, so keep it off the async workers
+            let flushed = tokio::time::timeout(
+                deadline,
+                tokio::task::spawn_blocking(move || provider.force_flush()),
+            )
+            .await;
+            match flushed {
+                Ok(Ok(results)) => {
+                    for e in results.into_iter().filter_map(Result::err) {
+                        eprintln!("[tracing] failed to flush spans to OTLP collector: {}", e);
+                    }
+                }
+                _ => eprintln!("[tracing] timed out flushing spans to OTLP collector"),
+            }
+        }
+    }
+}
+
+/// Console field formatter applying the same redaction rules as the Seq layer.
+fn redacting_fields() -> impl for<'w> FormatFields<'w> + 'static {
+    format::debug_fn(|writer, field, value| {
+        let name = field.name();
+        if name == "message" {
+            return write!(writer, "{:?}", value);
+        }
+        let redactor = redactor();
+        if redactor.is_sensitive(name) {
+            return write!(writer, "{}={}", name, REDACTED);
+        }
+        if Redactor::is_coordinate(name) {
+            if let Ok(coordinate) = format!("{:?}", value).parse::<f64>() {
+                return write!(writer, "{}={}", name, redactor.coarsen(coordinate));
+            }
+        }
+        write!(writer, "{}={:?}", name, value)
+    })
+    .delimited(" ")
+}
+
+pub fn setup_tracing(config: &AppConfig) -> Result<TracingGuard, Box<dyn std::error::Error>> {
+    set_redactor(Redactor::new(
+        &config.log_redact_fields,
+        config.log_coordinate_decimals,
+    ));
+
+    let seq = if config.seq.enabled {
+        Some(SeqHandle::spawn(SeqOptions {
+            seq_url: config.seq.url.clone(),
+            api_key: config.seq.api_key.clone(),
+            buffer_capacity: config.seq.buffer_capacity,
+            batch_size: config.seq.batch_size,
+            flush_interval: Duration::from_millis(config.seq.flush_interval_ms),
+            max_retries: config.seq.max_retries,
+            connect_timeout: Duration::from_secs(config.seq.connect_timeout_secs),
+            request_timeout: Duration::from_secs(config.seq.request_timeout_secs),
+        })?)
+    } else {
+        None
+    };
+    let seq_layer = seq.clone().map(SeqLayer::new);
+
+    let otlp = match &config.otlp_endpoint {
+        Some(endpoint) => Some(build_otlp_provider(endpoint, &config.otlp_protocol)?),
+        None => None,
+    };
+    let otlp_layer = otlp
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(tracer(provider)));
+
+    // The filter sits directly on the registry so its handle type stays nameable
+    let (filter_layer, log_filter) = reload::Layer::new(EnvFilter::new(&config.log_level));
     tracing_subscriber::registry()
-        .with(fmt::layer())
+        .with(filter_layer)
+        .with(fmt::layer().fmt_fields(redacting_fields()))
         .with(seq_layer)
-        .with(EnvFilter::new(log_level))
+        .with(otlp_layer)
         .try_init()
         .or_else(|_| {
             println!("Global default trace dispatcher has already been set");
             Ok::<(), Box<dyn std::error::Error>>(())
         })?;
 
-    Ok(())
+    Ok(TracingGuard {
+        seq,
+        otlp,
+        log_filter,
+    })
 }
@@ -0,0 +1,507 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha3::{Digest, Keccak256};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Domain every typed message the hub builds is bound to.
+pub const DOMAIN_NAME: &str = "Clutch";
+pub const DOMAIN_VERSION: &str = "1";
+
+/// Fields of `EIP712Domain` in the order the standard lists them, used when
+/// a payload leaves the domain type out.
+const DOMAIN_FIELDS: &[(&str, &str)] = &[
+    ("name", "string"),
+    ("version", "string"),
+    ("chainId", "uint256"),
+    ("verifyingContract", "address"),
+    ("salt", "bytes32"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// EIP-712 structured data in the JSON shape of `eth_signTypedData_v4`, so a
+/// payload can be handed to a wallet as is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<TypedField>>,
+    pub primary_type: String,
+    pub domain: Map<String, Value>,
+    pub message: Value,
+}
+
+impl TypedData {
+    /// Typed data for an unsigned ride request as built by
+    /// `createUnsignedRideRequest`. Coordinates are carried as decimal
+    /// strings, since EIP-712 has no floating point type.
+    pub fn ride_request(unsigned: &Value) -> Result<Self, String> {
+        let field = |pointer: &str| {
+            unsigned
+                .pointer(pointer)
+                .ok_or_else(|| format!("Ride request has no {} field", pointer))
+        };
+        let call_type = field("/data/function_call_type")?;
+        if call_type != "RideRequest" {
+            return Err(format!("Transaction is a {}, not a RideRequest", call_type));
+        }
+        let location = |name: &str| -> Result<Value, String> {
+            let coordinate = |axis: &str| {
+                let value = field(&format!("/data/arguments/{}/{}", name, axis))?;
+                match value {
+                    Value::Number(number) => Ok(number.to_string()),
+                    _ => Err(format!("Ride request {}.{} must be a number", name, axis)),
+                }
+            };
+            Ok(json!({
+                "latitude": coordinate("latitude")?,
+                "longitude": coordinate("longitude")?,
+            }))
+        };
+
+        Ok(TypedData {
+            types: types(&[
+                ("EIP712Domain", &[("name", "string"), ("version", "string")]),
+                (
+                    "RideRequest",
+                    &[
+                        ("from", "string"),
+                        ("nonce", "uint256"),
+                        ("fare", "int256"),
+                        ("pickupLocation", "Location"),
+                        ("dropoffLocation", "Location"),
+                    ],
+                ),
+                ("Location", &[("latitude", "string"), ("longitude", "string")]),
+            ]),
+            primary_type: "RideRequest".to_string(),
+            domain: hub_domain(),
+            message: json!({
+                "from": field("/from")?,
+                "nonce": field("/nonce")?,
+                "fare": field("/data/arguments/fare")?,
+                "pickupLocation": location("pickup_location")?,
+                "dropoffLocation": location("dropoff_location")?,
+            }),
+        })
+    }
+
+    /// A sign-in challenge proving control of `address`, as issued by
+    /// `signInChallenge`. `issued_at` is in seconds since the epoch.
+    pub fn sign_in(address: &str, nonce: &str, issued_at: u64) -> Self {
+        TypedData {
+            types: types(&[
+                ("EIP712Domain", &[("name", "string"), ("version", "string")]),
+                (
+                    "SignIn",
+                    &[
+                        ("address", "address"),
+                        ("statement", "string"),
+                        ("nonce", "string"),
+                        ("issuedAt", "uint256"),
+                    ],
+                ),
+            ]),
+            primary_type: "SignIn".to_string(),
+            domain: hub_domain(),
+            message: json!({
+                "address": address,
+                "statement": "Sign in to Clutch",
+                "nonce": nonce,
+                "issuedAt": issued_at,
+            }),
+        }
+    }
+
+    /// The digest a wallet signs:
+    /// `keccak256(0x19 0x01 ‖ domainSeparator ‖ hashStruct(message))`.
+    pub fn signing_hash(&self) -> Result<[u8; 32], String> {
+        let mut hasher = Keccak256::new();
+        hasher.update([0x19, 0x01]);
+        hasher.update(self.domain_separator()?);
+        hasher.update(self.hash_struct(&self.primary_type, &self.message)?);
+        Ok(hasher.finalize().into())
+    }
+
+    pub fn domain_separator(&self) -> Result<[u8; 32], String> {
+        let domain = Value::Object(self.domain.clone());
+        if self.types.contains_key("EIP712Domain") {
+            return self.hash_struct("EIP712Domain", &domain);
+        }
+        let fields = DOMAIN_FIELDS
+            .iter()
+            .filter(|(name, _)| self.domain.contains_key(*name))
+            .map(|(name, kind)| TypedField {
+                name: name.to_string(),
+                kind: kind.to_string(),
+            })
+            .collect();
+        let mut with_domain = self.clone();
+        with_domain.types.insert("EIP712Domain".to_string(), fields);
+        with_domain.hash_struct("EIP712Domain", &domain)
+    }
+
+    pub fn hash_struct(&self, name: &str, value: &Value) -> Result<[u8; 32], String> {
+        let fields = self.fields(name)?;
+        let Value::Object(object) = value else {
+            return Err(format!("{} must be an object", name));
+        };
+        let mut hasher = Keccak256::new();
+        hasher.update(Keccak256::digest(self.encode_type(name)?.as_bytes()));
+        for field in fields {
+            let value = object.get(&field.name).unwrap_or(&Value::Null);
+            hasher.update(
+                self.encode_value(&field.kind, value)
+                    .map_err(|e| format!("{}.{}: {}", name, field.name, e))?,
+            );
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    /// `Name(type field,...)` followed by the referenced struct types in
+    /// alphabetical order.
+    pub fn encode_type(&self, name: &str) -> Result<String, String> {
+        let mut referenced = BTreeSet::new();
+        self.collect_references(name, &mut referenced)?;
+        referenced.remove(name);
+        std::iter::once(name)
+            .chain(referenced.iter().map(String::as_str))
+            .map(|name| {
+                let fields = self.fields(name)?;
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|field| format!("{} {}", field.kind, field.name))
+                    .collect();
+                Ok(format!("{}({})", name, fields.join(",")))
+            })
+            .collect()
+    }
+
+    fn fields(&self, name: &str) -> Result<&[TypedField], String> {
+        self.types
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| format!("Type {} is not defined", name))
+    }
+
+    fn collect_references(&self, name: &str, found: &mut BTreeSet<String>) -> Result<(), String> {
+        if !found.insert(name.to_string()) {
+            return Ok(());
+        }
+        for field in self.fields(name)? {
+            let base = field.kind.split('[').next().unwrap_or_default();
+            if self.types.contains_key(base) {
+                self.collect_references(base, found)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_value(&self, kind: &str, value: &Value) -> Result<[u8; 32], String> {
+        if let Some(open) = kind.rfind('[') {
+            let element = &kind[..open];
+            let length = kind[open + 1..]
+                .strip_suffix(']')
+                .filter(|_| !element.is_empty())
+                .ok_or_else(|| format!("{} is not a valid type", kind))?;
+            let Value::Array(items) = value else {
+                return Err(format!("expected an array for {}", kind));
+            };
+            if !length.is_empty() && length.parse::<usize>().ok() != Some(items.len()) {
+                return Err(format!("expected {} items for {}", length, kind));
+            }
+            let mut hasher = Keccak256::new();
+            for item in items {
+                hasher.update(self.encode_value(element, item)?);
+            }
+            return Ok(hasher.finalize().into());
+        }
+        if self.types.contains_key(kind) {
+            return self.hash_struct(kind, value);
+        }
+
+        let mut word = [0u8; 32];
+        match kind {
+            "string" => {
+                let text = value.as_str().ok_or("expected a string")?;
+                word = Keccak256::digest(text.as_bytes()).into();
+            }
+            "bytes" => word = Keccak256::digest(decode_hex(value)?).into(),
+            "bool" => word[31] = value.as_bool().ok_or("expected a boolean")? as u8,
+            "address" => {
+                let bytes = decode_hex(value)?;
+                if bytes.len() != 20 {
+                    return Err("expected a 20-byte address".to_string());
+                }
+                word[12..].copy_from_slice(&bytes);
+            }
+            _ => {
+                if let Some(size) = kind.strip_prefix("bytes") {
+                    let size = bit_size(size, 1, 32)?;
+                    let bytes = decode_hex(value)?;
+                    if bytes.len() != size {
+                        return Err(format!("expected {} bytes", size));
+                    }
+                    word[..size].copy_from_slice(&bytes);
+                } else if let Some(bits) = kind.strip_prefix("uint") {
+                    word = encode_integer(value, bit_size(bits, 8, 256)?, false)?;
+                } else if let Some(bits) = kind.strip_prefix("int") {
+                    word = encode_integer(value, bit_size(bits, 8, 256)?, true)?;
+                } else {
+                    return Err(format!("type {} is not supported", kind));
+                }
+            }
+        }
+        Ok(word)
+    }
+}
+
+fn hub_domain() -> Map<String, Value> {
+    let mut domain = Map::new();
+    domain.insert("name".to_string(), Value::from(DOMAIN_NAME));
+    domain.insert("version".to_string(), Value::from(DOMAIN_VERSION));
+    domain
+}
+
+fn types(definitions: &[(&str, &[(&str, &str)])]) -> BTreeMap<String, Vec<TypedField>> {
+    definitions
+        .iter()
+        .map(|(name, fields)| {
+            let fields = fields
+                .iter()
+                .map(|(name, kind)| TypedField {
+                    name: name.to_string(),
+                    kind: kind.to_string(),
+                })
+                .collect();
+            (name.to_string(), fields)
+        })
+        .collect()
+}
+
+/// Parses the size suffix of `bytesN`, `uintN` or `intN`; an empty suffix
+/// means the largest size.
+fn bit_size(suffix: &str, step: usize, max: usize) -> Result<usize, String> {
+    if suffix.is_empty() {
+        return Ok(max);
+    }
+    match suffix.parse::<usize>() {
+        Ok(size) if size > 0 && size <= max && size % step == 0 => Ok(size),
+        _ => Err(format!("size {} is not valid", suffix)),
+    }
+}
+
+fn decode_hex(value: &Value) -> Result<Vec<u8>, String> {
+    let text = value.as_str().ok_or("expected a hex string")?;
+    hex::decode(text.trim_start_matches("0x")).map_err(|_| format!("{:?} is not valid hex", text))
+}
+
+/// Big-endian two's complement word of an integer given as a JSON number
+/// or as a decimal or `0x` hex string.
+fn encode_integer(value: &Value, bits: usize, signed: bool) -> Result<[u8; 32], String> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.trim().to_string(),
+        _ => return Err("expected an integer".to_string()),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+    let (radix, digits) = match digits.strip_prefix("0x") {
+        Some(digits) => (16, digits),
+        None => (10, digits),
+    };
+    if digits.is_empty() {
+        return Err(format!("{:?} is not an integer", text));
+    }
+
+    let mut word = [0u8; 32];
+    for digit in digits.chars() {
+        let digit = digit
+            .to_digit(radix)
+            .ok_or_else(|| format!("{:?} is not an integer", text))?;
+        let mut carry = digit;
+        for byte in word.iter_mut().rev() {
+            let product = *byte as u32 * radix + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+        if carry != 0 {
+            return Err(format!("{} does not fit in 256 bits", text));
+        }
+    }
+
+    let magnitude_bits = 256 - leading_zeros(&word);
+    let is_zero = magnitude_bits == 0;
+    let fits = match (signed, negative) {
+        (false, true) => is_zero,
+        (false, false) => magnitude_bits <= bits,
+        (true, false) => magnitude_bits < bits,
+        // -2^(bits-1) is the one negative value with a `bits`-bit magnitude
+        (true, true) => {
+            magnitude_bits < bits || (magnitude_bits == bits && trailing_zeros(&word) == bits - 1)
+        }
+    };
+    if !fits {
+        return Err(format!(
+            "{} is out of range for {}int{}",
+            text,
+            if signed { "" } else { "u" },
+            bits
+        ));
+    }
+    if negative && !is_zero {
+        for byte in word.iter_mut() {
+            *byte = !*byte;
+        }
+        for byte in word.iter_mut().rev() {
+            let (sum, overflow) = byte.overflowing_add(1);
+            *byte = sum;
+            if !overflow {
+                break;
+            }
+        }
+    }
+    Ok(word)
+}
+
+fn leading_zeros(word: &[u8; 32]) -> usize {
+    let mut zeros = 0;
+    for byte in word {
+        zeros += byte.leading_zeros() as usize;
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+fn trailing_zeros(word: &[u8; 32]) -> usize {
+    let mut zeros = 0;
+    for byte in word.iter().rev() {
+        zeros += byte.trailing_zeros() as usize;
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example from the EIP-712 specification.
+    fn mail() -> TypedData {
+        serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" }
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person" },
+                    { "name": "contents", "type": "string" }
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+                "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+                "contents": "Hello, Bob!"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_specification_example() {
+        let mail = mail();
+        assert_eq!(
+            mail.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            hex::encode(mail.domain_separator().unwrap()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            hex::encode(mail.hash_struct("Mail", &mail.message).unwrap()),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            hex::encode(mail.signing_hash().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        // Leaving the domain type out infers it from the domain's fields
+        let mut inferred = mail.clone();
+        inferred.types.remove("EIP712Domain");
+        assert_eq!(inferred.signing_hash(), mail.signing_hash());
+    }
+
+    #[test]
+    fn test_sign_in_challenge_requires_an_address() {
+        let address = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+        let challenge = TypedData::sign_in(address, "7c1f", 1_700_000_000);
+        assert_eq!(
+            challenge.encode_type("SignIn").unwrap(),
+            "SignIn(address address,string statement,string nonce,uint256 issuedAt)"
+        );
+        assert!(challenge.signing_hash().is_ok());
+        let error = TypedData::sign_in("alice", "7c1f", 0).signing_hash().unwrap_err();
+        assert_eq!(error, "SignIn.address: \"alice\" is not valid hex");
+    }
+
+    #[test]
+    fn test_malformed_values_and_types_are_errors() {
+        let mut mail = mail();
+        mail.message["to"]["wallet"] = json!("alice");
+        assert_eq!(
+            mail.signing_hash().unwrap_err(),
+            "Mail.to: Person.wallet: \"alice\" is not valid hex"
+        );
+
+        for kind in ["string[", "[", "[]", "string[2"] {
+            let mut mail = self::mail();
+            mail.types.get_mut("Mail").unwrap()[2].kind = kind.to_string();
+            mail.message["contents"] = json!(["Hello, Bob!"]);
+            assert_eq!(
+                mail.signing_hash().unwrap_err(),
+                format!("Mail.contents: {} is not a valid type", kind)
+            );
+        }
+    }
+
+    #[test]
+    fn test_integers_are_range_checked() {
+        let encode = |value: Value, bits, signed| encode_integer(&value, bits, signed);
+        assert_eq!(encode(json!(255), 8, false).unwrap()[31], 0xff);
+        assert!(encode(json!(256), 8, false).is_err());
+        assert!(encode(json!(-1), 256, false).is_err());
+        assert_eq!(encode(json!(-1), 256, true).unwrap(), [0xff; 32]);
+        assert_eq!(encode(json!("-128"), 8, true).unwrap()[31], 0x80);
+        assert!(encode(json!("-129"), 8, true).is_err());
+        assert!(encode(json!("128"), 8, true).is_err());
+        assert_eq!(encode(json!("0x0100"), 16, false).unwrap()[30], 1);
+        assert!(encode(json!(1.5), 256, false).is_err());
+    }
+}
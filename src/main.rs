@@ -1,17 +1,35 @@
-pub mod hub;
-
 use clap::Parser;
-use hub::configuration::AppConfig;
-use hub::metric::serve_metrics;
+use clutch_hub_api::hub;
+use hub::configuration::{AppConfig, LiveConfig, CONFIG_DIR, DEFAULT_ENV};
+use hub::keystore::{unlock_operator_key, OperatorKey};
+use hub::metric::{poll_latest_block, serve_metrics};
+use hub::reload::ConfigReloader;
+use hub::shutdown::{wait_for_signal, Shutdown};
 use hub::tracing::setup_tracing;
+use std::time::Duration;
+use tracing::info;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     env: Option<String>,
     #[clap(index = 1)]
     env_positional: Option<String>,
+    /// Validate the configuration and exit without starting the hub
+    #[clap(long)]
+    check_config: bool,
+    #[clap(subcommand)]
+    command: Option<hub::cli::Command>,
+}
+
+/// Lists every configuration problem and exits with status 1.
+fn exit_invalid(env: &str, errors: &[String]) -> ! {
+    eprintln!("Invalid configuration for env {:?} ({} problems):", env, errors.len());
+    for error in errors {
+        eprintln!("  - {}", error);
+    }
+    std::process::exit(1);
 }
 
 #[tokio::main]
@@ -20,13 +38,112 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let env_owned = args
         .env
         .or(args.env_positional)
-        .unwrap_or_else(|| "default".to_string());
-    let config = AppConfig::load_configuration(&env_owned)?;
+        .unwrap_or_else(|| DEFAULT_ENV.to_string());
+    if let Some(command) = args.command {
+        match hub::cli::run(command, &env_owned) {
+            Ok(output) => {
+                println!("{}", output);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let config = match AppConfig::load_configuration(&env_owned) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load configuration for env {:?}: {}", env_owned, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(errors) = config.validate() {
+        exit_invalid(&env_owned, &errors);
+    }
+    // Decrypting is the only way to tell a wrong password, so --check-config does it too
+    let operator_key = match unlock_operator_key(&config.operator) {
+        Ok(keys) => keys,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if args.check_config {
+        if let Err(errors) = config.check_addresses_bindable() {
+            exit_invalid(&env_owned, &errors);
+        }
+        println!("Configuration for env {:?} is valid", env_owned);
+        return Ok(());
+    }
+
+    let tracing_guard = setup_tracing(&config)?;
+    if let Some(OperatorKey(keys)) = &operator_key {
+        info!("Operator key unlocked for address {}", keys.address_key);
+    }
+    let shutdown = Shutdown::new();
+    let grace_period = Duration::from_secs(config.shutdown_grace_period_secs);
+
+    let metrics_server = if config.metrics.enabled {
+        Some(serve_metrics(&config.metrics.addr, shutdown.clone()).await?)
+    } else {
+        None
+    };
+    let ws_manager = match hub::server::connect_websocket(&config).await {
+        Ok(ws_manager) => ws_manager,
+        Err(e) => {
+            eprintln!("Failed to set up the clutch-node connection: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if config.metrics.enabled {
+        poll_latest_block(
+            ws_manager.clone(),
+            Duration::from_secs(config.metrics.block_poll_interval_secs),
+            shutdown.clone(),
+        );
+    }
+
+    let live = LiveConfig::new(config.clone());
+    let reloader = ConfigReloader::new(
+        CONFIG_DIR,
+        &env_owned,
+        live.clone(),
+        Some(tracing_guard.log_filter()),
+    );
+    let config_watcher = reloader.watch(
+        Duration::from_secs(config.config_watch_interval_secs),
+        shutdown.clone(),
+    );
+
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutdown requested, draining in-flight requests");
+        signal_shutdown.trigger();
+    });
+
+    // Returns once the server has stopped and drained its in-flight requests
+    let served = hub::server::run_graphql_server(
+        &config.ws_addr,
+        ws_manager.clone(),
+        live,
+        operator_key,
+        shutdown.clone(),
+    )
+    .await;
 
-    setup_tracing(&config.log_level, &config.seq_url, &config.seq_api_key)?;
-    serve_metrics(&config.serve_metric_addr);
-    let ws_manager = hub::server::connect_websocket(&config.clutch_node_ws_url).await;
-    hub::server::run_graphql_server(&config.ws_addr, ws_manager, config.clone()).await?;
+    // Stop the remaining components even if the server failed on its own
+    shutdown.trigger();
+    // The stages share one grace period counted from the shutdown request
+    ws_manager.shutdown(shutdown.remaining(grace_period)).await;
+    let _ = config_watcher.await;
+    if let Some(metrics_server) = metrics_server {
+        let _ = metrics_server.await;
+    }
+    info!("Shutdown complete");
 
+    tracing_guard.flush(shutdown.remaining(grace_period)).await;
+    served?;
     Ok(())
 }
@@ -0,0 +1,246 @@
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn hub(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_clutch-hub-api"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn hub_json(args: &[&str]) -> Value {
+    let output = hub(args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn test_keygen_and_address_agree() {
+    let keys = hub_json(&["keygen"]);
+    let address = keys["address"].as_str().unwrap();
+
+    let from_secret = hub(&["address", "--secret-key", keys["secret_key"].as_str().unwrap()]);
+    assert_eq!(stdout(&from_secret), address);
+    let from_public = hub(&["address", "--public-key", keys["public_key"].as_str().unwrap()]);
+    assert_eq!(stdout(&from_public), address);
+
+    let bad = hub(&["address", "--public-key", "zz"]);
+    assert_eq!(bad.status.code(), Some(1));
+}
+
+#[test]
+fn test_message_signature_round_trip() {
+    let keys = hub_json(&["keygen"]);
+    let secret_file = std::env::temp_dir().join(format!("clutch-cli-key-{}", std::process::id()));
+    std::fs::write(&secret_file, format!("{}\n", keys["secret_key"].as_str().unwrap())).unwrap();
+
+    let signature = hub_json(&[
+        "sign",
+        "--secret-key-file",
+        secret_file.to_str().unwrap(),
+        "--message",
+        "hello clutch",
+    ]);
+    assert_eq!(signature["address"], keys["address"]);
+
+    let verify = |message: &str| {
+        hub(&[
+            "verify",
+            "--message",
+            message,
+            "--address",
+            keys["address"].as_str().unwrap(),
+            "--r",
+            signature["r"].as_str().unwrap(),
+            "--s",
+            signature["s"].as_str().unwrap(),
+            "--v",
+            &signature["v"].to_string(),
+        ])
+    };
+    assert!(verify("hello clutch").status.success());
+    assert_eq!(verify("hello clutch!").status.code(), Some(1));
+    std::fs::remove_file(secret_file).unwrap();
+}
+
+#[test]
+fn test_ride_transaction_is_signed_from_stdin() {
+    let keys = hub_json(&["keygen"]);
+    let unsigned = json!({
+        "from": keys["address"],
+        "nonce": 7,
+        "data": {
+            "function_call_type": "RideRequest",
+            "arguments": { "fare": 5, "pickup_location": { "latitude": 1.0, "longitude": 2.0 } }
+        }
+    });
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_clutch-hub-api"))
+        .args(["sign", "--secret-key", keys["secret_key"].as_str().unwrap(), "--transaction", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(unsigned.to_string().as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let signed: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let raw = signed["raw_transaction"].as_str().unwrap();
+    let decoded: Value =
+        serde_json::from_slice(&hex::decode(raw.trim_start_matches("0x")).unwrap()).unwrap();
+    assert_eq!(decoded, signed["signed_transaction"]);
+    assert_eq!(decoded["nonce"], 7);
+
+    let path = std::env::temp_dir().join(format!("clutch-cli-tx-{}.json", std::process::id()));
+    std::fs::write(&path, decoded.to_string()).unwrap();
+    assert!(hub(&["verify", "--transaction", path.to_str().unwrap()]).status.success());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_token_is_minted_with_the_configured_secret() {
+    let keys = hub_json(&["keygen"]);
+    let minted = hub_json(&[
+        "token",
+        "--env",
+        "development",
+        "--public-key",
+        keys["address"].as_str().unwrap(),
+        "--hours",
+        "2",
+    ]);
+
+    let claims = jsonwebtoken::decode::<Value>(
+        minted["token"].as_str().unwrap(),
+        &jsonwebtoken::DecodingKey::from_secret(b"development-only-jwt-secret-never-deploy-this"),
+        &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256),
+    )
+    .unwrap()
+    .claims;
+    // The token subject is the checksummed address, however it was given
+    let checksummed = minted["address"].as_str().unwrap();
+    assert_eq!(checksummed.to_ascii_lowercase(), keys["address"]);
+    assert_eq!(claims["pk"], checksummed);
+    assert_eq!(claims["exp"], minted["expires_at"]);
+
+    // The base configuration alone has no secret to sign with
+    let address = keys["address"].as_str().unwrap();
+    let output = hub(&["token", "--env", "default", "--public-key", address]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_keystore_encrypts_and_unlocks_keys() {
+    let keys = hub_json(&["keygen"]);
+    let dir = std::env::temp_dir().join(format!("clutch-cli-keystore-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let (key_file, password_file, keystore) =
+        (dir.join("key"), dir.join("password"), dir.join("key.json"));
+    std::fs::write(&key_file, keys["secret_key"].as_str().unwrap()).unwrap();
+    std::fs::write(&password_file, "correct horse\n").unwrap();
+
+    let create = [
+        "keystore",
+        "--out",
+        keystore.to_str().unwrap(),
+        "--secret-key-file",
+        key_file.to_str().unwrap(),
+        "--kdf",
+        "scrypt-light",
+        "--password-file",
+        password_file.to_str().unwrap(),
+    ];
+    let created = hub_json(&create);
+    assert_eq!(created["address"], keys["address"]);
+    // Never overwrites an existing keystore
+    assert_eq!(hub(&create).status.code(), Some(1));
+
+    let address = hub(&["address", "--keystore", keystore.to_str().unwrap()]);
+    assert_eq!(stdout(&address), keys["address"].as_str().unwrap());
+
+    let sign = |password: &str| {
+        Command::new(env!("CARGO_BIN_EXE_clutch-hub-api"))
+            .args(["sign", "--keystore", keystore.to_str().unwrap(), "--message", "hi"])
+            .env("CLUTCH_KEYSTORE_PASSWORD", password)
+            .output()
+            .unwrap()
+    };
+    let signed = sign("correct horse");
+    assert!(signed.status.success());
+    let signature: Value = serde_json::from_slice(&signed.stdout).unwrap();
+    assert_eq!(signature["address"], keys["address"]);
+
+    let wrong = sign("battery staple");
+    assert_eq!(wrong.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&wrong.stderr).contains("Wrong keystore password"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_mnemonic_accounts_are_reproducible() {
+    let generated = hub_json(&["mnemonic", "--words", "24"]);
+    let phrase = generated["mnemonic"].as_str().unwrap();
+    assert_eq!(phrase.split(' ').count(), 24);
+
+    let path = std::env::temp_dir().join(format!("clutch-cli-mnemonic-{}", std::process::id()));
+    std::fs::write(&path, format!("{}\n", phrase)).unwrap();
+    let file = path.to_str().unwrap();
+    let derived = hub_json(&["mnemonic", "--mnemonic-file", file, "--count", "2"]);
+    assert!(derived.get("mnemonic").is_none());
+    assert_eq!(derived["accounts"][0], generated["accounts"][0]);
+    assert_eq!(derived["accounts"][1]["path"], "m/44'/60'/0'/0/1");
+
+    let custom = hub_json(&["mnemonic", "--mnemonic-file", file, "--path", "m/44'/60'/0'/0/1"]);
+    assert_eq!(custom["accounts"][0], derived["accounts"][1]);
+
+    std::fs::write(&path, "test test test test test test test test test test test test").unwrap();
+    let invalid = hub(&["mnemonic", "--mnemonic-file", file]);
+    assert_eq!(invalid.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&invalid.stderr).starts_with("Invalid mnemonic"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_wallet_signatures_round_trip() {
+    let keys = hub_json(&["keygen"]);
+    let (secret_key, address) =
+        (keys["secret_key"].as_str().unwrap(), keys["address"].as_str().unwrap());
+
+    let personal = hub_json(&["sign", "--secret-key", secret_key, "--message", "hi", "--personal"]);
+    let signature = personal["signature"].as_str().unwrap();
+    let verify = |extra: &[&str]| {
+        let mut args = vec!["verify", "--message", "hi", "--address", address];
+        args.extend_from_slice(extra);
+        hub(&args).status.success()
+    };
+    assert!(verify(&["--personal", "--signature", signature]));
+    assert!(!verify(&["--signature", signature]));
+
+    let path = std::env::temp_dir().join(format!("clutch-cli-typed-{}.json", std::process::id()));
+    let typed_data = json!({
+        "types": { "SignIn": [{ "name": "nonce", "type": "string" }] },
+        "primaryType": "SignIn",
+        "domain": { "name": "Clutch", "version": "1" },
+        "message": { "nonce": "abc" }
+    });
+    std::fs::write(&path, typed_data.to_string()).unwrap();
+    let file = path.to_str().unwrap();
+    let signed = hub_json(&["sign", "--secret-key", secret_key, "--typed-data", file]);
+    let signature = signed["signature"].as_str().unwrap();
+    let verified =
+        hub(&["verify", "--typed-data", file, "--address", address, "--signature", signature]);
+    assert!(verified.status.success());
+    std::fs::remove_file(path).unwrap();
+}
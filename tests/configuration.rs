@@ -0,0 +1,356 @@
+mod support;
+
+use clutch_hub_api::hub::configuration::{AppConfig, RateLimit};
+use clutch_hub_api::hub::keystore::{Kdf, Keystore};
+use clutch_hub_api::hub::redact::Secret;
+use clutch_hub_api::hub::secrets::SecretProvider;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use support::{test_config, test_keys};
+
+const NODE_URL: &str = "ws://127.0.0.1:8081";
+
+/// Writes `files` into a fresh directory and returns its path.
+fn config_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "clutch-hub-config-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_valid_config_passes() {
+    assert_eq!(test_config(NODE_URL).validate(), Ok(()));
+}
+
+#[test]
+fn test_all_problems_are_reported_at_once() {
+    let mut config = test_config("http://127.0.0.1:8081");
+    config.ws_addr = "0.0.0.0".to_string();
+    config.seq.enabled = true;
+    config.seq.url = "not a url".to_string();
+    config.auth.jwt_secret = "short".into();
+    config.auth.jwt_expiration_hours = 0;
+    config.node.client_cert_file = Some("/nonexistent/hub.pem".to_string());
+    config.node.cert_pins = vec!["abcd".to_string()];
+    config.limits.max_body_bytes = 0;
+    config.limits.max_query_depth = 0;
+    config.limits.max_query_complexity = 10;
+    config.config_watch_interval_secs = 86_400;
+
+    let errors = config.validate().unwrap_err();
+    for field in [
+        "ws_addr",
+        "node.ws_url",
+        "seq.url",
+        "auth.jwt_secret",
+        "auth.jwt_expiration_hours",
+        "node.client_cert_file \"/nonexistent/hub.pem\"",
+        "node.client_cert_file and node.client_key_file",
+        "node.cert_pins",
+        "node.ca_file, node.client_cert_file, node.client_key_file and node.cert_pins need",
+        "limits.max_body_bytes",
+        "limits.max_query_depth",
+        "limits.max_query_complexity",
+        "config_watch_interval_secs must be between 0 and 3600",
+    ] {
+        assert!(
+            errors.iter().any(|error| error.starts_with(field)),
+            "no error for {} in {:?}",
+            field,
+            errors
+        );
+    }
+    assert_eq!(errors.len(), 13, "{:?}", errors);
+}
+
+#[test]
+fn test_disabled_sections_are_not_validated() {
+    let mut config = test_config(NODE_URL);
+    config.seq.enabled = false;
+    config.seq.url = "not a url".to_string();
+    config.metrics.enabled = false;
+    config.metrics.addr = "metrics".to_string();
+
+    assert_eq!(config.validate(), Ok(()));
+}
+
+#[test]
+fn test_environment_file_overlays_defaults() {
+    let dir = config_dir(
+        "overlay",
+        &[
+            (
+                "default.toml",
+                "log_level = \"debug\"\n[node]\nws_url = \"ws://node:8081\"\nmax_batch_size = 20\n",
+            ),
+            (
+                "staging.toml",
+                "[node]\nmax_batch_size = 5\n\
+                 [auth]\njwt_secret = \"staging-secret\"\n\
+                 [seq]\nenabled = true\n",
+            ),
+        ],
+    );
+
+    let config = AppConfig::load_from(dir.to_str().unwrap(), "staging").unwrap();
+    // Overlay values win, the base fills what the overlay leaves out
+    assert_eq!(config.node.max_batch_size, 5);
+    assert_eq!(config.node.ws_url, "ws://node:8081");
+    assert_eq!(config.log_level, "debug");
+    assert_eq!(config.auth.jwt_secret.expose(), "staging-secret");
+    assert!(config.seq.enabled);
+    // Sections missing from both files fall back to built-in defaults
+    assert!(config.metrics.enabled);
+    assert_eq!(config.metrics.addr, "0.0.0.0:3000");
+    assert_eq!(config.auth.jwt_expiration_hours, 6);
+    assert_eq!(config.cors.allowed_origins, vec!["*"]);
+
+    let base = AppConfig::load_from(dir.to_str().unwrap(), "default").unwrap();
+    assert_eq!(base.node.max_batch_size, 20);
+    assert!(!base.seq.enabled);
+    assert!(base.auth.jwt_secret.is_empty());
+
+    assert!(AppConfig::load_from(dir.to_str().unwrap(), "production").is_err());
+}
+
+#[test]
+fn test_shipped_files_only_expose_the_schema_in_development() {
+    let default = AppConfig::load_from("config", "default").unwrap();
+    assert!(!default.limits.introspection);
+    let development = AppConfig::load_from("config", "development").unwrap();
+    assert!(development.limits.introspection);
+}
+
+#[test]
+fn test_legacy_and_unknown_keys_are_rejected() {
+    let dir = config_dir(
+        "legacy",
+        &[
+            ("default.toml", "clutch_node_ws_url = \"ws://node:8081\"\nseq_url = \"http://seq\"\n"),
+            ("typo.toml", "[node]\nws_ulr = \"ws://node:8081\"\n"),
+        ],
+    );
+
+    let err = AppConfig::load_from(dir.to_str().unwrap(), "default")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("clutch_node_ws_url (or APP_CLUTCH_NODE_WS_URL)"), "{}", err);
+    assert!(err.contains("set node.ws_url instead"), "{}", err);
+    assert!(err.contains("set seq.url instead"), "{}", err);
+
+    std::fs::write(dir.join("default.toml"), "").unwrap();
+    let err = AppConfig::load_from(dir.to_str().unwrap(), "typo")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("ws_ulr"), "{}", err);
+}
+
+#[test]
+fn test_rate_limits_are_read_per_operation() {
+    let dir = config_dir(
+        "rate-limits",
+        &[
+            ("default.toml", &std::fs::read_to_string("config/default.toml").unwrap()),
+            (
+                "staging.toml",
+                "[limits]\ntrusted_proxies = [\"10.0.0.0/8\"]\n\
+                 [limits.operation_rate_limits]\n\
+                 generateToken = { per_minute = 2, burst = 1 }\n\
+                 rideRequest = { per_minute = 0, burst = 0 }\n",
+            ),
+        ],
+    );
+
+    let config = AppConfig::load_from(dir.to_str().unwrap(), "staging").unwrap();
+    let limits = &config.limits;
+    assert_eq!(limits.operation_rate_limit("generateToken"), Some(RateLimit::new(2, 1)));
+    assert_eq!(
+        limits.operation_rate_limit("sendRawTransaction"),
+        Some(RateLimit::new(60, 10))
+    );
+    assert!(!limits.operation_rate_limit("rideRequest").unwrap().is_enabled());
+    assert_eq!(limits.operation_rate_limit("userRideRequests"), None);
+    assert_eq!(limits.rate_limit, RateLimit::new(600, 100));
+    assert_eq!(limits.trusted_proxies, vec!["10.0.0.0/8"]);
+
+    let mut config = test_config(NODE_URL);
+    config.limits.trusted_proxies = vec!["nginx".to_string()];
+    config.limits.rate_limit = RateLimit::new(60, 0);
+    let errors = config.validate().unwrap_err();
+    assert!(errors[0].starts_with("limits.rate_limit.burst"), "{:?}", errors);
+    assert!(errors[1].starts_with("limits.trusted_proxies"), "{:?}", errors);
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+}
+
+/// Serves secrets from memory, standing in for a vault client.
+struct StaticSecrets(HashMap<&'static str, &'static str>);
+
+impl SecretProvider for StaticSecrets {
+    fn kind(&self) -> &'static str {
+        "env"
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Secret, String> {
+        self.0
+            .get(reference)
+            .map(|value| Secret::new(*value))
+            .ok_or_else(|| format!("{} is not set", reference))
+    }
+}
+
+#[test]
+fn test_secrets_are_read_from_files() {
+    let dir = config_dir(
+        "secret-files",
+        &[("default.toml", ""), ("jwt_secret", "secret-from-a-mounted-file-0123456789\n")],
+    );
+    let overlay = format!(
+        "[auth]\njwt_secret = \"inline\"\njwt_secret_file = {:?}\n\
+         [seq]\napi_key_file = \"/missing\"\n",
+        dir.join("jwt_secret")
+    );
+    std::fs::write(dir.join("production.toml"), overlay).unwrap();
+
+    let error = AppConfig::load_from(dir.to_str().unwrap(), "production").unwrap_err();
+    assert!(error.to_string().starts_with("seq.api_key_file: cannot read \"/missing\""));
+
+    std::fs::write(
+        dir.join("production.toml"),
+        format!("[auth]\njwt_secret_file = {:?}\n", dir.join("jwt_secret")),
+    )
+    .unwrap();
+    let config = AppConfig::load_from(dir.to_str().unwrap(), "production").unwrap();
+    assert_eq!(config.environment, "production");
+    assert_eq!(config.auth.jwt_secret.expose(), "secret-from-a-mounted-file-0123456789");
+}
+
+#[test]
+fn test_secret_providers_are_pluggable() {
+    let mut config = test_config(NODE_URL);
+    config.auth.jwt_secret_env = Some("JWT".to_string());
+    config.node.auth_token_env = Some("NODE_TOKEN".to_string());
+    config.seq.api_key_env = Some("SEQ_KEY".to_string());
+    let secrets = StaticSecrets(HashMap::from([
+        ("JWT", "jwt-secret-from-the-vault-0123456789"),
+        ("NODE_TOKEN", "node-token"),
+    ]));
+
+    let errors = config.resolve_secrets(&secrets, &secrets).unwrap_err();
+    assert_eq!(errors, vec!["seq.api_key_env: SEQ_KEY is not set"]);
+    assert_eq!(config.auth.jwt_secret.expose(), "jwt-secret-from-the-vault-0123456789");
+    assert_eq!(config.node.auth_token.unwrap().expose(), "node-token");
+}
+
+#[test]
+fn test_public_secret_is_refused_outside_development() {
+    let mut config = test_config(NODE_URL);
+    config.auth.jwt_secret = "development-only-jwt-secret-never-deploy-this".into();
+    config.environment = "development".to_string();
+    assert_eq!(config.validate(), Ok(()));
+
+    for env in ["production", "default"] {
+        config.environment = env.to_string();
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("auth.jwt_secret is a publicly known default"));
+    }
+}
+
+/// `--check-config` for the development files, on ports that are free.
+fn check_config() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_clutch-hub-api"));
+    command
+        .args(["--env", "development", "--check-config"])
+        .env("APP_WS_ADDR", "127.0.0.1:0")
+        .env("APP_METRICS__ADDR", "127.0.0.1:0");
+    command
+}
+
+#[test]
+fn test_check_config_validates_and_exits() {
+    let output = check_config().output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"development\" is valid"));
+
+    let output = check_config()
+        .env("APP_AUTH__JWT_EXPIRATION_HOURS", "0")
+        .env("APP_METRICS__ADDR", "metrics")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("(2 problems)"), "{}", stderr);
+    assert!(stderr.contains("auth.jwt_expiration_hours must be between 1 and 720, got 0"));
+    assert!(stderr.contains("metrics.addr \"metrics\" is not a valid host:port address"));
+
+    // Without --env only the base file is read, and it carries no secret
+    let output = Command::new(env!("CARGO_BIN_EXE_clutch-hub-api"))
+        .arg("--check-config")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("auth.jwt_secret must be set"));
+}
+
+#[test]
+fn test_check_config_binds_the_listen_addresses() {
+    let mut config = test_config(NODE_URL);
+    config.ws_addr = "0.0.0.0:8080".to_string();
+    config.metrics.enabled = true;
+    config.metrics.addr = "127.0.0.1:8080".to_string();
+    let errors = config.validate().unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["metrics.addr \"127.0.0.1:8080\" and ws_addr \"0.0.0.0:8080\" cannot share a port"]
+    );
+    config.metrics.addr = "127.0.0.1:0".to_string();
+    assert_eq!(config.validate(), Ok(()));
+
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = taken.local_addr().unwrap().to_string();
+    let output = check_config().env("APP_WS_ADDR", &addr).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("ws_addr {:?} cannot be bound", addr)), "{}", stderr);
+}
+
+#[test]
+fn test_operator_keystore_is_unlocked_at_startup() {
+    let dir = config_dir("operator", &[]);
+    let keys = test_keys(0);
+    let keystore = dir.join("operator.json");
+    Keystore::encrypt(&keys, "operator password", Kdf::Pbkdf2 { iterations: 1024 })
+        .unwrap()
+        .write(&keystore)
+        .unwrap();
+
+    let mut config = test_config(NODE_URL);
+    config.operator.keystore_file = Some(dir.join("missing.json").to_str().unwrap().to_string());
+    let errors = config.validate().unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].starts_with("operator.keystore_file"));
+    assert!(errors[1].starts_with("operator.keystore_password must be set"));
+
+    let check = |password: &str| {
+        check_config()
+            .env("APP_OPERATOR__KEYSTORE_FILE", &keystore)
+            .env("APP_OPERATOR__KEYSTORE_PASSWORD", password)
+            .output()
+            .unwrap()
+    };
+    assert!(check("operator password").status.success());
+    let output = check("guess");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("operator.keystore_file: Wrong keystore password"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,407 @@
+mod support;
+
+use actix_web::{test as actix_test, web, App};
+use async_graphql::{EmptySubscription, Request, Schema, Variables};
+use async_trait::async_trait;
+use clutch_hub_api::hub::clutch_node_client::types::{
+    Block, SendRawTransactionResponse, Transaction,
+};
+use clutch_hub_api::hub::clutch_node_client::NodeApi;
+use clutch_hub_api::hub::graphql::{build_schema, Mutation, Query};
+use clutch_hub_api::hub::graphql::handler::graphql_handler;
+use clutch_hub_api::hub::graphql::types::AuthUser;
+use clutch_hub_api::hub::keystore::OperatorKey;
+use clutch_hub_api::hub::signature_keys::SignatureKeys;
+use clutch_hub_api::hub::typed_data::TypedData;
+use serde_json::json;
+use std::sync::Arc;
+use support::{
+    connect, fast_options, test_config, test_keys, wallet_signature, MockNode, Reply,
+};
+
+const USER: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+
+fn authenticated(query: &str) -> Request {
+    Request::new(query).data(AuthUser {
+        address: USER.parse().unwrap(),
+    })
+}
+
+const CHALLENGE_QUERY: &str = "query($address: String!) { signInChallenge(address: $address) }";
+
+const TOKEN_MUTATION: &str = "mutation($challenge: JSON!, $signature: String!) {
+    generateToken(challenge: $challenge, signature: $signature) { token expiresAt address }
+}";
+
+/// Fetches a sign-in challenge for `identity` as a client would.
+async fn challenge(
+    schema: &Schema<Query, Mutation, EmptySubscription>,
+    identity: &str,
+) -> Result<TypedData, String> {
+    let request = Request::new(CHALLENGE_QUERY)
+        .variables(Variables::from_json(json!({ "address": identity })));
+    let response = schema.execute(request).await;
+    if let Some(error) = response.errors.first() {
+        return Err(error.message.clone());
+    }
+    let data = response.data.into_json().unwrap();
+    Ok(serde_json::from_value(data["signInChallenge"].clone()).unwrap())
+}
+
+async fn generate_token(
+    schema: &Schema<Query, Mutation, EmptySubscription>,
+    challenge: &TypedData,
+    signature: &str,
+) -> Result<serde_json::Value, String> {
+    let request = Request::new(TOKEN_MUTATION).variables(Variables::from_json(
+        json!({ "challenge": challenge, "signature": signature }),
+    ));
+    let response = schema.execute(request).await;
+    if let Some(error) = response.errors.first() {
+        return Err(error.message.clone());
+    }
+    Ok(response.data.into_json().unwrap()["generateToken"].clone())
+}
+
+#[tokio::test]
+async fn test_generate_token_for_a_signed_challenge() {
+    let keys = test_keys(0);
+    let schema = build_schema(Arc::new(FakeNode { nonce: Ok(0) }), test_config("ws://unused"));
+
+    let challenge = challenge(&schema, &keys.address_key).await.unwrap();
+    assert_eq!(challenge.primary_type, "SignIn");
+    let token = generate_token(&schema, &challenge, &wallet_signature(&keys, &challenge))
+        .await
+        .unwrap();
+    assert!(!token["token"].as_str().unwrap().is_empty());
+    assert_eq!(token["address"], "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+
+    // Someone else's signature over the same challenge proves nothing
+    let error = generate_token(&schema, &challenge, &wallet_signature(&test_keys(1), &challenge))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error,
+        "Failed to generate token: Sign-in challenge was not signed by \
+         0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+    );
+
+    // Nor does a challenge the hub did not issue, even if its owner signed it
+    let issued_at = challenge.message["issuedAt"].as_u64().unwrap();
+    let forged = TypedData::sign_in(&keys.address_key, "00", issued_at);
+    let error = generate_token(&schema, &forged, &wallet_signature(&keys, &forged))
+        .await
+        .unwrap_err();
+    assert!(error.ends_with("Sign-in challenge was not issued by this hub"), "{}", error);
+
+    // The signature covers the whole typed data, not just the message
+    let mut elsewhere = challenge.clone();
+    elsewhere.domain.insert("name".to_string(), json!("Elsewhere"));
+    let error = generate_token(&schema, &challenge, &wallet_signature(&keys, &elsewhere))
+        .await
+        .unwrap_err();
+    assert!(error.contains("was not signed by"), "{}", error);
+    let error = generate_token(&schema, &challenge, "0x1234").await.unwrap_err();
+    assert!(error.contains("Signature must be 65 bytes"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_token_subject_is_the_checksummed_address() {
+    let keys = test_keys(0);
+    let schema = build_schema(Arc::new(FakeNode { nonce: Ok(0) }), test_config("ws://unused"));
+
+    // The public key, the lowercase address and the checksummed one are one user
+    for identity in [
+        keys.public_key.clone(),
+        keys.address_key.clone(),
+        "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".to_string(),
+    ] {
+        let challenge = challenge(&schema, &identity).await.unwrap();
+        assert_eq!(challenge.message["address"], "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+        let token = generate_token(&schema, &challenge, &wallet_signature(&keys, &challenge))
+            .await
+            .unwrap();
+        assert_eq!(token["address"], "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+    }
+
+    // A mixed-case address with a broken checksum is a typo, not a user
+    let error = challenge(&schema, "0xF39Fd6e51aad88F6F4ce6aB8827279cffFb92266")
+        .await
+        .unwrap_err();
+    assert!(error.contains("checksum"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_ride_request_requires_authentication() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let response = schema
+        .execute(
+            "mutation { createUnsignedRideRequest(pickupLatitude: 1, pickupLongitude: 2, \
+             dropoffLatitude: 3, dropoffLongitude: 4, fare: 100) }",
+        )
+        .await;
+    assert_eq!(response.errors.len(), 1);
+    assert!(response.errors[0].message.contains("Unauthorized"));
+    assert!(node.frames().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_ride_request_uses_node_nonce() {
+    let node = MockNode::start().await;
+    node.respond_with("get_next_nonce", |params| {
+        assert_eq!(params["address"], USER);
+        Reply::Result(json!({ "nonce": 17 }))
+    })
+    .await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let response = schema
+        .execute(authenticated(
+            "mutation { createUnsignedRideRequest(pickupLatitude: 1.5, pickupLongitude: 2.5, \
+             dropoffLatitude: 3.5, dropoffLongitude: 4.5, fare: 100) }",
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let tx = &response.data.into_json().unwrap()["createUnsignedRideRequest"];
+    assert_eq!(tx["from"], USER);
+    assert_eq!(tx["nonce"], 17);
+    assert_eq!(tx["data"]["function_call_type"], "RideRequest");
+    assert_eq!(tx["data"]["arguments"]["fare"], 100);
+    assert_eq!(tx["data"]["arguments"]["pickup_location"]["latitude"], 1.5);
+}
+
+#[tokio::test]
+async fn test_ride_request_can_be_signed_by_a_wallet() {
+    let keys = test_keys(0);
+    let node = MockNode::start().await;
+    node.respond_with("get_next_nonce", |_| Reply::Result(json!({ "nonce": 2 })))
+        .await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let response = schema
+        .execute(
+            Request::new(
+                "mutation { createUnsignedRideRequest(pickupLatitude: 1.5, pickupLongitude: 2.5, \
+                 dropoffLatitude: 3.5, dropoffLongitude: 4.5, fare: 100) }",
+            )
+            .data(AuthUser {
+                address: keys.address_key.parse().unwrap(),
+            }),
+        )
+        .await;
+    let mut tx = response.data.into_json().unwrap()["createUnsignedRideRequest"].clone();
+
+    let response = schema
+        .execute(
+            Request::new("query($tx: JSON!) { rideRequestTypedData(transaction: $tx) }")
+                .variables(Variables::from_json(json!({ "tx": tx }))),
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let typed_data = response.data.into_json().unwrap()["rideRequestTypedData"].clone();
+    assert_eq!(typed_data["primaryType"], "RideRequest");
+    assert_eq!(typed_data["message"]["pickupLocation"]["latitude"], "1.5");
+
+    // What a wallet returns for eth_signTypedData_v4, attached to the transaction
+    let typed_data: TypedData = serde_json::from_value(typed_data).unwrap();
+    let (r, s, v) = SignatureKeys::sign_typed_data(&keys.secret_key, &typed_data).unwrap();
+    tx["signature_r"] = json!(r);
+    tx["signature_s"] = json!(s);
+    tx["signature_v"] = json!(v);
+    tx["signature_scheme"] = json!("eip712");
+    assert!(SignatureKeys::verify_transaction(&tx).unwrap());
+
+    tx["nonce"] = json!(3);
+    assert!(!SignatureKeys::verify_transaction(&tx).unwrap());
+}
+
+#[tokio::test]
+async fn test_send_raw_transaction_forwards_prefixed_payload() {
+    let node = MockNode::start().await;
+    node.respond_with("send_raw_transaction", |params| {
+        Reply::Result(json!({ "accepted": params }))
+    })
+    .await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let response = schema
+        .execute(authenticated(
+            r#"mutation { sendRawTransaction(rawTransaction: "deadbeef") }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["sendRawTransaction"]["accepted"], "0xdeadbeef");
+}
+
+#[tokio::test]
+async fn test_send_raw_transaction_passes_a_bare_hash_through() {
+    let node = MockNode::start().await;
+    node.respond("send_raw_transaction", json!("0xabc123")).await;
+    let client = connect(&node, fast_options()).await;
+    let submitted = client.send_raw_transaction("0x01").await.unwrap();
+    assert_eq!(submitted.tx_hash(), Some("0xabc123"));
+
+    let schema = build_schema(client, test_config(&node.url()));
+    let response = schema
+        .execute(authenticated(
+            r#"mutation { sendRawTransaction(rawTransaction: "0x01") }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["sendRawTransaction"], "0xabc123");
+}
+
+#[tokio::test]
+async fn test_send_raw_transaction_reports_node_error() {
+    let node = MockNode::start().await;
+    node.respond_error("send_raw_transaction", -32000, "nonce too low")
+        .await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let response = schema
+        .execute(authenticated(
+            r#"mutation { sendRawTransaction(rawTransaction: "0x01") }"#,
+        ))
+        .await;
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(
+        response.errors[0].message,
+        "Failed to send transaction: nonce too low"
+    );
+}
+
+/// In-memory node used to exercise resolvers without a WebSocket.
+struct FakeNode {
+    nonce: Result<u64, String>,
+}
+
+#[async_trait]
+impl NodeApi for FakeNode {
+    async fn get_next_nonce(&self, _address: &str) -> Result<u64, String> {
+        self.nonce.clone()
+    }
+
+    async fn send_raw_transaction(
+        &self,
+        _raw_transaction: &str,
+    ) -> Result<SendRawTransactionResponse, String> {
+        Err("not used".to_string())
+    }
+
+    async fn get_latest_block(&self) -> Result<Block, String> {
+        Err("not used".to_string())
+    }
+
+    async fn get_block_by_index(&self, _index: u64) -> Result<Block, String> {
+        Err("not used".to_string())
+    }
+
+    async fn get_transaction(&self, _hash: &str) -> Result<Transaction, String> {
+        Err("not used".to_string())
+    }
+
+    async fn get_balance(&self, _address: &str) -> Result<u64, String> {
+        Err("not used".to_string())
+    }
+}
+
+#[tokio::test]
+async fn test_ride_request_against_fake_node() {
+    let ride = "mutation { createUnsignedRideRequest(pickupLatitude: 1, pickupLongitude: 2, \
+                dropoffLatitude: 3, dropoffLongitude: 4, fare: 100) }";
+
+    let schema = build_schema(Arc::new(FakeNode { nonce: Ok(4) }), test_config("ws://unused"));
+    let response = schema.execute(authenticated(ride)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["createUnsignedRideRequest"]["nonce"], 4);
+
+    let failing = FakeNode {
+        nonce: Err("node unavailable".to_string()),
+    };
+    let schema = build_schema(Arc::new(failing), test_config("ws://unused"));
+    let response = schema.execute(authenticated(ride)).await;
+    assert_eq!(response.errors[0].message, "Failed to get nonce: node unavailable");
+}
+
+#[tokio::test]
+async fn test_deep_and_complex_queries_are_rejected_before_execution() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+    let client = connect(&node, fast_options()).await;
+    let mut config = test_config(&node.url());
+    config.limits.max_query_depth = 1;
+    let schema = build_schema(client, config);
+
+    let response = schema.execute("{ rideRequest { pickupLocation } }").await;
+    assert_eq!(response.errors.len(), 1);
+    assert!(response.errors[0].message.contains("too deep"), "{:?}", response.errors);
+
+    // Each node-backed field costs NODE_FIELD_COMPLEXITY, so aliases are bounded
+    let ride = "createUnsignedRideRequest(pickupLatitude: 1, pickupLongitude: 2, \
+                dropoffLatitude: 3, dropoffLongitude: 4, fare: 100)";
+    let aliased: Vec<String> = (0..6).map(|i| format!("r{}: {}", i, ride)).collect();
+    let response = schema
+        .execute(authenticated(&format!("mutation {{ {} }}", aliased.join(" "))))
+        .await;
+    assert_eq!(response.errors.len(), 1);
+    assert!(response.errors[0].message.contains("too complex"), "{:?}", response.errors);
+    assert!(node.frames().await.is_empty());
+
+    let response = schema
+        .execute(authenticated(&format!("mutation {{ {} }}", aliased[..5].join(" "))))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+}
+
+#[tokio::test]
+async fn test_introspection_can_be_disabled() {
+    let introspect = "{ __schema { queryType { name } } }";
+    let mut config = test_config("ws://unused");
+    config.limits.introspection = true;
+
+    let schema = build_schema(Arc::new(FakeNode { nonce: Ok(0) }), config.clone());
+    let response = schema.execute(introspect).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["__schema"]["queryType"]["name"], "Query");
+
+    config.limits.introspection = false;
+    let schema = build_schema(Arc::new(FakeNode { nonce: Ok(0) }), config);
+    let response = schema.execute(introspect).await;
+    assert!(response.data.into_json().unwrap()["__schema"].is_null());
+}
+
+#[actix_web::test]
+async fn test_operator_key_reaches_resolvers() {
+    let config = test_config("ws://unused");
+    let schema = build_schema(Arc::new(FakeNode { nonce: Ok(0) }), config.clone());
+    for operator_key in [None, Some(OperatorKey(Arc::new(test_keys(1))))] {
+        let expected = operator_key.as_ref().map(|OperatorKey(keys)| keys.address_key.clone());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(schema.clone()))
+                .configure(|app| {
+                    if let Some(operator_key) = operator_key {
+                        app.app_data(web::Data::new(operator_key));
+                    }
+                })
+                .service(web::resource("/graphql").route(web::post().to(graphql_handler))),
+        )
+        .await;
+        let request = actix_test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({ "query": "{ operatorAddress }" }))
+            .to_request();
+        let response: serde_json::Value = actix_test::call_and_read_body_json(&app, request).await;
+        assert_eq!(response["data"]["operatorAddress"], json!(expected));
+    }
+}
@@ -0,0 +1,121 @@
+mod support;
+
+use actix_web::{test, web, App};
+use clutch_hub_api::hub::clutch_node_client::ClutchNodeClient;
+use clutch_hub_api::hub::configuration::{AppConfig, LiveConfig};
+use clutch_hub_api::hub::health::{livez, readyz};
+use clutch_hub_api::hub::shutdown::Shutdown;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use support::{connect, fast_options, test_config, MockNode};
+
+async fn probe(client: Arc<ClutchNodeClient>, config: LiveConfig, path: &str) -> (u16, Value) {
+    probe_draining(client, config, Shutdown::new(), path).await
+}
+
+async fn probe_draining(
+    client: Arc<ClutchNodeClient>,
+    config: LiveConfig,
+    shutdown: Shutdown,
+    path: &str,
+) -> (u16, Value) {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(shutdown))
+            .app_data(web::Data::new(client))
+            .service(web::resource("/livez").route(web::get().to(livez)))
+            .service(web::resource("/readyz").route(web::get().to(readyz))),
+    )
+    .await;
+    let response = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+    let status = response.status().as_u16();
+    (status, test::read_body_json(response).await)
+}
+
+#[actix_web::test]
+async fn test_ready_while_node_is_connected() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+
+    let (status, body) = probe(client, LiveConfig::new(test_config(&node.url())), "/readyz").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "up");
+    assert_eq!(body["components"]["node"]["status"], "up");
+    assert_eq!(body["components"]["config"]["status"], "up");
+    assert_eq!(body["components"]["workers"]["status"], "up");
+}
+
+#[actix_web::test]
+async fn test_not_ready_without_node_but_still_live() {
+    let node = MockNode::start().await;
+    let config = LiveConfig::new(test_config(&node.url()));
+    let client = connect(&node, fast_options()).await;
+    drop(node);
+    for _ in 0..100 {
+        if !client.is_connected().await {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let (status, body) = probe(client.clone(), config.clone(), "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["components"]["node"]["status"], "down");
+
+    let (status, body) = probe(client, config, "/livez").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "up");
+}
+
+#[actix_web::test]
+async fn test_rejected_reload_makes_hub_unready() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+    let live = LiveConfig::new(test_config(&node.url()));
+    live.set_reload_errors(vec![
+        "otlp_protocol must be http or grpc".to_string(),
+        "node cannot change without a restart".to_string(),
+    ]);
+
+    let (status, body) = probe(client, live, "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["components"]["node"]["status"], "up");
+    assert_eq!(body["components"]["config"]["status"], "down");
+    assert_eq!(body["components"]["config"]["errors"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn test_draining_hub_is_unready_but_live() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+    let config = LiveConfig::new(test_config(&node.url()));
+    let shutdown = Shutdown::new();
+    shutdown.trigger();
+
+    let (status, body) =
+        probe_draining(client.clone(), config.clone(), shutdown.clone(), "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["components"]["node"]["status"], "up");
+    assert_eq!(body["components"]["shutdown"]["status"], "down");
+
+    let (status, _) = probe_draining(client, config, shutdown, "/livez").await;
+    assert_eq!(status, 200);
+}
+
+#[actix_web::test]
+async fn test_container_probes_target_the_hub_port() {
+    let root = env!("CARGO_MANIFEST_DIR");
+    let config = AppConfig::load_from(&format!("{}/config", root), "default").unwrap();
+    let port = |addr: &str| addr.rsplit(':').next().unwrap().to_string();
+    let probe = format!("http://127.0.0.1:{}/readyz", port(&config.ws_addr));
+    // /readyz is served next to /graphql, not by the metrics server
+    assert_ne!(port(&config.ws_addr), port(&config.metrics.addr));
+
+    for file in ["Dockerfile", "docker-compose.yml"] {
+        let contents = std::fs::read_to_string(format!("{}/{}", root, file)).unwrap();
+        assert!(contents.contains(&probe), "{} does not probe {}", file, probe);
+    }
+}
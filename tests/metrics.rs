@@ -0,0 +1,98 @@
+mod support;
+
+use async_graphql::Request;
+use clutch_hub_api::hub::graphql::build_schema;
+use clutch_hub_api::hub::metric::{
+    metrics_handler, poll_latest_block, serve_metrics, LATEST_BLOCK_INDEX,
+};
+use clutch_hub_api::hub::shutdown::Shutdown;
+use serde_json::json;
+use std::time::Duration;
+use support::{connect, fast_options, test_config, MockNode};
+
+#[tokio::test]
+async fn test_block_gauges_follow_node() {
+    let node = MockNode::start().await;
+    node.respond("get_latest_block", json!({ "index": 77, "hash": "0xbeef" }))
+        .await;
+    let client = connect(&node, fast_options()).await;
+
+    let shutdown = Shutdown::new();
+    poll_latest_block(client, Duration::from_millis(20), shutdown.clone());
+    for _ in 0..100 {
+        if LATEST_BLOCK_INDEX.get() == 77 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The poller stops with the hub
+    shutdown.trigger();
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    let polled = node.frames().await.len();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(node.frames().await.len(), polled);
+
+    let exposition = metrics_handler().await;
+    assert!(exposition.contains("latest_block_index 77"), "{}", exposition);
+    assert!(exposition.contains(r#"latest_block{block_hash="0xbeef"} 77"#));
+    assert!(exposition
+        .contains(r#"node_rpc_requests_total{method="get_latest_block",outcome="ok"}"#));
+}
+
+#[tokio::test]
+async fn test_graphql_operations_are_counted() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let query = r#"mutation IssueToken {
+        generateToken(challenge: {}, signature: "bad") { token }
+    }"#;
+    schema
+        .execute(Request::new(query).operation_name("IssueToken"))
+        .await;
+
+    let exposition = metrics_handler().await;
+    assert!(exposition
+        .contains(r#"graphql_operations_total{operation="generateToken",outcome="error"}"#));
+    assert!(exposition.contains("graphql_operation_duration_seconds_bucket"));
+    assert!(exposition.contains("jwt_issue_failures_total"));
+}
+
+#[tokio::test]
+async fn test_operation_names_do_not_add_series() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    for i in 0..50 {
+        let query = format!("query Probe{} {{ __typename }}", i);
+        schema.execute(Request::new(query)).await;
+    }
+    schema.execute("{ a: __typename b: __typename }").await;
+    schema.execute("{ __typename ...on Query { __schema { queryType { name } } } }").await;
+
+    let exposition = metrics_handler().await;
+    assert!(!exposition.contains("Probe"), "{}", exposition);
+    for operation in ["__typename", "multiple"] {
+        let series =
+            format!(r#"graphql_operations_total{{operation="{}",outcome="ok"}}"#, operation);
+        assert!(exposition.contains(&series), "{}", exposition);
+    }
+    // One series per root field and outcome, whatever the operations were called
+    let series = exposition
+        .lines()
+        .filter(|line| line.starts_with("graphql_operations_total{"))
+        .count();
+    assert!(series <= 6, "{}", exposition);
+}
+
+#[tokio::test]
+async fn test_metrics_bind_failure_is_an_error() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = taken.local_addr().unwrap().to_string();
+
+    let error = serve_metrics(&addr, Shutdown::new()).await.unwrap_err();
+    assert!(error.starts_with(&format!("Failed to bind metrics server to {}", addr)));
+}
@@ -0,0 +1,282 @@
+mod support;
+
+use clutch_hub_api::hub::clutch_node_client::{
+    ClientOptions, ClutchNodeClient, NodeApi, TlsOptions,
+};
+use serde_json::{json, Value};
+use std::time::Duration;
+use support::{connect, fast_options, wait_until_connected, MockNode, Reply};
+
+#[tokio::test]
+async fn test_send_request_returns_canned_result() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 42 })).await;
+    let client = connect(&node, fast_options()).await;
+
+    let result = client
+        .send_request("get_next_nonce", json!({ "address": "0xabc" }))
+        .await
+        .unwrap();
+    assert_eq!(result["nonce"], 42);
+    assert_eq!(client.get_next_nonce("0xabc").await.unwrap(), 42);
+}
+
+#[tokio::test]
+async fn test_node_error_is_surfaced() {
+    let node = MockNode::start().await;
+    node.respond_error("get_next_nonce", -32000, "unknown account").await;
+    let client = connect(&node, fast_options()).await;
+
+    let err = client
+        .send_request("get_next_nonce", json!({ "address": "0xabc" }))
+        .await
+        .unwrap_err();
+    assert_eq!(err, "unknown account");
+}
+
+#[tokio::test]
+async fn test_programmable_response_sees_params() {
+    let node = MockNode::start().await;
+    node.respond_with("get_next_nonce", |params| {
+        let address = params["address"].as_str().unwrap_or_default();
+        Reply::Result(json!({ "nonce": address.len() }))
+    })
+    .await;
+    let client = connect(&node, fast_options()).await;
+
+    assert_eq!(client.get_next_nonce("0x1234").await.unwrap(), 6);
+}
+
+#[tokio::test]
+async fn test_delayed_reply_times_out() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+    node.delay("get_next_nonce", Duration::from_secs(2)).await;
+    let client = connect(&node, fast_options()).await;
+
+    let err = client
+        .send_request("get_next_nonce", json!({ "address": "0xabc" }))
+        .await
+        .unwrap_err();
+    assert_eq!(err, "Request timed out");
+}
+
+#[tokio::test]
+async fn test_silent_node_times_out() {
+    let node = MockNode::start().await;
+    node.respond_with("get_next_nonce", |_| Reply::Silence).await;
+    let client = connect(&node, fast_options()).await;
+
+    let err = client
+        .send_request("get_next_nonce", json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(err, "Request timed out");
+}
+
+#[tokio::test]
+async fn test_batch_is_sent_as_one_frame() {
+    let node = MockNode::start().await;
+    node.respond_with("get_next_nonce", |params| {
+        Reply::Result(json!({ "nonce": params["address"] }))
+    })
+    .await;
+    let client = connect(&node, fast_options()).await;
+
+    let results = client
+        .send_batch(vec![
+            ("get_next_nonce", json!({ "address": "a" })),
+            ("get_next_nonce", json!({ "address": "b" })),
+            ("unknown_method", json!({})),
+        ])
+        .await;
+
+    assert_eq!(results[0].as_ref().unwrap()["nonce"], "a");
+    assert_eq!(results[1].as_ref().unwrap()["nonce"], "b");
+    assert!(results[2].as_ref().unwrap_err().contains("Method not found"));
+
+    let frames = node.frames().await;
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].as_array().map(Vec::len), Some(3));
+}
+
+#[tokio::test]
+async fn test_auto_batch_coalesces_concurrent_requests() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 5 })).await;
+    let options = ClientOptions {
+        auto_batch: true,
+        ..fast_options()
+    };
+    let client = connect(&node, options).await;
+
+    let (a, b, c) = tokio::join!(
+        client.get_next_nonce("a"),
+        client.get_next_nonce("b"),
+        client.get_next_nonce("c"),
+    );
+    assert_eq!((a.unwrap(), b.unwrap(), c.unwrap()), (5, 5, 5));
+
+    let frames = node.frames().await;
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].as_array().map(Vec::len), Some(3));
+}
+
+#[tokio::test]
+async fn test_dropped_connection_fails_pending_and_reconnects() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 9 })).await;
+    node.delay("get_next_nonce", Duration::from_millis(300)).await;
+    let client = connect(&node, fast_options()).await;
+
+    let pending = {
+        let client = client.clone();
+        tokio::spawn(async move { client.send_request("get_next_nonce", json!({})).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    node.drop_connections();
+
+    let err = pending.await.unwrap().unwrap_err();
+    assert_eq!(err, "Connection lost before receiving response");
+
+    // The client reconnects on its own and serves new requests
+    node.delay("get_next_nonce", Duration::ZERO).await;
+    for _ in 0..100 {
+        if node.connection_count() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    wait_until_connected(&client).await;
+    assert_eq!(node.connection_count(), 2);
+    assert_eq!(client.get_next_nonce("0xabc").await.unwrap(), 9);
+}
+
+#[tokio::test]
+async fn test_requests_fail_fast_while_disconnected() {
+    let node = MockNode::start().await;
+    let url = node.url();
+    drop(node);
+
+    let client = ClutchNodeClient::with_options(url, fast_options()).unwrap();
+    let err = client
+        .send_request("get_next_nonce", json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(err, "WebSocket connection not established");
+}
+
+#[tokio::test]
+async fn test_notifications_do_not_disturb_requests() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 3 })).await;
+    let client = connect(&node, fast_options()).await;
+
+    node.notify(json!({ "jsonrpc": "2.0", "method": "new_block", "params": { "index": 1 } }));
+    node.notify(Value::String("garbage".to_string()));
+    assert_eq!(client.get_next_nonce("0xabc").await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_typed_methods_send_expected_params() {
+    let node = MockNode::start().await;
+    node.respond_with("get_block_by_index", |params| {
+        Reply::Result(json!({
+            "index": params["index"],
+            "hash": "0xblock",
+            "previous_hash": "0xparent",
+            "timestamp": 1700000000,
+            "transactions": [{ "hash": "0xtx", "from": "0xabc", "nonce": 2, "data": {} }],
+        }))
+    })
+    .await;
+    node.respond("get_balance", json!({ "balance": 1000 })).await;
+    node.respond_with("send_raw_transaction", |params| {
+        Reply::Result(json!({ "tx_hash": "0xtx", "echo": params }))
+    })
+    .await;
+    let client = connect(&node, fast_options()).await;
+
+    let block = client.get_block_by_index(12).await.unwrap();
+    assert_eq!(block.index, 12);
+    assert_eq!(block.transactions[0].nonce, 2);
+    assert_eq!(client.get_balance("0xabc").await.unwrap(), 1000);
+
+    // Raw transactions are sent as a bare string, not wrapped in an object
+    let submitted = client.send_raw_transaction("0xdead").await.unwrap();
+    assert_eq!(submitted.tx_hash(), Some("0xtx"));
+    assert_eq!(serde_json::to_value(&submitted).unwrap()["echo"], "0xdead");
+}
+
+#[tokio::test]
+async fn test_typed_method_rejects_malformed_result() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "count": 1 })).await;
+    let client = connect(&node, fast_options()).await;
+
+    let err = client.get_next_nonce("0xabc").await.unwrap_err();
+    assert!(err.starts_with("Unexpected get_next_nonce response from node"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_unanswered_pings_force_a_reconnect() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 5 })).await;
+    let options = ClientOptions {
+        ping_interval: Duration::from_millis(50),
+        pong_timeout: Duration::from_millis(100),
+        ..fast_options()
+    };
+    let client = connect(&node, options).await;
+
+    // The socket stays open, only the pongs stop
+    node.stall_connections();
+    for _ in 0..200 {
+        if node.connection_count() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(node.connection_count(), 2);
+    wait_until_connected(&client).await;
+    assert_eq!(client.get_next_nonce("0xabc").await.unwrap(), 5);
+}
+
+#[tokio::test]
+async fn test_connect_gives_up_on_a_blackholed_node() {
+    // Accepts TCP connections but never answers the WebSocket handshake
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let options = ClientOptions {
+        connect_timeout: Duration::from_millis(100),
+        ..fast_options()
+    };
+    let _client = ClutchNodeClient::with_options(url, options).unwrap();
+
+    let mut held = Vec::new();
+    for _ in 0..2 {
+        let accepted = tokio::time::timeout(Duration::from_secs(2), listener.accept()).await;
+        held.push(accepted.expect("client stopped retrying").unwrap());
+    }
+}
+
+#[tokio::test]
+async fn test_unusable_tls_settings_fail_at_construction() {
+    let options = ClientOptions {
+        tls: TlsOptions {
+            ca_file: Some("/nonexistent/ca.pem".to_string()),
+            ..TlsOptions::default()
+        },
+        ..fast_options()
+    };
+    let err = ClutchNodeClient::with_options("wss://127.0.0.1:1".to_string(), options.clone())
+        .err()
+        .unwrap();
+    assert!(err.contains("/nonexistent/ca.pem"), "{}", err);
+
+    // Pins on a plain ws:// URL would never be checked
+    let err = ClutchNodeClient::with_options("ws://127.0.0.1:1".to_string(), options)
+        .err()
+        .unwrap();
+    assert!(err.contains("wss://"), "{}", err);
+}
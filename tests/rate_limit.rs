@@ -0,0 +1,256 @@
+mod support;
+
+use actix_web::{test, web, App};
+use clutch_hub_api::hub::auth::generate_jwt_token;
+use clutch_hub_api::hub::configuration::{AppConfig, LiveConfig, RateLimit};
+use clutch_hub_api::hub::graphql::build_schema;
+use clutch_hub_api::hub::graphql::handler::graphql_handler;
+use clutch_hub_api::hub::rate_limit::RateLimiter;
+use serde_json::{json, Value};
+use support::{connect, fast_options, test_config, test_keys, MockNode};
+
+const USER: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+
+/// A `generateToken` field that signs in the first fixture account.
+fn token_field() -> String {
+    format!("{} {{ token }}", support::token_field(&test_keys(0), &test_config("ws://unused")))
+}
+
+const RIDE_FIELD: &str = "createUnsignedRideRequest(pickupLatitude: 1, pickupLongitude: 2, \
+                          dropoffLatitude: 3, dropoffLongitude: 4, fare: 100)";
+
+struct Reply {
+    status: u16,
+    retry_after: Option<String>,
+    body: Value,
+}
+
+fn limited_config(node: &MockNode) -> AppConfig {
+    let mut config = test_config(&node.url());
+    config.limits.rate_limit = RateLimit::new(60, 20);
+    let operations = &mut config.limits.operation_rate_limits;
+    operations.insert("generateToken".to_string(), RateLimit::new(6, 2));
+    operations.insert("createUnsignedRideRequest".to_string(), RateLimit::new(6, 1));
+    config.limits.trusted_proxies = vec!["10.0.0.0/8".to_string()];
+    config
+}
+
+/// Sends `requests` in order to one rate-limited app. `reload` is applied to
+/// the live configuration after the request with that index.
+async fn send_all(
+    node: &MockNode,
+    live: LiveConfig,
+    requests: Vec<test::TestRequest>,
+    reload: Option<(usize, AppConfig)>,
+) -> Vec<Reply> {
+    let config = (*live.current()).clone();
+    let client = connect(node, fast_options()).await;
+    let schema = build_schema(client, live.clone());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::PayloadConfig::new(config.limits.max_body_bytes))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(schema))
+            .service(
+                web::resource("/graphql")
+                    .wrap(RateLimiter::new(live.clone()))
+                    .route(web::post().to(graphql_handler)),
+            ),
+    )
+    .await;
+
+    let mut replies = Vec::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        let response = test::call_service(&app, request.to_request()).await;
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .map(|value| value.to_str().unwrap().to_string());
+        replies.push(Reply {
+            status: response.status().as_u16(),
+            retry_after,
+            body: serde_json::from_slice(&test::read_body(response).await).unwrap_or_default(),
+        });
+        if let Some((_, config)) = reload.as_ref().filter(|(after, _)| *after == index) {
+            live.replace(config.clone());
+        }
+    }
+    replies
+}
+
+fn graphql(peer: &str, query: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/graphql")
+        .peer_addr(peer.parse().unwrap())
+        .set_json(json!({ "query": query }))
+}
+
+fn mutation(fields: &[&str]) -> String {
+    format!("mutation {{ {} }}", fields.join(" "))
+}
+
+#[actix_web::test]
+async fn test_operation_limit_answers_429_with_retry_after() {
+    let node = MockNode::start().await;
+    let field = token_field();
+    let token = mutation(&[&field]);
+    let aliased = mutation(&[
+        &format!("a: {}", field),
+        &format!("b: {}", field),
+        &format!("c: {}", field),
+    ]);
+
+    let replies = send_all(
+        &node,
+        LiveConfig::new(limited_config(&node)),
+        vec![
+            graphql("1.2.3.4:5000", &token),
+            graphql("1.2.3.4:5000", &token),
+            graphql("1.2.3.4:5000", &token),
+            // Other clients and other operations are unaffected
+            graphql("5.6.7.8:5000", &token),
+            graphql("1.2.3.4:5000", "{ rideRequest { pickupLocation } }"),
+            // Aliases cannot fit more calls into one request than a bucket holds
+            graphql("9.9.9.9:5000", &aliased),
+        ],
+        None,
+    )
+    .await;
+
+    let statuses: Vec<u16> = replies.iter().map(|reply| reply.status).collect();
+    assert_eq!(statuses, [200, 200, 429, 200, 200, 400]);
+    assert_eq!(replies[2].retry_after.as_deref(), Some("10"));
+    let message = replies[2].body["errors"][0]["message"].as_str().unwrap();
+    assert!(message.contains("generateToken"), "{}", message);
+    assert!(replies[0].retry_after.is_none());
+    // Waiting would never let three calls through a burst of two
+    assert!(replies[5].retry_after.is_none());
+    let message = replies[5].body["errors"][0]["message"].as_str().unwrap();
+    assert!(message.contains("generateToken 3 times"), "{}", message);
+}
+
+#[actix_web::test]
+async fn test_multipart_requests_are_limited_like_json() {
+    let node = MockNode::start().await;
+    let operations = json!({ "query": mutation(&[&token_field()]) });
+    let body = format!(
+        "--BOUNDARY\r\n\
+         Content-Disposition: form-data; name=\"operations\"\r\n\r\n\
+         {}\r\n\
+         --BOUNDARY\r\n\
+         Content-Disposition: form-data; name=\"map\"\r\n\r\n\
+         {{}}\r\n\
+         --BOUNDARY--\r\n",
+        operations
+    );
+    let multipart = || {
+        test::TestRequest::post()
+            .uri("/graphql")
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+            .insert_header(("Content-Type", "multipart/form-data; boundary=BOUNDARY"))
+            .set_payload(body.clone())
+    };
+
+    let replies = send_all(
+        &node,
+        LiveConfig::new(limited_config(&node)),
+        vec![multipart(), multipart(), multipart()],
+        None,
+    )
+    .await;
+
+    let statuses: Vec<u16> = replies.iter().map(|reply| reply.status).collect();
+    assert_eq!(statuses, [200, 200, 429]);
+    assert!(replies[0].body["data"]["generateToken"]["token"].is_string());
+}
+
+#[actix_web::test]
+async fn test_clients_behind_trusted_proxies_are_told_apart() {
+    let node = MockNode::start().await;
+    let token = mutation(&[&token_field()]);
+    let forwarded = |client: &str| {
+        graphql("10.0.0.2:5000", &token).insert_header(("X-Forwarded-For", client.to_string()))
+    };
+
+    let replies = send_all(
+        &node,
+        LiveConfig::new(limited_config(&node)),
+        vec![
+            forwarded("1.1.1.1"),
+            forwarded("1.1.1.1"),
+            forwarded("2.2.2.2"),
+            // A forged entry left of the proxy's own does not change the client
+            forwarded("6.6.6.6, 1.1.1.1"),
+            // An untrusted peer's header is ignored
+            graphql("3.3.3.3:5000", &token).insert_header(("X-Forwarded-For", "1.1.1.1")),
+        ],
+        None,
+    )
+    .await;
+
+    let statuses: Vec<u16> = replies.iter().map(|reply| reply.status).collect();
+    assert_eq!(statuses, [200, 200, 200, 429, 200]);
+}
+
+#[actix_web::test]
+async fn test_authenticated_requests_are_limited_per_address() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+    let config = limited_config(&node);
+    let user = USER.parse().unwrap();
+    let (token, _) = generate_jwt_token(&user, 1, config.auth.jwt_secret.expose()).unwrap();
+    let ride = mutation(&[RIDE_FIELD]);
+    let authenticated = |peer: &str| {
+        graphql(peer, &ride).insert_header(("Authorization", format!("Bearer {}", token)))
+    };
+
+    let mut relaxed = config.clone();
+    relaxed
+        .limits
+        .operation_rate_limits
+        .insert("createUnsignedRideRequest".to_string(), RateLimit::new(0, 0));
+    let replies = send_all(
+        &node,
+        LiveConfig::new(config),
+        vec![
+            authenticated("1.1.1.1:5000"),
+            // The same user from another network shares the bucket
+            authenticated("2.2.2.2:5000"),
+            // Anonymous requests from that network have their own
+            graphql("2.2.2.2:5000", &ride),
+            // Reloaded limits apply at once
+            authenticated("2.2.2.2:5000"),
+        ],
+        Some((2, relaxed)),
+    )
+    .await;
+
+    let statuses: Vec<u16> = replies.iter().map(|reply| reply.status).collect();
+    assert_eq!(statuses, [200, 429, 200, 200]);
+    assert!(replies[0].body["data"]["createUnsignedRideRequest"].is_object());
+    let unauthorized = replies[2].body["errors"][0]["message"].as_str().unwrap();
+    assert!(unauthorized.contains("Unauthorized"), "{}", unauthorized);
+}
+
+#[actix_web::test]
+async fn test_oversized_bodies_are_refused() {
+    let node = MockNode::start().await;
+    let mut config = limited_config(&node);
+    config.limits.max_body_bytes = 256;
+    let query = "{ rideRequest { pickupLocation } }";
+    let padded = format!("{}{}", query, " ".repeat(256));
+
+    let replies = send_all(
+        &node,
+        LiveConfig::new(config),
+        vec![
+            graphql("1.2.3.4:5000", &padded),
+            graphql("1.2.3.4:5000", query),
+        ],
+        None,
+    )
+    .await;
+
+    let statuses: Vec<u16> = replies.iter().map(|reply| reply.status).collect();
+    assert_eq!(statuses, [413, 200]);
+}
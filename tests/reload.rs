@@ -0,0 +1,146 @@
+mod support;
+
+use clutch_hub_api::hub::configuration::{AppConfig, LiveConfig};
+use clutch_hub_api::hub::graphql::build_schema;
+use clutch_hub_api::hub::reload::ConfigReloader;
+use clutch_hub_api::hub::shutdown::Shutdown;
+use clutch_hub_api::hub::tracing::LogFilterHandle;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use support::{connect, fast_options, test_keys, token_field, MockNode};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Replaces the `test` environment overlay.
+fn write_overlay(dir: &Path, contents: &str) {
+    std::fs::write(dir.join("test.toml"), contents).unwrap();
+}
+
+fn config_dir(name: &str, overlay: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "clutch-hub-reload-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("default.toml"),
+        "ws_addr = \"127.0.0.1:0\"\n\
+         [auth]\njwt_secret = \"reload-test-secret-with-enough-length\"\n",
+    )
+    .unwrap();
+    write_overlay(&dir, overlay);
+    dir
+}
+
+fn reloader(dir: &Path, live: &LiveConfig, log_filter: Option<LogFilterHandle>) -> ConfigReloader {
+    ConfigReloader::new(dir.to_str().unwrap(), "test", live.clone(), log_filter)
+}
+
+fn load(dir: &Path) -> LiveConfig {
+    LiveConfig::new(AppConfig::load_from(dir.to_str().unwrap(), "test").unwrap())
+}
+
+#[test]
+fn test_reload_applies_runtime_settings() {
+    let dir = config_dir("apply", "log_level = \"info\"");
+    let live = load(&dir);
+    let (_filter_layer, log_filter) = reload::Layer::<_, Registry>::new(EnvFilter::new("info"));
+    let reloader = reloader(&dir, &live, Some(log_filter.clone()));
+
+    write_overlay(
+        &dir,
+        "log_level = \"debug\"\n[cors]\nallowed_origins = [\"https://app.example\"]",
+    );
+    reloader.reload().unwrap();
+
+    let current = live.current();
+    assert_eq!(current.log_level, "debug");
+    assert_eq!(log_filter.with_current(|filter| filter.to_string()).unwrap(), "debug");
+    assert!(current.cors.allows("https://app.example"));
+    assert!(!current.cors.allows("https://evil.example"));
+}
+
+#[test]
+fn test_reload_rejects_invalid_and_startup_only_changes() {
+    let dir = config_dir("reject", "");
+    let live = load(&dir);
+    let reloader = reloader(&dir, &live, None);
+
+    write_overlay(
+        &dir,
+        "log_level = \"debug\"\n\
+         [metrics]\naddr = \"0.0.0.0:9999\"\n\
+         [node]\nws_url = \"ws://other:8081\"",
+    );
+    assert_eq!(
+        reloader.reload().unwrap_err(),
+        vec![
+            "metrics.addr cannot change without a restart",
+            "node.ws_url cannot change without a restart",
+        ]
+    );
+
+    let ca_file = dir.join("ca.pem");
+    std::fs::write(&ca_file, "").unwrap();
+    write_overlay(
+        &dir,
+        &format!("[node]\nws_url = \"wss://127.0.0.1:8081\"\nca_file = {:?}", ca_file),
+    );
+    assert_eq!(
+        reloader.reload().unwrap_err(),
+        vec![
+            "node.ws_url cannot change without a restart",
+            "node.ca_file cannot change without a restart",
+        ]
+    );
+
+    write_overlay(&dir, "log_level = \"debug\"\n[auth]\njwt_expiration_hours = 0");
+    assert!(reloader.reload().is_err());
+
+    // Neither attempt changed anything, not even the reloadable log level
+    assert_eq!(live.current().log_level, "info");
+    assert_eq!(live.current().auth.jwt_expiration_hours, 6);
+    assert_eq!(live.reload_errors().len(), 1);
+
+    // The readiness probe reports the last attempt only
+    write_overlay(&dir, "log_level = \"debug\"");
+    reloader.reload().unwrap();
+    assert!(live.reload_errors().is_empty());
+}
+
+#[tokio::test]
+async fn test_file_changes_are_picked_up_by_the_watcher() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+    let dir = config_dir("watch", "");
+    let live = load(&dir);
+    let schema = build_schema(client, live.clone());
+    let shutdown = Shutdown::new();
+    let watcher = reloader(&dir, &live, None).watch(Duration::from_millis(20), shutdown.clone());
+
+    let token_lifetime = || async {
+        let field = token_field(&test_keys(0), &live.current());
+        let response = schema
+            .execute(format!("mutation {{ {} {{ expiresAt }} }}", field))
+            .await;
+        let expires_at = response.data.into_json().unwrap()["generateToken"]["expiresAt"]
+            .as_u64()
+            .unwrap();
+        expires_at - chrono::Utc::now().timestamp() as u64
+    };
+    assert!(token_lifetime().await > 5 * 3600);
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    write_overlay(&dir, "[auth]\njwt_expiration_hours = 1");
+    for _ in 0..100 {
+        if live.current().auth.jwt_expiration_hours == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(token_lifetime().await <= 3600);
+
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(2), watcher).await.unwrap().unwrap();
+}
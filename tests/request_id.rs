@@ -0,0 +1,148 @@
+mod support;
+
+use actix_web::{test, web, App};
+use clutch_hub_api::hub::auth::generate_jwt_token;
+use clutch_hub_api::hub::clutch_node_client::ClientOptions;
+use clutch_hub_api::hub::configuration::LiveConfig;
+use clutch_hub_api::hub::graphql::build_schema;
+use clutch_hub_api::hub::graphql::handler::graphql_handler;
+use clutch_hub_api::hub::rate_limit::RateLimiter;
+use serde_json::json;
+use std::io;
+use std::sync::{Arc, Mutex};
+use support::{connect, fast_options, test_config, MockNode};
+
+const USER: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+
+const NONCE_QUERY: &str = "mutation Ride { createUnsignedRideRequest(pickupLatitude: 1, \
+                           pickupLongitude: 2, dropoffLatitude: 3, dropoffLongitude: 4, fare: 100) }";
+
+async fn post_graphql(
+    node: &MockNode,
+    request_id: Option<&str>,
+    propagate: bool,
+) -> actix_web::dev::ServiceResponse {
+    let config = test_config(&node.url());
+    let options = ClientOptions {
+        propagate_trace_context: propagate,
+        ..fast_options()
+    };
+    let client = connect(node, options).await;
+    let schema = build_schema(client, config.clone());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(schema))
+            .service(web::resource("/graphql").route(web::post().to(graphql_handler))),
+    )
+    .await;
+
+    let user = USER.parse().unwrap();
+    let (token, _) = generate_jwt_token(&user, 1, config.auth.jwt_secret.expose()).unwrap();
+
+    let mut request = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({ "query": NONCE_QUERY }));
+    if let Some(id) = request_id {
+        request = request.insert_header(("X-Request-Id", id));
+    }
+    test::call_service(&app, request.to_request()).await
+}
+
+#[actix_web::test]
+async fn test_incoming_request_id_is_echoed_and_sent_to_node() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+
+    let response = post_graphql(&node, Some("ride-1234"), true).await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers().get("x-request-id").unwrap(), "ride-1234");
+
+    let frames = node.frames().await;
+    assert_eq!(frames[0]["method"], "get_next_nonce");
+    assert_eq!(frames[0]["request_id"], "ride-1234");
+}
+
+#[actix_web::test]
+async fn test_missing_or_unusable_request_id_is_generated() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+
+    let response = post_graphql(&node, None, true).await;
+    let generated = response.headers().get("x-request-id").unwrap().to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(generated).is_ok(), "{}", generated);
+    assert_eq!(node.frames().await[0]["request_id"], generated);
+
+    let response = post_graphql(&node, Some("has spaces"), true).await;
+    let replaced = response.headers().get("x-request-id").unwrap().to_str().unwrap();
+    assert_ne!(replaced, "has spaces");
+    assert!(uuid::Uuid::parse_str(replaced).is_ok(), "{}", replaced);
+}
+
+#[actix_web::test]
+async fn test_request_id_is_not_sent_to_node_by_default() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+
+    let response = post_graphql(&node, Some("ride-1234"), false).await;
+    assert_eq!(response.headers().get("x-request-id").unwrap(), "ride-1234");
+    assert!(node.frames().await[0].get("request_id").is_none());
+}
+
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[actix_web::test]
+async fn test_rejected_token_is_logged_with_the_request_id() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let node = MockNode::start().await;
+    let config = test_config(&node.url());
+    let client = connect(&node, fast_options()).await;
+    let live = LiveConfig::new(config.clone());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(build_schema(client, live.clone())))
+            .service(
+                web::resource("/graphql")
+                    .wrap(RateLimiter::new(live))
+                    .route(web::post().to(graphql_handler)),
+            ),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Authorization", "Bearer not-a-jwt"))
+        .insert_header(("X-Request-Id", "ride-5678"))
+        .set_json(json!({ "query": "{ rideRequest { pickupLocation } }" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert!(response.status().is_success());
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let failure = logs
+        .lines()
+        .find(|line| line.contains("JWT validation failed"))
+        .unwrap_or_else(|| panic!("no JWT failure in {}", logs));
+    assert!(failure.contains("request_id=ride-5678"), "{}", failure);
+}
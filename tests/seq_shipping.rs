@@ -0,0 +1,129 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use clutch_hub_api::hub::seq::{SeqHandle, SeqOptions};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+struct FakeSeq {
+    payloads: Arc<Mutex<Vec<String>>>,
+    failures_left: Arc<Mutex<u32>>,
+}
+
+async fn ingest(State(seq): State<FakeSeq>, body: String) -> StatusCode {
+    let mut failures_left = seq.failures_left.lock().unwrap();
+    if *failures_left > 0 {
+        *failures_left -= 1;
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    seq.payloads.lock().unwrap().push(body);
+    StatusCode::CREATED
+}
+
+async fn start_fake_seq(failures: u32) -> (String, FakeSeq) {
+    let seq = FakeSeq::default();
+    *seq.failures_left.lock().unwrap() = failures;
+    let app = Router::new()
+        .route("/ingest/clef", post(ingest))
+        .with_state(seq.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, seq)
+}
+
+fn options(seq_url: String, batch_size: usize, max_retries: u32) -> SeqOptions {
+    SeqOptions {
+        seq_url,
+        api_key: "test".into(),
+        buffer_capacity: 100,
+        batch_size,
+        flush_interval: Duration::from_secs(60),
+        max_retries,
+        connect_timeout: Duration::from_secs(5),
+        request_timeout: Duration::from_secs(5),
+    }
+}
+
+#[tokio::test]
+async fn test_events_are_posted_as_one_multiline_batch() {
+    let (url, seq) = start_fake_seq(0).await;
+    let handle = SeqHandle::spawn(options(url, 10, 0)).unwrap();
+
+    for i in 0..5 {
+        handle.enqueue(json!({ "@mt": "event {i}", "i": i }));
+    }
+    assert!(handle.flush(Duration::from_secs(5)).await);
+
+    let payloads = seq.payloads.lock().unwrap().clone();
+    assert_eq!(payloads.len(), 1);
+    let lines: Vec<&str> = payloads[0].lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(lines[4]).unwrap()["i"], 4);
+}
+
+#[tokio::test]
+async fn test_full_batches_are_posted_without_waiting() {
+    let (url, seq) = start_fake_seq(0).await;
+    let handle = SeqHandle::spawn(options(url, 2, 0)).unwrap();
+
+    for i in 0..4 {
+        handle.enqueue(json!({ "i": i }));
+    }
+    for _ in 0..100 {
+        if seq.payloads.lock().unwrap().len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(seq.payloads.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_failed_batches_are_retried() {
+    let (url, seq) = start_fake_seq(2).await;
+    let handle = SeqHandle::spawn(options(url, 10, 3)).unwrap();
+
+    handle.enqueue(json!({ "@mt": "retry me" }));
+    assert!(handle.flush(Duration::from_secs(5)).await);
+
+    let payloads = seq.payloads.lock().unwrap().clone();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads[0].contains("retry me"));
+}
+
+#[tokio::test]
+async fn test_unresponsive_seq_does_not_stall_shipping() {
+    // Accepts connections but never answers them
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let accepted = Arc::new(Mutex::new(Vec::new()));
+    let held = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            held.lock().unwrap().push(stream);
+        }
+    });
+
+    let mut options = options(url, 10, 1);
+    options.request_timeout = Duration::from_millis(200);
+    let handle = SeqHandle::spawn(options).unwrap();
+
+    handle.enqueue(json!({ "@mt": "lost" }));
+    // Two timed-out attempts and the backoff between them fit the deadline
+    assert!(handle.flush(Duration::from_secs(3)).await);
+    assert_eq!(accepted.lock().unwrap().len(), 2);
+
+    // The shipper is free for the next batch
+    handle.enqueue(json!({ "@mt": "also lost" }));
+    assert!(handle.flush(Duration::from_secs(3)).await);
+}
+
+#[test]
+fn test_enqueue_works_outside_a_runtime() {
+    let handle = SeqHandle::spawn(options("http://127.0.0.1:9".to_string(), 10, 0)).unwrap();
+    handle.enqueue(json!({ "@mt": "no runtime here" }));
+}
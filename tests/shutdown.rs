@@ -0,0 +1,167 @@
+mod support;
+
+use clutch_hub_api::hub::auth::generate_jwt_token;
+use clutch_hub_api::hub::metric::serve_metrics;
+use clutch_hub_api::hub::server::run_graphql_server;
+use clutch_hub_api::hub::shutdown::Shutdown;
+use serde_json::{json, Value};
+use std::time::Duration;
+use support::{connect, fast_options, test_config, MockNode};
+
+const USER: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+
+fn free_addr() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_client_shutdown_waits_for_pending_requests() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 8 })).await;
+    node.delay("get_next_nonce", Duration::from_millis(200)).await;
+    let client = connect(&node, fast_options()).await;
+
+    let pending = {
+        let client = client.clone();
+        tokio::spawn(async move { client.send_request("get_next_nonce", json!({})).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    client.shutdown(Duration::from_secs(2)).await;
+
+    assert_eq!(pending.await.unwrap().unwrap()["nonce"], 8);
+    assert!(!client.is_connected().await);
+
+    // The client stays closed instead of reconnecting
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(node.connection_count(), 1);
+    assert!(client.send_request("get_next_nonce", json!({})).await.is_err());
+}
+
+#[tokio::test]
+async fn test_client_shutdown_fails_requests_left_after_grace_period() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 8 })).await;
+    node.delay("get_next_nonce", Duration::from_secs(2)).await;
+    let client = connect(&node, fast_options()).await;
+
+    let pending = {
+        let client = client.clone();
+        tokio::spawn(async move { client.send_request("get_next_nonce", json!({})).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    client.shutdown(Duration::from_millis(50)).await;
+
+    assert_eq!(pending.await.unwrap().unwrap_err(), "Client is shutting down");
+}
+
+#[tokio::test]
+async fn test_client_shutdown_stays_within_grace_period() {
+    let node = MockNode::start().await;
+    let client = connect(&node, fast_options()).await;
+
+    // A node that stops reading never answers. Once the socket buffers are
+    // full, writing this request blocks, and with it the close frame.
+    node.stall_connections();
+    let pending = {
+        let client = client.clone();
+        let params = json!({ "padding": "x".repeat(32 * 1024 * 1024) });
+        tokio::spawn(async move { client.send_request("get_next_nonce", params).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let started = std::time::Instant::now();
+    client.shutdown(Duration::from_millis(200)).await;
+    let elapsed = started.elapsed();
+    assert!(elapsed < Duration::from_millis(300), "shutdown took {:?}", elapsed);
+    pending.abort();
+}
+
+#[tokio::test]
+async fn test_server_drains_in_flight_request_and_stops_accepting() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 3 })).await;
+    node.delay("get_next_nonce", Duration::from_millis(300)).await;
+    let client = connect(&node, fast_options()).await;
+
+    let addr = free_addr();
+    let config = test_config(&node.url());
+    let user = USER.parse().unwrap();
+    let (token, _) = generate_jwt_token(&user, 1, config.auth.jwt_secret.expose()).unwrap();
+    let shutdown = Shutdown::new();
+    let server = tokio::spawn({
+        let addr = addr.clone();
+        let shutdown = shutdown.clone();
+        async move { run_graphql_server(&addr, client, config, None, shutdown).await }
+    });
+    for _ in 0..100 {
+        if reqwest::get(format!("http://{}/livez", addr)).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let in_flight = tokio::spawn({
+        let url = format!("http://{}/graphql", addr);
+        async move {
+            // A client of its own, so its keep-alive connection closes with the task
+            reqwest::Client::new()
+                .post(url)
+                .bearer_auth(token)
+                .json(&json!({
+                    "query": "mutation { createUnsignedRideRequest(pickupLatitude: 1, \
+                              pickupLongitude: 2, dropoffLatitude: 3, dropoffLongitude: 4, fare: 5) }"
+                }))
+                .send()
+                .await
+        }
+    });
+    // Shut down while the resolver is waiting on the node
+    for _ in 0..100 {
+        if !node.frames().await.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    shutdown.trigger();
+
+    let response = in_flight.await.unwrap().unwrap();
+    assert!(response.status().is_success());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["createUnsignedRideRequest"]["nonce"], 3);
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(reqwest::get(format!("http://{}/livez", addr)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_metrics_server_stops_on_shutdown() {
+    let addr = free_addr();
+    let shutdown = Shutdown::new();
+    let metrics = serve_metrics(&addr, shutdown.clone()).await.unwrap();
+    assert!(reqwest::get(format!("http://{}/metrics", addr)).await.is_ok());
+
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), metrics)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_stages_share_one_grace_period() {
+    let shutdown = Shutdown::new();
+    let grace = Duration::from_secs(1);
+    assert_eq!(shutdown.remaining(grace), grace);
+
+    shutdown.trigger();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // Triggering again does not restart the clock
+    shutdown.trigger();
+    assert!(shutdown.remaining(grace) <= Duration::from_millis(900));
+    assert_eq!(shutdown.remaining(Duration::from_millis(50)), Duration::ZERO);
+}
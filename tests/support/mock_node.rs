@@ -0,0 +1,264 @@
+//! Scriptable in-process stand-in for clutch-node's WebSocket JSON-RPC endpoint.
+
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{accept_async, WebSocketStream};
+
+/// What the mock answers for a single JSON-RPC request.
+#[derive(Clone, Debug)]
+pub enum Reply {
+    Result(Value),
+    Error(i32, String),
+    /// Swallow the request so the caller times out.
+    Silence,
+}
+
+type Handler = Arc<dyn Fn(&Value) -> Reply + Send + Sync>;
+
+#[derive(Clone)]
+struct Script {
+    handler: Handler,
+    delay: Duration,
+}
+
+#[derive(Clone)]
+enum Control {
+    Drop,
+    Stall,
+    Push(String),
+}
+
+#[derive(Default)]
+struct State {
+    scripts: HashMap<String, Script>,
+    frames: Vec<Value>,
+}
+
+type NodeSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+
+pub struct MockNode {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    control: broadcast::Sender<Control>,
+    connections: Arc<AtomicUsize>,
+    accept_task: JoinHandle<()>,
+}
+
+impl MockNode {
+    /// Binds to an ephemeral port and starts accepting connections.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let (control, _) = broadcast::channel(16);
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accept_task = {
+            let state = state.clone();
+            let control = control.clone();
+            let connections = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(serve_connection(stream, state.clone(), control.subscribe()));
+                }
+            })
+        };
+
+        MockNode {
+            addr,
+            state,
+            control,
+            connections,
+            accept_task,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Answers `method` with a fixed result.
+    pub async fn respond(&self, method: &str, result: Value) {
+        self.respond_with(method, move |_| Reply::Result(result.clone()))
+            .await;
+    }
+
+    /// Answers `method` with a JSON-RPC error.
+    pub async fn respond_error(&self, method: &str, code: i32, message: &str) {
+        let message = message.to_string();
+        self.respond_with(method, move |_| Reply::Error(code, message.clone()))
+            .await;
+    }
+
+    /// Answers `method` by calling `handler` with the request params.
+    pub async fn respond_with<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(&Value) -> Reply + Send + Sync + 'static,
+    {
+        let mut state = self.state.lock().await;
+        let delay = state
+            .scripts
+            .get(method)
+            .map(|script| script.delay)
+            .unwrap_or_default();
+        state.scripts.insert(
+            method.to_string(),
+            Script {
+                handler: Arc::new(handler),
+                delay,
+            },
+        );
+    }
+
+    /// Delays every reply to `method`.
+    pub async fn delay(&self, method: &str, delay: Duration) {
+        let mut state = self.state.lock().await;
+        if let Some(script) = state.scripts.get_mut(method) {
+            script.delay = delay;
+        }
+    }
+
+    /// Abruptly closes every open connection.
+    pub fn drop_connections(&self) {
+        let _ = self.control.send(Control::Drop);
+    }
+
+    /// Stops reading from every open connection while keeping it open, so
+    /// requests and pings go unanswered. New connections are served normally.
+    pub fn stall_connections(&self) {
+        let _ = self.control.send(Control::Stall);
+    }
+
+    /// Pushes an unsolicited frame to every open connection.
+    pub fn notify(&self, notification: Value) {
+        let _ = self.control.send(Control::Push(notification.to_string()));
+    }
+
+    /// Every text frame received so far, in arrival order.
+    pub async fn frames(&self) -> Vec<Value> {
+        self.state.lock().await.frames.clone()
+    }
+
+    /// Number of connections accepted since start.
+    pub fn connection_count(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockNode {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        let _ = self.control.send(Control::Drop);
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    state: Arc<Mutex<State>>,
+    mut control: broadcast::Receiver<Control>,
+) {
+    let Ok(ws_stream) = accept_async(stream).await else {
+        return;
+    };
+    let (sink, mut source) = ws_stream.split();
+    let sink = Arc::new(Mutex::new(Some(sink)));
+    // tungstenite queues pong replies while reading, so not reading swallows them
+    let mut stalled = false;
+
+    loop {
+        tokio::select! {
+            msg = source.next(), if !stalled => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    tokio::spawn(handle_frame(text, state.clone(), sink.clone()));
+                }
+                Some(Ok(_)) => {}
+                _ => break,
+            },
+            ctrl = control.recv() => match ctrl {
+                Ok(Control::Push(text)) => send(&sink, text).await,
+                Ok(Control::Stall) => stalled = true,
+                Ok(Control::Drop) | Err(_) => break,
+            },
+        }
+    }
+
+    // Dropping both halves closes the socket without a close handshake,
+    // even while delayed replies are still pending
+    sink.lock().await.take();
+}
+
+async fn send(sink: &Arc<Mutex<Option<NodeSink>>>, text: String) {
+    if let Some(sink) = sink.lock().await.as_mut() {
+        let _ = sink.send(Message::Text(text)).await;
+    }
+}
+
+async fn handle_frame(text: String, state: Arc<Mutex<State>>, sink: Arc<Mutex<Option<NodeSink>>>) {
+    let Ok(frame) = serde_json::from_str::<Value>(&text) else {
+        return;
+    };
+    state.lock().await.frames.push(frame.clone());
+
+    let reply = match frame {
+        Value::Array(requests) => {
+            let mut responses = Vec::new();
+            for request in &requests {
+                if let Some(response) = answer(request, &state).await {
+                    responses.push(response);
+                }
+            }
+            if responses.is_empty() {
+                return;
+            }
+            Value::Array(responses)
+        }
+        request => match answer(&request, &state).await {
+            Some(response) => response,
+            None => return,
+        },
+    };
+
+    send(&sink, reply.to_string()).await;
+}
+
+async fn answer(request: &Value, state: &Arc<Mutex<State>>) -> Option<Value> {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    let script = state.lock().await.scripts.get(method).cloned();
+    let reply = match script {
+        Some(script) => {
+            tokio::time::sleep(script.delay).await;
+            (script.handler)(&params)
+        }
+        None => Reply::Error(-32601, format!("Method not found: {}", method)),
+    };
+
+    match reply {
+        Reply::Result(result) => Some(json!({
+            "jsonrpc": "2.0",
+            "result": result,
+            "error": null,
+            "id": id,
+        })),
+        Reply::Error(code, message) => Some(json!({
+            "jsonrpc": "2.0",
+            "result": null,
+            "error": { "code": code, "message": message, "data": null },
+            "id": id,
+        })),
+        Reply::Silence => None,
+    }
+}
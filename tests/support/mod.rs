@@ -0,0 +1,94 @@
+#![allow(dead_code)]
+
+pub mod mock_node;
+
+use clutch_hub_api::hub::auth::sign_in_challenge;
+use clutch_hub_api::hub::clutch_node_client::{ClientOptions, ClutchNodeClient};
+use clutch_hub_api::hub::configuration::{AppConfig, AuthConfig, MetricsConfig, NodeConfig};
+use clutch_hub_api::hub::hd_keys::{derive_keys, DerivationPath};
+use clutch_hub_api::hub::signature_keys::SignatureKeys;
+use clutch_hub_api::hub::typed_data::TypedData;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[allow(unused_imports)]
+pub use mock_node::{MockNode, Reply};
+
+/// Client options with short timings so failure paths finish quickly.
+pub fn fast_options() -> ClientOptions {
+    ClientOptions {
+        request_timeout: Duration::from_millis(500),
+        reconnect_delay: Duration::from_millis(50),
+        ..ClientOptions::default()
+    }
+}
+
+/// Connects a client to `node` and waits until the link is up.
+pub async fn connect(node: &MockNode, options: ClientOptions) -> Arc<ClutchNodeClient> {
+    let client = ClutchNodeClient::with_options(node.url(), options).unwrap();
+    wait_until_connected(&client).await;
+    client
+}
+
+pub async fn wait_until_connected(client: &ClutchNodeClient) {
+    for _ in 0..200 {
+        if client.is_connected().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("client did not connect to the mock node");
+}
+
+/// Mnemonic the fixture accounts are derived from (Hardhat's default, so the
+/// same accounts show up in local wallets and dev chains).
+pub const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+/// The `index`-th fixture account, the same on every run.
+pub fn test_keys(index: u32) -> SignatureKeys {
+    derive_keys(TEST_MNEMONIC, "", &DerivationPath::ethereum(index)).unwrap()
+}
+
+/// Signs `typed_data` with `keys` the way `eth_signTypedData_v4` does,
+/// returning the 65-byte hex signature.
+pub fn wallet_signature(keys: &SignatureKeys, typed_data: &TypedData) -> String {
+    let (r, s, v) = SignatureKeys::sign_typed_data(&keys.secret_key, typed_data).unwrap();
+    format!("0x{}{}{:02x}", r, s, v)
+}
+
+/// A `generateToken` field, without a selection set, answering a fresh
+/// sign-in challenge for `keys`.
+pub fn token_field(keys: &SignatureKeys, config: &AppConfig) -> String {
+    let address = keys.address_key.parse().unwrap();
+    let challenge = sign_in_challenge(&address, config.auth.jwt_secret.expose());
+    let signature = wallet_signature(keys, &challenge);
+    let challenge = serde_json::to_value(&challenge).unwrap();
+    format!(
+        r#"generateToken(challenge: {}, signature: "{}")"#,
+        async_graphql::Value::from_json(challenge).unwrap(),
+        signature
+    )
+}
+
+pub fn test_config(node_url: &str) -> AppConfig {
+    AppConfig {
+        ws_addr: "127.0.0.1:0".to_string(),
+        shutdown_grace_period_secs: 5,
+        metrics: MetricsConfig {
+            addr: "127.0.0.1:0".to_string(),
+            block_poll_interval_secs: 1,
+            ..MetricsConfig::default()
+        },
+        node: NodeConfig {
+            ws_url: node_url.to_string(),
+            reconnect_delay_secs: 1,
+            ..NodeConfig::default()
+        },
+        auth: AuthConfig {
+            jwt_secret: "integration-test-secret-with-enough-length".into(),
+            jwt_expiration_hours: 1,
+            ..AuthConfig::default()
+        },
+        ..AppConfig::default()
+    }
+}
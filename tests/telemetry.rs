@@ -0,0 +1,171 @@
+mod support;
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use async_graphql::Request;
+use clutch_hub_api::hub::clutch_node_client::ClientOptions;
+use clutch_hub_api::hub::graphql::build_schema;
+use clutch_hub_api::hub::graphql::types::AuthUser;
+use clutch_hub_api::hub::telemetry::{build_otlp_provider, extract_context, tracer};
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use prost::Message;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tracing::{info_span, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use support::{connect, fast_options, test_config, MockNode};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+#[derive(Clone, Default)]
+struct FakeCollector {
+    exports: Arc<Mutex<Vec<ExportTraceServiceRequest>>>,
+}
+
+async fn export(State(collector): State<FakeCollector>, body: Bytes) -> StatusCode {
+    match ExportTraceServiceRequest::decode(body) {
+        Ok(request) => {
+            collector.exports.lock().unwrap().push(request);
+            StatusCode::OK
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+async fn start_fake_collector() -> (String, FakeCollector) {
+    let collector = FakeCollector::default();
+    let app = Router::new()
+        .route("/v1/traces", post(export))
+        .with_state(collector.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, collector)
+}
+
+fn incoming_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("traceparent"),
+        HeaderValue::from_str(&format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID)).unwrap(),
+    );
+    headers
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_node_call_is_exported_and_propagated() {
+    let (collector_url, collector) = start_fake_collector().await;
+    let provider = build_otlp_provider(&collector_url, "http").unwrap();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer(&provider)));
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+    let options = ClientOptions {
+        propagate_trace_context: true,
+        ..fast_options()
+    };
+    let client = connect(&node, options).await;
+
+    let request_span = info_span!("graphql.request");
+    request_span.set_parent(extract_context(&incoming_headers()));
+    client
+        .send_request("get_next_nonce", json!({ "address": "0xabc" }))
+        .instrument(request_span)
+        .await
+        .unwrap();
+
+    // The node sees the trace of the incoming HTTP request
+    let frames = node.frames().await;
+    let traceparent = frames[0]["traceparent"].as_str().unwrap();
+    assert!(traceparent.starts_with(&format!("00-{}-", TRACE_ID)), "{}", traceparent);
+    assert!(!traceparent.contains(PARENT_SPAN_ID));
+
+    let flushed = tokio::task::spawn_blocking(move || provider.force_flush())
+        .await
+        .unwrap();
+    assert!(flushed.iter().all(Result::is_ok), "{:?}", flushed);
+
+    let exports = collector.exports.lock().unwrap();
+    let spans: Vec<_> = exports
+        .iter()
+        .flat_map(|export| &export.resource_spans)
+        .flat_map(|resource| &resource.scope_spans)
+        .flat_map(|scope| &scope.spans)
+        .collect();
+    let rpc = spans.iter().find(|span| span.name == "node.rpc").unwrap();
+    let request = spans.iter().find(|span| span.name == "graphql.request").unwrap();
+    assert_eq!(hex::encode(&rpc.trace_id), TRACE_ID);
+    assert_eq!(rpc.parent_span_id, request.span_id);
+    assert_eq!(hex::encode(&request.parent_span_id), PARENT_SPAN_ID);
+    assert!(traceparent.contains(&hex::encode(&rpc.span_id)));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_exported_spans_are_redacted() {
+    let (collector_url, collector) = start_fake_collector().await;
+    let provider = build_otlp_provider(&collector_url, "http").unwrap();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer(&provider)));
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+    node.respond("send_raw_transaction", json!("0xhash")).await;
+    let client = connect(&node, fast_options()).await;
+    let schema = build_schema(client, test_config(&node.url()));
+
+    let query = "mutation { createUnsignedRideRequest(pickupLatitude: 52.520008, \
+                 pickupLongitude: 13.404954, dropoffLatitude: 1, dropoffLongitude: 2, fare: 100) \
+                 sendRawTransaction(rawTransaction: \"0xfeedface1234\") }";
+    let request = Request::new(query).data(AuthUser {
+        address: "0xdeb4cfb63db134698e1879ea24904df074726cc0".parse().unwrap(),
+    });
+    let response = schema.execute(request).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    tracing::info_span!("ride", pickup_latitude = 52.520008, raw_transaction = "0xfeedface1234")
+        .in_scope(|| {});
+
+    let flushed = tokio::task::spawn_blocking(move || provider.force_flush())
+        .await
+        .unwrap();
+    assert!(flushed.iter().all(Result::is_ok), "{:?}", flushed);
+
+    let exported = format!("{:?}", collector.exports.lock().unwrap());
+    // The query text is exported with its sensitive arguments elided
+    assert!(exported.contains("<secret>"), "{}", exported);
+    assert!(exported.contains("52.52"), "{}", exported);
+    for raw in ["52.520008", "13.404954", "feedface"] {
+        assert!(!exported.contains(raw), "{} was exported: {}", raw, exported);
+    }
+}
+
+#[tokio::test]
+async fn test_trace_context_is_not_sent_by_default() {
+    let node = MockNode::start().await;
+    node.respond("get_next_nonce", json!({ "nonce": 1 })).await;
+    let client = connect(&node, fast_options()).await;
+
+    let request_span = info_span!("graphql.request");
+    request_span.set_parent(extract_context(&incoming_headers()));
+    client
+        .send_request("get_next_nonce", json!({}))
+        .instrument(request_span)
+        .await
+        .unwrap();
+
+    assert!(node.frames().await[0].get("traceparent").is_none());
+}
+
+#[test]
+fn test_unknown_protocol_is_rejected() {
+    let err = build_otlp_provider("http://127.0.0.1:4318", "udp").unwrap_err();
+    assert_eq!(err, "Unsupported OTLP protocol: udp");
+}